@@ -1,18 +1,103 @@
+use crate::config::ConfigError;
+use crate::memo::MemoTemplate;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use tracing::{debug, info};
 
-pub fn read_accounts_json(
-    accounts_config_path: &str,
-) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+/// A YNAB account that transactions from a SpareBank1 account are imported into
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AccountTarget {
+    /// Budget the account belongs to, the profile's budget when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_id: Option<String>,
+    pub account_id: String,
+    /// Memo template overriding the profile's for this account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo_template: Option<MemoTemplate>,
+    /// Keep only the balance in sync with a single adjustment transaction, importing nothing
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub balance_only: bool,
+}
+
+impl AccountTarget {
+    /// Target an account in the profile's default budget
+    pub fn new(account_id: &str) -> Self {
+        Self {
+            budget_id: None,
+            account_id: account_id.to_string(),
+            memo_template: None,
+            balance_only: false,
+        }
+    }
+
+    /// Target an account in a specific budget
+    pub fn in_budget(budget_id: &str, account_id: &str) -> Self {
+        Self {
+            budget_id: Some(budget_id.to_string()),
+            account_id: account_id.to_string(),
+            memo_template: None,
+            balance_only: false,
+        }
+    }
+
+    /// Budget to post to, falling back to the given default budget
+    pub fn budget_id<'a>(&'a self, default_budget: &'a str) -> &'a str {
+        self.budget_id.as_deref().unwrap_or(default_budget)
+    }
+}
+
+/// Account mapping from SpareBank1 account key to the YNAB accounts it is synced into
+pub type AccountConfig = HashMap<String, Vec<AccountTarget>>;
+
+/// Account mapping without balance-only targets, accounts left without targets are dropped
+pub fn import_targets(account_config: &AccountConfig) -> AccountConfig {
+    account_config
+        .iter()
+        .filter_map(|(key, targets)| {
+            let targets: Vec<AccountTarget> = targets
+                .iter()
+                .filter(|target| !target.balance_only)
+                .cloned()
+                .collect();
+            (!targets.is_empty()).then(|| (key.clone(), targets))
+        })
+        .collect()
+}
+
+/// Accepted shapes for a single entry in the account configuration file
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AccountMapping {
+    /// Plain YNAB account id in the default budget (format written by setup)
+    AccountId(String),
+    Target(AccountTarget),
+    Targets(Vec<AccountTarget>),
+}
+
+impl From<AccountMapping> for Vec<AccountTarget> {
+    fn from(mapping: AccountMapping) -> Self {
+        match mapping {
+            AccountMapping::AccountId(account_id) => vec![AccountTarget::new(&account_id)],
+            AccountMapping::Target(target) => vec![target],
+            AccountMapping::Targets(targets) => targets,
+        }
+    }
+}
+
+pub fn read_accounts_json(accounts_config_path: &str) -> Result<AccountConfig, ConfigError> {
     debug!(
         "Reading account configuration from: {}",
         accounts_config_path
     );
     let file = File::open(accounts_config_path)?;
     let reader = BufReader::new(file);
-    let accounts: HashMap<String, String> = serde_json::from_reader(reader)?;
+    let mappings: HashMap<String, AccountMapping> = serde_json::from_reader(reader)?;
+    let accounts: AccountConfig = mappings
+        .into_iter()
+        .map(|(key, mapping)| (key, mapping.into()))
+        .collect();
 
     info!(
         "Loaded {} account mappings from configuration",
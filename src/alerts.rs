@@ -0,0 +1,302 @@
+use crate::learn::payee_key;
+use crate::rules::FlagColor;
+use crate::ynab::{self, ImportTransaction, TransactionDetail};
+use chrono::NaiveDate;
+use chrono_tz::Europe::Oslo;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use tracing::{debug, info};
+
+/// Fewest past transactions a payee needs before its average amount is used
+const MIN_HISTORY: usize = 3;
+
+/// AlertError represents all possible errors when loading an alerts file
+#[derive(Debug, thiserror::Error)]
+pub enum AlertError {
+    #[error("Failed to read alerts file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Failed to parse alerts file: {0}")]
+    ParseError(#[from] serde_json::Error),
+
+    #[error("Invalid alert settings: {0}")]
+    ValidationError(String),
+}
+
+/// Which transactions are alerted on, as written in the alerts configuration file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertConfig {
+    /// Amount in NOK, in or out, above which a transaction is alerted on
+    pub large_amount: Option<f64>,
+    /// Large amount thresholds per SpareBank1 account key, overriding `large_amount`
+    #[serde(default)]
+    pub account_large_amount: HashMap<String, f64>,
+    /// Alert on payees not seen in the YNAB history
+    #[serde(default)]
+    pub new_payee: bool,
+    /// Alert on charges of the same amount by the same merchant within this many minutes
+    pub duplicate_minutes: Option<i64>,
+    /// Alert on amounts this many times a payee's average in the YNAB history
+    pub deviation_factor: Option<f64>,
+    /// Flag alerted transactions in YNAB, unless a rule flagged them
+    pub flag: Option<FlagColor>,
+}
+
+impl AlertConfig {
+    pub fn validate(&self) -> Result<(), AlertError> {
+        let mut thresholds = self
+            .large_amount
+            .iter()
+            .chain(self.account_large_amount.values());
+        if thresholds.any(|amount| amount.is_nan() || *amount <= 0.0) {
+            return Err(AlertError::ValidationError(
+                "large amounts must be above 0".to_string(),
+            ));
+        }
+        if self.duplicate_minutes.is_some_and(|minutes| minutes < 0) {
+            return Err(AlertError::ValidationError(
+                "duplicate_minutes must not be negative".to_string(),
+            ));
+        }
+        if self
+            .deviation_factor
+            .is_some_and(|factor| factor.is_nan() || factor <= 1.0)
+        {
+            return Err(AlertError::ValidationError(
+                "deviation_factor must be above 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether any alert needs the YNAB history
+    pub fn needs_history(&self) -> bool {
+        self.new_payee || self.deviation_factor.is_some()
+    }
+
+    /// Large amount threshold of a SpareBank1 account
+    fn large_amount(&self, account: &str) -> Option<f64> {
+        self.account_large_amount
+            .get(account)
+            .copied()
+            .or(self.large_amount)
+    }
+}
+
+/// Reads alert settings from a JSON file
+pub fn read_alerts_json(alerts_config_path: &str) -> Result<AlertConfig, AlertError> {
+    debug!("Reading alerts from: {}", alerts_config_path);
+    let file = File::open(alerts_config_path)?;
+    let config: AlertConfig = serde_json::from_reader(BufReader::new(file))?;
+    config.validate()?;
+    info!("Loaded alert settings from {}", alerts_config_path);
+    Ok(config)
+}
+
+/// Why a transaction was alerted on
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AlertKind {
+    LargeAmount {
+        threshold: f64,
+    },
+    NewPayee,
+    DuplicateCharge {
+        /// Minutes since the earlier charge
+        minutes: i64,
+    },
+    Deviation {
+        /// Average NOK amount of the payee's past transactions
+        average: f64,
+    },
+}
+
+/// An imported transaction worth a closer look
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    /// SpareBank1 account key
+    pub account: String,
+    /// SpareBank1 transaction id
+    pub transaction_id: String,
+    pub date: NaiveDate,
+    pub payee: String,
+    pub amount: f64,
+    #[serde(flatten)]
+    pub kind: AlertKind,
+}
+
+impl Alert {
+    /// Why the transaction was alerted on
+    pub fn reason(&self) -> String {
+        match &self.kind {
+            AlertKind::LargeAmount { threshold } => format!("above {:.2} NOK", threshold),
+            AlertKind::NewPayee => "first transaction from this payee".to_string(),
+            AlertKind::DuplicateCharge { minutes } => {
+                format!("same charge {} minutes earlier", minutes)
+            }
+            AlertKind::Deviation { average } => format!("usually {:.2} NOK", average),
+        }
+    }
+
+    /// Describes the alert on a single line
+    pub fn explain(&self) -> String {
+        format!(
+            "{} | {} | {:.2} NOK | {}",
+            self.date,
+            self.payee,
+            self.amount,
+            self.reason()
+        )
+    }
+}
+
+/// Amounts of past transactions per normalised payee, learned from YNAB transactions
+#[derive(Debug, Default)]
+pub struct PayeeHistory {
+    amounts: HashMap<String, Vec<i64>>,
+}
+
+impl PayeeHistory {
+    /// Learns from every transaction except deleted ones and transfers
+    pub fn learn(transactions: &[TransactionDetail]) -> Self {
+        let mut history = PayeeHistory::default();
+        for transaction in transactions {
+            if transaction.deleted || transaction.transfer_account_id.is_some() {
+                continue;
+            }
+            // Imported transactions remember the payee the bank sent
+            let Some(bank_payee) = transaction
+                .import_payee_name_original
+                .as_ref()
+                .or(transaction.payee_name.as_ref())
+            else {
+                continue;
+            };
+            let key = payee_key(bank_payee);
+            if !key.is_empty() {
+                history
+                    .amounts
+                    .entry(key)
+                    .or_default()
+                    .push(transaction.amount);
+            }
+        }
+        history
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.amounts.contains_key(key)
+    }
+
+    /// Average milliunit amount of a payee's past transactions in the same direction
+    fn average(&self, key: &str, outflow: bool) -> Option<i64> {
+        let amounts: Vec<i64> = self
+            .amounts
+            .get(key)?
+            .iter()
+            .copied()
+            .filter(|amount| (*amount < 0) == outflow)
+            .collect();
+        if amounts.len() < MIN_HISTORY {
+            return None;
+        }
+        Some(amounts.iter().sum::<i64>() / amounts.len() as i64)
+    }
+}
+
+/// Checks transactions to import against the alert settings, flagging the ones alerted on
+///
+/// Transfers between mapped accounts are never alerted on. Returns the alerts in the order
+/// of the transactions.
+pub fn check_transactions(
+    imports: &mut [ImportTransaction],
+    history: &PayeeHistory,
+    config: &AlertConfig,
+) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+    let mut new_payees = HashSet::new();
+
+    for index in 0..imports.len() {
+        let import = &imports[index];
+        if import.transfer.is_some() {
+            continue;
+        }
+        let transaction = &import.transaction;
+        let key = payee_key(&transaction.payee);
+        let amount = ynab::to_milliunits(transaction.amount);
+        let mut kinds = Vec::new();
+
+        if let Some(threshold) = config.large_amount(&transaction.account) {
+            if transaction.amount.abs() > threshold {
+                kinds.push(AlertKind::LargeAmount { threshold });
+            }
+        }
+
+        // A payee showing up several times in one sync is only new the first time
+        if config.new_payee
+            && !key.is_empty()
+            && !history.contains(&key)
+            && new_payees.insert(key.clone())
+        {
+            kinds.push(AlertKind::NewPayee);
+        }
+
+        if let Some(window) = config.duplicate_minutes {
+            // Only the later of two equal charges is alerted on
+            let earlier = imports
+                .iter()
+                .enumerate()
+                .filter(|(other_index, other)| {
+                    other.transfer.is_none()
+                        && (other.transaction.date, *other_index) < (transaction.date, index)
+                })
+                .map(|(_, other)| &other.transaction)
+                .filter(|other| {
+                    amount < 0
+                        && other.account == transaction.account
+                        && other.amount == transaction.amount
+                        // A pending charge and its booked version are the same purchase
+                        && other.booking_status == transaction.booking_status
+                        && payee_key(&other.payee) == key
+                })
+                .map(|other| (transaction.date - other.date).num_minutes().abs())
+                .filter(|minutes| *minutes <= window)
+                .min();
+            if let Some(minutes) = earlier {
+                kinds.push(AlertKind::DuplicateCharge { minutes });
+            }
+        }
+
+        if let (Some(factor), Some(average)) =
+            (config.deviation_factor, history.average(&key, amount < 0))
+        {
+            if average != 0 && amount as f64 / average as f64 > factor {
+                kinds.push(AlertKind::Deviation {
+                    average: average as f64 / 1000.0,
+                });
+            }
+        }
+
+        if kinds.is_empty() {
+            continue;
+        }
+        let date = transaction.date.with_timezone(&Oslo).date_naive();
+        alerts.extend(kinds.into_iter().map(|kind| Alert {
+            account: transaction.account.clone(),
+            transaction_id: transaction.id.clone(),
+            date,
+            payee: import.payee_name.clone(),
+            amount: transaction.amount,
+            kind,
+        }));
+        let import = &mut imports[index];
+        if import.flag.is_none() {
+            import.flag = config.flag;
+        }
+    }
+
+    alerts
+}
@@ -1,7 +1,23 @@
 use crate::config::{Config, ConfigError};
+use crate::metrics::{Api, RecordedSend};
+use crate::sparebanken1;
+use reqwest::StatusCode;
 use serde::Deserialize;
 use std::fs;
-use tracing::{debug, info, warn};
+use tracing::{debug, info, instrument, warn};
+
+/// AuthError represents failures to obtain a SpareBank1 access token
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("SpareBank1 rejected the refresh token ({0}), run the setup again")]
+    TokenRejected(StatusCode),
+
+    #[error("Failed to refresh SpareBank1 access token: {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    ConfigError(#[from] ConfigError),
+}
 
 #[derive(Debug, Deserialize)]
 pub struct Sparebanken1AuthDataResponse {
@@ -33,25 +49,33 @@ fn save_refresh_token(
     fs::write(refresh_token_file_path, new_refresh_token)
 }
 
-async fn refresh_access_token(
-    config: &Config,
-    refresh_token: String,
-) -> Result<String, Box<dyn std::error::Error>> {
+/// Exchanges the refresh token for an access token, in its own span to tell it apart from API calls
+#[instrument(name = "refresh_token", skip_all)]
+async fn refresh_access_token(config: &Config, refresh_token: String) -> Result<String, AuthError> {
     debug!("Refreshing access token using refresh token");
     let client = reqwest::Client::new();
-    let url: &str = "https://api-auth.sparebank1.no/oauth/token";
+    let url = sparebanken1::auth_url();
 
     let body = format!(
         "grant_type=refresh_token&refresh_token={}&client_id={}&client_secret={}",
         refresh_token, config.sparebank1_client_id, config.sparebank1_client_secret
     );
 
-    let response: Sparebanken1AuthDataResponse = client
-        .post(url)
+    let response = client
+        .post(&url)
         .header("Content-Type", "application/x-www-form-urlencoded")
         .body(body)
-        .send()
-        .await?
+        .send_recorded(Api::Sparebank1)
+        .await?;
+    // An expired or revoked refresh token is rejected with invalid_grant
+    if matches!(
+        response.status(),
+        StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED
+    ) {
+        return Err(AuthError::TokenRejected(response.status()));
+    }
+    let response: Sparebanken1AuthDataResponse = response
+        .error_for_status()?
         .json::<Sparebanken1AuthDataResponse>()
         .await?;
 
@@ -61,9 +85,8 @@ async fn refresh_access_token(
     Ok(response.access_token)
 }
 
-pub async fn get_access_token(config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+pub async fn get_access_token(config: &Config) -> Result<String, AuthError> {
     debug!("Getting access token");
     let refresh_token = get_refresh_token(config)?;
-    let access_token = refresh_access_token(config, refresh_token);
-    return Ok(access_token.await?);
+    refresh_access_token(config, refresh_token).await
 }
@@ -0,0 +1,130 @@
+use crate::account_config::AccountConfig;
+use crate::sparebanken1;
+use crate::ynab::{Account, TransactionDetail};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Bank balance of a SpareBank1 account next to the cleared balance of a YNAB account it maps to
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceCheck {
+    /// SpareBank1 account key
+    pub account_key: String,
+    pub account_name: String,
+    pub budget_id: String,
+    pub ynab_account_id: String,
+    pub ynab_account_name: String,
+    /// Balance reported by SpareBank1, in milliunits
+    pub bank_balance: i64,
+    /// Cleared balance in YNAB, in milliunits
+    pub ynab_cleared_balance: i64,
+    /// The YNAB account is kept in sync by balance adjustments only
+    pub balance_only: bool,
+}
+
+impl BalanceCheck {
+    /// Amount YNAB is off by, positive when YNAB is above the bank
+    pub fn difference(&self) -> i64 {
+        self.ynab_cleared_balance - self.bank_balance
+    }
+
+    /// Whether the balances agree within a tolerance in milliunits
+    pub fn matches(&self, tolerance: i64) -> bool {
+        self.difference().abs() <= tolerance
+    }
+}
+
+/// Pairs every mapped SpareBank1 account with the YNAB accounts it is synced into
+///
+/// `ynab_accounts` holds the accounts of each budget. Accounts missing on either side are
+/// logged and left out.
+pub fn compare_balances(
+    account_config: &AccountConfig,
+    default_budget: &str,
+    bank_accounts: &[sparebanken1::Account],
+    ynab_accounts: &HashMap<String, Vec<Account>>,
+) -> Vec<BalanceCheck> {
+    let mut checks = Vec::new();
+
+    let mut account_keys: Vec<&String> = account_config.keys().collect();
+    account_keys.sort();
+    for account_key in account_keys {
+        let Some(bank_account) = bank_accounts.iter().find(|a| &a.key == account_key) else {
+            warn!(
+                "SpareBank1 account {} not found, balance not checked",
+                account_key
+            );
+            continue;
+        };
+
+        for target in &account_config[account_key] {
+            let budget_id = target.budget_id(default_budget);
+            let Some(ynab_account) = ynab_accounts
+                .get(budget_id)
+                .and_then(|accounts| accounts.iter().find(|a| a.id == target.account_id))
+            else {
+                warn!(
+                    "YNAB account {} not found in budget {}, balance not checked",
+                    target.account_id, budget_id
+                );
+                continue;
+            };
+
+            checks.push(BalanceCheck {
+                account_key: account_key.clone(),
+                account_name: bank_account.name.clone(),
+                budget_id: budget_id.to_string(),
+                ynab_account_id: ynab_account.id.clone(),
+                ynab_account_name: ynab_account.name.clone(),
+                bank_balance: (bank_account.balance * 1000.0).round() as i64,
+                ynab_cleared_balance: ynab_account.cleared_balance,
+                balance_only: target.balance_only,
+            });
+        }
+    }
+
+    checks
+}
+
+/// Payee of balance adjustment transactions
+pub const ADJUSTMENT_PAYEE: &str = "SpareBank1 Balance Adjustment";
+
+/// A transaction bringing a balance-only YNAB account in line with the bank
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceAdjustment {
+    pub budget_id: String,
+    pub account_id: String,
+    pub account_name: String,
+    pub date: NaiveDate,
+    /// Amount in milliunits
+    pub amount: i64,
+    /// Balance reported by SpareBank1, in milliunits
+    pub bank_balance: i64,
+}
+
+/// Adjustments for every balance-only account whose cleared balance differs from the bank
+pub fn adjustments(checks: &[BalanceCheck], date: NaiveDate) -> Vec<BalanceAdjustment> {
+    checks
+        .iter()
+        .filter(|check| check.balance_only && check.difference() != 0)
+        .map(|check| BalanceAdjustment {
+            budget_id: check.budget_id.clone(),
+            account_id: check.ynab_account_id.clone(),
+            account_name: check.ynab_account_name.clone(),
+            date,
+            amount: -check.difference(),
+            bank_balance: check.bank_balance,
+        })
+        .collect()
+}
+
+/// Cleared transactions dated on or before `up_to`, the ones reconciling an account would lock
+pub fn reconcilable(
+    transactions: &[TransactionDetail],
+    up_to: NaiveDate,
+) -> Vec<&TransactionDetail> {
+    transactions
+        .iter()
+        .filter(|t| !t.deleted && t.cleared == "cleared" && t.date <= up_to)
+        .collect()
+}
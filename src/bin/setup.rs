@@ -1,4 +1,5 @@
 use clap::Parser;
+use sparebank1_to_ynab::logging::{self, LogOptions};
 use sparebank1_to_ynab::sparebanken1;
 use sparebank1_to_ynab::ynab::{Account, Budget, YnabClient};
 
@@ -16,7 +17,6 @@ extern crate termion;
 use rand::Rng;
 use termion::color::{Fg, Red, Reset};
 use tracing::{debug, info};
-use tracing_subscriber;
 
 #[derive(Debug)]
 pub struct AuthResponse {
@@ -96,7 +96,7 @@ fn print_ynab_accounts(accounts: &[Account]) {
 
 fn select_budget(ynab_budgets: &[Budget]) -> &Budget {
     if ynab_budgets.len() == 1 {
-        return ynab_budgets.first().expect("Nope");
+        ynab_budgets.first().expect("Nope")
     } else {
         println!("YNAB Budgets:");
         for (index, budget) in ynab_budgets.iter().enumerate() {
@@ -113,7 +113,7 @@ fn select_budget(ynab_budgets: &[Budget]) -> &Budget {
 
         println!("YNAB Budgets: {:?}", ynab_budgets);
         println!("Choice: {}", choice);
-        return ynab_budgets.get(choice - 1).expect("Do it");
+        ynab_budgets.get(choice - 1).expect("Do it")
     }
 }
 /// SpareBank1 to YNAB setup wizard
@@ -131,6 +131,9 @@ struct Args {
     
     /// YNAB personal access token
     ynab_access_token: String,
+
+    #[command(flatten)]
+    log: LogOptions,
 }
 
 
@@ -169,19 +172,13 @@ fn write_config_file(
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    // Initialize tracing subscriber for logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
+    let args = Args::parse();
+    let _log_guard = logging::init(&args.log, false)?;
 
     info!("Starting SpareBank1 to YNAB setup wizard");
 
     let state = rand::rng().random_range(100_000..1_000_000);
     let redirect_uri = "http://localhost:9050";
-    let args = Args::parse();
 
     let url = format!(
         "https://api-auth.sparebank1.no/oauth/authorize?client_id={}&state={}&redirect_uri={}&finInst={}&response_type=code",
@@ -1,12 +1,22 @@
-use clap::Parser;
-use sparebank1_to_ynab::account_config;
-use sparebank1_to_ynab::auth_data;
-use sparebank1_to_ynab::config::Config;
-use sparebank1_to_ynab::sparebanken1;
-use sparebank1_to_ynab::ynab::YnabClient;
-use std::error::Error;
-use tracing::{error, info, warn};
-use tracing_subscriber;
+use chrono::{TimeDelta, Utc};
+use chrono_tz::Europe::Oslo;
+use clap::{Parser, Subcommand, ValueEnum};
+use futures::future::join_all;
+use sparebank1_to_ynab::exit_code::ExitReason;
+use sparebank1_to_ynab::logging::{self, LogOptions};
+use sparebank1_to_ynab::notify::{self, Notification, Notifier};
+use sparebank1_to_ynab::profile::{self, Profile};
+use sparebank1_to_ynab::report::{ProfileReport, SyncReport};
+use sparebank1_to_ynab::server;
+use sparebank1_to_ynab::sync::{self, SyncError, SyncSummary};
+use sparebank1_to_ynab::{health, metrics};
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use tracing::{error, field, info, info_span, warn, Instrument};
+
+/// Longest interval between syncs in daemon mode, one week
+const MAX_INTERVAL_MINUTES: u64 = 7 * 24 * 60;
 
 /// SpareBank1 to YNAB transaction synchronization tool
 #[derive(Parser, Debug)]
@@ -15,97 +25,397 @@ struct Args {
     /// Enable dry-run mode (preview transactions without importing)
     #[arg(short, long)]
     dry_run: bool,
+
+    /// Only sync the named profile (can be repeated, defaults to all profiles)
+    #[arg(short, long = "profile", value_name = "NAME")]
+    profiles: Vec<String>,
+
+    /// Sync profiles concurrently instead of one after another
+    #[arg(short, long)]
+    concurrent: bool,
+
+    /// Exit with an error when a YNAB balance differs from SpareBank1 beyond the tolerance
+    #[arg(long)]
+    fail_on_balance_mismatch: bool,
+
+    /// Mark cleared transactions reconciled in YNAB for accounts whose balance matches the bank
+    #[arg(long)]
+    reconcile: bool,
+
+    /// Format of the sync report printed when the run finishes
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Keep running and sync every MINUTES minutes (daemon mode), at most a week
+    #[arg(
+        long,
+        value_name = "MINUTES",
+        value_parser = clap::value_parser!(u64).range(1..=MAX_INTERVAL_MINUTES)
+    )]
+    interval: Option<u64>,
+
+    /// Serve metrics and health checks on this address in daemon mode, e.g. 0.0.0.0:9898
+    #[arg(
+        long,
+        value_name = "ADDR",
+        alias = "metrics-addr",
+        requires = "interval"
+    )]
+    listen: Option<SocketAddr>,
+
+    #[command(flatten)]
+    log: LogOptions,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Format of the sync report
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// Table with one row per account
+    Text,
+    /// JSON document, logs go to stderr so stdout stays parseable
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Preview which rules match recent transactions without importing anything
+    TestRules {
+        /// Rules file to test instead of the configured one
+        #[arg(long, value_name = "PATH")]
+        rules: Option<String>,
+
+        /// Only list transactions matched by a rule
+        #[arg(long)]
+        matched_only: bool,
+    },
+}
+
+/// Runs the sync for a profile inside a span carrying the profile name
+async fn run_profile(profile: &Profile, dry_run: bool) -> Result<SyncSummary, SyncError> {
+    // CLI flag takes precedence over config
+    let dry_run = dry_run || profile.config.dry_run;
+    let span = info_span!(
+        "profile",
+        profile = %profile.name,
+        dry_run,
+        fetched = field::Empty,
+        imported = field::Empty,
+        duration_ms = field::Empty,
+    );
+    let started = Instant::now();
+
+    let result = async {
+        if dry_run {
+            warn!("DRY-RUN MODE: No transactions will be sent to YNAB");
+        }
+        sync::sync_profile(profile, dry_run).await
+    }
+    .instrument(span.clone())
+    .await;
+
+    if let Ok(summary) = &result {
+        span.record("fetched", summary.fetched);
+        span.record("imported", summary.imported);
+    }
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    result
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    // Initialize tracing subscriber for logging
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    // Logs are kept off stdout when it carries the JSON report
+    let _log_guard = match logging::init(&args.log, args.output == OutputFormat::Json) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitReason::Config.into();
+        }
+    };
 
     info!("Starting SpareBank1 to YNAB sync");
+    run(&args).await.into()
+}
 
-    let args = Args::parse();
-    let config = Config::new()?;
+/// Ctrl-C, or SIGTERM on Unix, listened for from creation on so a signal during a sync is kept
+struct ShutdownSignal {
+    #[cfg(unix)]
+    interrupt: tokio::signal::unix::Signal,
+    #[cfg(unix)]
+    terminate: tokio::signal::unix::Signal,
+    #[cfg(not(unix))]
+    ctrl_c: tokio::signal::windows::CtrlC,
+}
 
-    // CLI flag takes precedence over config
-    let dry_run = args.dry_run || config.dry_run;
+impl ShutdownSignal {
+    fn listen() -> std::io::Result<Self> {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            Ok(Self {
+                interrupt: signal(SignalKind::interrupt())?,
+                terminate: signal(SignalKind::terminate())?,
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            Ok(Self {
+                ctrl_c: tokio::signal::windows::ctrl_c()?,
+            })
+        }
+    }
+
+    /// Resolves on the next signal, or at once if one arrived since the last call
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        {
+            tokio::select! {
+                _ = self.interrupt.recv() => {}
+                _ = self.terminate.recv() => {}
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            self.ctrl_c.recv().await;
+        }
+    }
+}
+
+/// Loads the profiles selected on the command line
+fn select_profiles(args: &Args) -> Result<Vec<Profile>, String> {
+    let mut profiles = profile::load_profiles().map_err(|e| e.to_string())?;
+
+    if !args.profiles.is_empty() {
+        if let Some(unknown) = args
+            .profiles
+            .iter()
+            .find(|name| !profiles.iter().any(|p| &p.name == *name))
+        {
+            return Err(format!("Unknown profile: {}", unknown));
+        }
+        profiles.retain(|p| args.profiles.contains(&p.name));
+    }
+    Ok(profiles)
+}
+
+/// Loads the selected profiles and syncs them once, or repeatedly in daemon mode
+async fn run(args: &Args) -> ExitReason {
+    // Notifications are loaded first, so a broken profile configuration is notified as well
+    let mut notifier = match notify::load_notifications() {
+        Ok(config) => Notifier::new(config, Utc::now().with_timezone(&Oslo)),
+        Err(e) => {
+            error!("{}", e);
+            return ExitReason::Config;
+        }
+    };
+
+    let mut profiles = match select_profiles(args) {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            error!("{}", e);
+            if args.command.is_none() {
+                if let Some(notification) = notifier.record_config_error(&e) {
+                    notifier.send(&notification).await;
+                }
+            }
+            return ExitReason::Config;
+        }
+    };
+    for p in profiles.iter_mut() {
+        p.config.fail_on_balance_mismatch |= args.fail_on_balance_mismatch;
+        p.config.reconcile |= args.reconcile;
+    }
 
-    if dry_run {
-        warn!("DRY-RUN MODE: No transactions will be sent to YNAB");
+    if let Some(Command::TestRules {
+        rules,
+        matched_only,
+    }) = &args.command
+    {
+        for p in &profiles {
+            if let Err(e) = sync::test_rules(p, rules.as_deref(), *matched_only)
+                .instrument(info_span!("profile", name = %p.name))
+                .await
+            {
+                error!("Profile {}: testing rules failed: {}", p.name, e);
+                return ExitReason::from(&e);
+            }
+        }
+        return ExitReason::Success;
     }
-    info!("Fetching access token");
-    let access_token = match auth_data::get_access_token(&config).await {
-        Ok(token) => {
-            info!("Successfully obtained access token");
-            token
+
+    let Some(interval) = args.interval else {
+        let reason = sync_profiles(&profiles, args, &mut notifier).await;
+        // With a state file, scheduled one-shot runs send the daily summary as well
+        if let Some(summary) = notifier.daily_summary_due(Utc::now().with_timezone(&Oslo)) {
+            notifier.send(&summary).await;
         }
+        return reason;
+    };
+
+    // Listening starts before the first round, so a signal during any sync is not lost
+    let mut shutdown = match ShutdownSignal::listen() {
+        Ok(shutdown) => shutdown,
         Err(e) => {
-            error!("Failed to get access token: {}", e);
-            return Err(e);
+            error!("Failed to listen for shutdown signals: {}", e);
+            return ExitReason::Failure;
         }
     };
 
-    info!(
-        "Loading account configuration from {}",
-        config.account_config_path
-    );
-    let account_config = account_config::read_accounts_json(&config.account_config_path)?;
-    let accounts: Vec<String> = account_config.keys().cloned().collect();
-    info!("Configured accounts: {}", accounts.len());
-
-    info!("Fetching transactions from SpareBank1");
-    let transactions = sparebanken1::get_transactions(&access_token, accounts).await?;
-    info!("Retrieved {} transactions", transactions.len());
-
-    // Create YnabClient instance
-    let ynab_client = YnabClient::new(
-        account_config,
-        config.ynab_access_token.clone(),
-        config.ynab_budget_id.clone(),
+    health::global().start(TimeDelta::minutes(interval as i64), Utc::now());
+    for p in &profiles {
+        metrics::global().register_profile(&p.name, &p.config.refresh_token_file_path);
+        health::global().register_profile(p);
+        let validated = sync::validate_profile(&p.config);
+        if let Err(e) = &validated {
+            error!("Profile {}: invalid configuration: {}", p.name, e);
+        }
+        health::global().record_config(&p.name, &validated);
+    }
+    if let Some(addr) = args.listen {
+        if let Err(e) = server::start(addr).await {
+            error!("Failed to start HTTP server on {}: {}", addr, e);
+            return ExitReason::Failure;
+        }
+    }
+
+    // A sync in progress is finished before stopping, so imports are never cut off halfway
+    info!("Running as a daemon, syncing every {} minutes", interval);
+    loop {
+        let reason = sync_profiles(&profiles, args, &mut notifier).await;
+        health::global().record_round(Utc::now());
+        if let Some(summary) = notifier.daily_summary_due(Utc::now().with_timezone(&Oslo)) {
+            notifier.send(&summary).await;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(interval * 60)) => {}
+            _ = shutdown.recv() => {
+                info!("Stopping daemon");
+                return reason;
+            }
+        }
+    }
+}
+
+/// Syncs every profile once inside a span for the run, returning the exit reason for the round
+async fn sync_profiles(profiles: &[Profile], args: &Args, notifier: &mut Notifier) -> ExitReason {
+    let span = info_span!(
+        "sync_run",
+        started_at = %Utc::now().to_rfc3339(),
+        profiles = profiles.len(),
+        failed = field::Empty,
+        exit_code = field::Empty,
+        duration_ms = field::Empty,
     );
+    let started = Instant::now();
 
-    if dry_run {
-        // Dry-run mode: display transactions without importing
-        info!(
-            "DRY-RUN: Would import {} transactions to YNAB",
-            transactions.len()
-        );
+    let (reason, failed) = sync_round(profiles, args, notifier)
+        .instrument(span.clone())
+        .await;
 
-        for (index, transaction) in transactions.iter().enumerate() {
-            info!(
-                "  [{}] {} | {} | {} NOK | {}",
-                index + 1,
-                transaction.date.format("%Y-%m-%d"),
-                transaction.payee,
-                transaction.amount,
-                transaction.description
-            );
-        }
+    span.record("failed", failed);
+    span.record("exit_code", reason.code());
+    span.record("duration_ms", started.elapsed().as_millis() as u64);
+    reason
+}
+
+/// Syncs every profile once and prints the report, returning the exit reason and failed profiles
+async fn sync_round(
+    profiles: &[Profile],
+    args: &Args,
+    notifier: &mut Notifier,
+) -> (ExitReason, usize) {
+    info!("Syncing {} profile(s)", profiles.len());
+    let started_at = Utc::now();
 
-        let now = chrono::offset::Local::now();
-        info!("Dry-run completed at {}", now);
-        warn!("DRY-RUN MODE: No transactions were actually sent to YNAB");
+    let results = if args.concurrent {
+        join_all(profiles.iter().map(|p| run_profile(p, args.dry_run))).await
     } else {
-        // Normal mode: import transactions to YNAB
-        info!("Importing transactions to YNAB");
-        let ynab_response = ynab_client.add_transactions(transactions).await?;
-
-        let now = chrono::offset::Local::now();
-        info!("Sync completed at {}", now);
-        info!(
-            "Added {} new transactions",
-            ynab_response.transaction_ids.len()
-        );
-        info!(
-            "Skipped {} duplicate transactions",
-            ynab_response.duplicate_import_ids.len()
-        );
+        let mut results = Vec::with_capacity(profiles.len());
+        for p in profiles {
+            results.push(run_profile(p, args.dry_run).await);
+        }
+        results
+    };
+
+    // Per-profile report
+    let mut reports = Vec::with_capacity(profiles.len());
+    let mut failures = Vec::new();
+    for (profile, result) in profiles.iter().zip(results) {
+        let dry_run = args.dry_run || profile.config.dry_run;
+        metrics::global().record_sync(&profile.name, &result);
+        if let Some(notification) = notifier.record_sync(&profile.name, &result) {
+            notifier.send(&notification).await;
+        }
+        health::global().record_sync(&profile.name, &result, Utc::now());
+        // Alerts found in dry-run mode are only previewed
+        if let (false, Some(notification)) = (
+            dry_run,
+            SyncSummary::of(&result)
+                .and_then(|s| Notification::for_alerts(&profile.name, &s.alerts)),
+        ) {
+            notifier.send(&notification).await;
+        }
+        match result {
+            Ok(summary) => {
+                info!(
+                    "Profile {}: fetched {}, skipped {} by rule, added {}, skipped {} duplicates, updated {}, settled {} pending, {} pending vanished, {} balance mismatches, {} balance adjustments, reconciled {}, learned {}, {} alerts",
+                    profile.name,
+                    summary.fetched,
+                    summary.skipped,
+                    summary.imported,
+                    summary.duplicates,
+                    summary.updated,
+                    summary.settled,
+                    summary.vanished,
+                    summary.balance_mismatches,
+                    summary.adjusted,
+                    summary.reconciled,
+                    summary.learned,
+                    summary.alerts.len()
+                );
+                reports.push(ProfileReport::ok(&profile.name, dry_run, summary));
+            }
+            Err(e) => {
+                error!("Profile {}: sync failed: {}", profile.name, e);
+                failures.push(ExitReason::from(&e));
+                reports.push(ProfileReport::failed(
+                    &profile.name,
+                    dry_run,
+                    e.to_string(),
+                    e.summary().cloned(),
+                ));
+            }
+        }
     }
 
-    Ok(())
+    let report = SyncReport {
+        started_at,
+        finished_at: Utc::now(),
+        profiles: reports,
+    };
+    match args.output {
+        OutputFormat::Text => println!("{}", report.to_table()),
+        OutputFormat::Json => match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                error!("Failed to serialize sync report: {}", e);
+                return (ExitReason::Failure, failures.len());
+            }
+        },
+    }
+
+    let reason = ExitReason::for_run(&failures, profiles.len());
+    if reason != ExitReason::Success {
+        error!(
+            "{} of {} profiles failed to sync (exit code {})",
+            failures.len(),
+            profiles.len(),
+            reason.code()
+        );
+    }
+    (reason, failures.len())
 }
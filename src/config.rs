@@ -1,5 +1,12 @@
+use crate::health;
+use crate::learn::{self, LearnMode};
+use crate::memo::MemoTemplate;
+use crate::pending::VanishedPending;
 use std::env;
+use std::fmt::Display;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Once;
 use tracing::{debug, info, warn};
 
 /// ConfigError represents all possible errors when initializing configuration
@@ -13,6 +20,12 @@ pub enum ConfigError {
 
     #[error("Invalid configuration: {0}")]
     ValidationError(String),
+
+    #[error("Failed to read configuration file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Failed to parse configuration file: {0}")]
+    ParseError(#[from] serde_json::Error),
 }
 
 /// Config holds the application configuration
@@ -26,21 +39,34 @@ pub struct Config {
     pub account_config_path: String,
     pub refresh_token_file_path: String,
     pub initial_refresh_token: String,
+    pub rules_config_path: Option<String>,
+    /// File with the transactions to alert on
+    pub alerts_config_path: Option<String>,
+    /// Memo template for accounts without their own
+    pub memo_template: Option<MemoTemplate>,
+    /// What to do with pending transactions that disappear from the bank
+    pub pending_vanished: VanishedPending,
+    /// File recording what was written to YNAB, enables updating changed transactions
+    pub sync_state_path: Option<String>,
+    /// Largest difference in NOK between bank and YNAB balances reported as matching
+    pub balance_tolerance: f64,
+    /// Fail the sync when a balance differs beyond the tolerance
+    pub fail_on_balance_mismatch: bool,
+    /// Mark cleared transactions reconciled in YNAB when the balance matches the bank
+    pub reconcile: bool,
+    /// Whether categories learned from YNAB history are used on new imports
+    pub learn_categories: LearnMode,
+    /// Share of a payee's past transactions that must agree on a learned category
+    pub learn_confidence: f64,
+    /// Days a refresh token stays valid, for reporting when it is near expiry
+    pub refresh_token_lifetime_days: u32,
     pub dry_run: bool,
 }
 
 impl Config {
     /// Creates a new Config from environment variables
     pub fn new() -> Result<Self, ConfigError> {
-        // Load environment variables from .env file if present
-        if let Err(e) = dotenvy::dotenv() {
-            warn!(
-                "No .env file found, using system environment variables: {}",
-                e
-            );
-        } else {
-            debug!("Loaded configuration from .env file");
-        }
+        Self::load_dotenv();
 
         let config = Self {
             sparebank1_client_id: Self::get_env_or_error("SPAREBANK1_CLIENT_ID")?,
@@ -54,6 +80,19 @@ impl Config {
                 "refresh_token.txt",
             )?,
             initial_refresh_token: Self::get_env_or_error("INITIAL_REFRESH_TOKEN")?,
+            rules_config_path: Self::get_env_optional("RULES_CONFIG_PATH")?,
+            alerts_config_path: Self::get_env_optional("ALERTS_CONFIG_PATH")?,
+            memo_template: Self::get_env_parsed("MEMO_TEMPLATE")?,
+            pending_vanished: Self::get_env_parsed("PENDING_VANISHED")?.unwrap_or_default(),
+            sync_state_path: Self::get_env_optional("SYNC_STATE_PATH")?,
+            balance_tolerance: Self::get_env_parsed("BALANCE_TOLERANCE")?.unwrap_or_default(),
+            fail_on_balance_mismatch: Self::get_env_bool("FAIL_ON_BALANCE_MISMATCH"),
+            reconcile: Self::get_env_bool("RECONCILE"),
+            learn_categories: Self::get_env_parsed("LEARN_CATEGORIES")?.unwrap_or_default(),
+            learn_confidence: Self::get_env_parsed("LEARN_CONFIDENCE")?
+                .unwrap_or(learn::DEFAULT_CONFIDENCE),
+            refresh_token_lifetime_days: Self::get_env_parsed("REFRESH_TOKEN_LIFETIME_DAYS")?
+                .unwrap_or(health::DEFAULT_REFRESH_TOKEN_LIFETIME_DAYS),
             dry_run: Self::get_env_bool("DRY_RUN"),
         };
 
@@ -68,6 +107,7 @@ impl Config {
     }
 
     /// Creates a new Config with explicitly provided values (useful for testing and setup)
+    #[allow(clippy::too_many_arguments)]
     pub fn with_values(
         sparebank1_client_id: String,
         sparebank1_client_secret: String,
@@ -88,6 +128,17 @@ impl Config {
             refresh_token_file_path: refresh_token_file_path
                 .unwrap_or_else(|| "refresh_token.txt".to_string()),
             initial_refresh_token,
+            rules_config_path: None,
+            alerts_config_path: None,
+            memo_template: None,
+            pending_vanished: VanishedPending::default(),
+            sync_state_path: None,
+            balance_tolerance: 0.0,
+            fail_on_balance_mismatch: false,
+            reconcile: false,
+            learn_categories: LearnMode::default(),
+            learn_confidence: learn::DEFAULT_CONFIDENCE,
+            refresh_token_lifetime_days: health::DEFAULT_REFRESH_TOKEN_LIFETIME_DAYS,
             dry_run: false,
         };
 
@@ -97,9 +148,24 @@ impl Config {
         Ok(config)
     }
 
+    /// Load environment variables from .env file if present (only once per process)
+    pub(crate) fn load_dotenv() {
+        static LOAD_DOTENV: Once = Once::new();
+        LOAD_DOTENV.call_once(|| {
+            if let Err(e) = dotenvy::dotenv() {
+                warn!(
+                    "No .env file found, using system environment variables: {}",
+                    e
+                );
+            } else {
+                debug!("Loaded configuration from .env file");
+            }
+        });
+    }
+
     /// Get an environment variable or return an error if it's not present
     fn get_env_or_error(name: &str) -> Result<String, ConfigError> {
-        env::var(name).map_err(|e| ConfigError::EnvVarError(e))
+        env::var(name).map_err(ConfigError::EnvVarError)
     }
 
     /// Get an environment variable with a default value if not present
@@ -111,8 +177,34 @@ impl Config {
         }
     }
 
+    /// Base URL of an API from the environment variable `name`, for testing against a mock server
+    pub fn api_url(name: &str, default: &str) -> String {
+        env::var(name).unwrap_or_else(|_| default.to_string())
+    }
+
+    /// Get an optional environment variable, None if not present
+    fn get_env_optional(name: &str) -> Result<Option<String>, ConfigError> {
+        match env::var(name) {
+            Ok(val) => Ok(Some(val)),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(e) => Err(ConfigError::EnvVarError(e)),
+        }
+    }
+
+    /// Get an optional environment variable parsed into a value, None if not present
+    fn get_env_parsed<T>(name: &str) -> Result<Option<T>, ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        Self::get_env_optional(name)?
+            .map(|value| value.parse())
+            .transpose()
+            .map_err(|e| ConfigError::ValidationError(format!("{}: {}", name, e)))
+    }
+
     /// Get a boolean environment variable (true if set to "1", "true", "yes", case-insensitive)
-    fn get_env_bool(name: &str) -> bool {
+    pub(crate) fn get_env_bool(name: &str) -> bool {
         match env::var(name) {
             Ok(val) => {
                 let val_lower = val.to_lowercase();
@@ -123,7 +215,7 @@ impl Config {
     }
 
     /// Validates the configuration values
-    fn validate(&self) -> Result<(), ConfigError> {
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
         // Check that required IDs and tokens are not empty
         if self.sparebank1_client_id.trim().is_empty() {
             return Err(ConfigError::ValidationError(
@@ -153,6 +245,32 @@ impl Config {
                 self.account_config_path
             );
         }
+        if self.balance_tolerance.is_nan() || self.balance_tolerance < 0.0 {
+            return Err(ConfigError::ValidationError(
+                "BALANCE_TOLERANCE must be a non-negative number".to_string(),
+            ));
+        }
+        if !(self.learn_confidence > 0.0 && self.learn_confidence <= 1.0) {
+            return Err(ConfigError::ValidationError(
+                "LEARN_CONFIDENCE must be above 0 and at most 1".to_string(),
+            ));
+        }
+        if self.refresh_token_lifetime_days == 0 {
+            return Err(ConfigError::ValidationError(
+                "REFRESH_TOKEN_LIFETIME_DAYS must be at least 1".to_string(),
+            ));
+        }
+
+        if let Some(rules_config_path) = &self.rules_config_path {
+            if !PathBuf::from(rules_config_path).exists() {
+                warn!("Rules file does not exist at {}", rules_config_path);
+            }
+        }
+        if let Some(alerts_config_path) = &self.alerts_config_path {
+            if !PathBuf::from(alerts_config_path).exists() {
+                warn!("Alerts file does not exist at {}", alerts_config_path);
+            }
+        }
 
         Ok(())
     }
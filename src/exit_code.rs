@@ -0,0 +1,76 @@
+use crate::auth_data::AuthError;
+use crate::sync::SyncError;
+
+/// Exit code of the sync tool, one per class of failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExitReason {
+    Success = 0,
+    /// Failures outside the classes below
+    Failure = 1,
+    /// Invalid configuration, account mapping, rules, alerts or sync state
+    Config = 3,
+    /// SpareBank1 rejected the refresh token, the setup must be run again
+    ReauthRequired = 4,
+    /// SpareBank1 could not be reached or returned an error
+    Sparebank1Unavailable = 5,
+    /// YNAB could not be reached or returned an error
+    YnabUnavailable = 6,
+    /// Balances differ from SpareBank1 and failing on mismatches is enabled
+    BalanceMismatch = 7,
+    /// Some profiles synced and others failed
+    PartialSync = 8,
+}
+
+impl ExitReason {
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Exit reason for a run, given the failures of its profiles
+    ///
+    /// A rejected refresh token is reported even when other profiles synced, since it never
+    /// recovers without running the setup again. Otherwise a run where only some profiles failed
+    /// is a partial sync. When every profile failed, the failure needing attention first wins:
+    /// configuration before authentication before outages.
+    pub fn for_run(failures: &[ExitReason], profiles: usize) -> ExitReason {
+        if failures.is_empty() {
+            ExitReason::Success
+        } else if failures.len() < profiles {
+            if failures.contains(&ExitReason::ReauthRequired) {
+                ExitReason::ReauthRequired
+            } else {
+                ExitReason::PartialSync
+            }
+        } else {
+            failures
+                .iter()
+                .copied()
+                .min()
+                .unwrap_or(ExitReason::Failure)
+        }
+    }
+}
+
+impl From<&SyncError> for ExitReason {
+    fn from(error: &SyncError) -> Self {
+        match error {
+            SyncError::ConfigError(_)
+            | SyncError::RuleError(_)
+            | SyncError::AlertError(_)
+            | SyncError::StateError(_) => ExitReason::Config,
+            SyncError::AuthError(AuthError::TokenRejected(_)) => ExitReason::ReauthRequired,
+            SyncError::AuthError(AuthError::ConfigError(_)) => ExitReason::Config,
+            SyncError::AuthError(AuthError::RequestError(_)) | SyncError::Sparebank1Error(_) => {
+                ExitReason::Sparebank1Unavailable
+            }
+            SyncError::YnabError(_) => ExitReason::YnabUnavailable,
+            SyncError::BalanceMismatch { .. } => ExitReason::BalanceMismatch,
+        }
+    }
+}
+
+impl From<ExitReason> for std::process::ExitCode {
+    fn from(reason: ExitReason) -> Self {
+        std::process::ExitCode::from(reason.code())
+    }
+}
@@ -0,0 +1,206 @@
+use crate::auth_data::AuthError;
+use crate::exit_code::ExitReason;
+use crate::profile::Profile;
+use crate::sync::{SyncError, SyncSummary};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::{Mutex, OnceLock};
+
+/// Days a refresh token is assumed to stay valid after it was issued
+pub const DEFAULT_REFRESH_TOKEN_LIFETIME_DAYS: u32 = 365;
+
+/// Days before the refresh token expires that it is reported as near expiry
+pub const REFRESH_TOKEN_WARNING_DAYS: i64 = 7;
+
+/// Sync rounds that may be missed before the daemon is reported as stuck
+const MISSED_ROUNDS: i32 = 3;
+
+#[derive(Debug, Clone)]
+struct LastSync {
+    finished_at: DateTime<Utc>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ProfileHealth {
+    refresh_token_file_path: String,
+    refresh_token_lifetime_days: u32,
+    config_error: Option<String>,
+    token_rejected: bool,
+    last_sync: Option<LastSync>,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    started_at: Option<DateTime<Utc>>,
+    interval: Option<TimeDelta>,
+    last_round: Option<DateTime<Utc>>,
+    profiles: BTreeMap<String, ProfileHealth>,
+}
+
+/// Readiness of a single profile, with what keeps it from being ready
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProfileReadiness {
+    pub profile: String,
+    pub ready: bool,
+    pub last_sync_at: Option<DateTime<Utc>>,
+    pub refresh_token_expires_at: Option<DateTime<Utc>>,
+    pub problems: Vec<String>,
+}
+
+/// Readiness of the daemon, ready when every profile is
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub profiles: Vec<ProfileReadiness>,
+}
+
+/// Health of the daemon and its profiles, reported by the HTTP server
+#[derive(Debug, Default)]
+pub struct Health {
+    state: Mutex<HealthState>,
+}
+
+/// Health shared by the whole process
+pub fn global() -> &'static Health {
+    static HEALTH: OnceLock<Health> = OnceLock::new();
+    HEALTH.get_or_init(Health::default)
+}
+
+impl Health {
+    fn state(&self) -> std::sync::MutexGuard<'_, HealthState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Marks the daemon as started, syncing every interval
+    pub fn start(&self, interval: TimeDelta, now: DateTime<Utc>) {
+        let mut state = self.state();
+        state.started_at = Some(now);
+        state.interval = Some(interval);
+    }
+
+    pub fn register_profile(&self, profile: &Profile) {
+        self.state().profiles.insert(
+            profile.name.clone(),
+            ProfileHealth {
+                refresh_token_file_path: profile.config.refresh_token_file_path.clone(),
+                refresh_token_lifetime_days: profile.config.refresh_token_lifetime_days,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Records whether the account mapping and rules of a profile could be loaded
+    pub fn record_config(&self, profile: &str, result: &Result<(), SyncError>) {
+        let mut state = self.state();
+        let health = state.profiles.entry(profile.to_string()).or_default();
+        health.config_error = result.as_ref().err().map(|e| e.to_string());
+    }
+
+    /// Records the outcome of syncing a profile
+    pub fn record_sync(
+        &self,
+        profile: &str,
+        result: &Result<SyncSummary, SyncError>,
+        now: DateTime<Utc>,
+    ) {
+        let mut state = self.state();
+        let health = state.profiles.entry(profile.to_string()).or_default();
+        health.last_sync = Some(LastSync {
+            finished_at: now,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+
+        match result {
+            Ok(_) => {
+                health.config_error = None;
+                health.token_rejected = false;
+            }
+            Err(SyncError::AuthError(AuthError::TokenRejected(_))) => health.token_rejected = true,
+            Err(e) if ExitReason::from(e) == ExitReason::Config => {
+                health.config_error = Some(e.to_string());
+            }
+            // Failures after the token refresh show the token still works
+            Err(SyncError::AuthError(_)) => {}
+            Err(_) => health.token_rejected = false,
+        }
+    }
+
+    /// Records that a sync round over all profiles finished
+    pub fn record_round(&self, now: DateTime<Utc>) {
+        self.state().last_round = Some(now);
+    }
+
+    /// Whether the daemon keeps syncing, false when several rounds in a row were missed
+    pub fn is_alive(&self, now: DateTime<Utc>) -> bool {
+        let state = self.state();
+        let (Some(started_at), Some(interval)) = (state.started_at, state.interval) else {
+            return true;
+        };
+        let last = state.last_round.unwrap_or(started_at);
+        now - last <= interval * MISSED_ROUNDS
+    }
+
+    /// Readiness of every profile
+    pub fn readiness(&self, now: DateTime<Utc>) -> Readiness {
+        let state = self.state();
+        let profiles: Vec<ProfileReadiness> = state
+            .profiles
+            .iter()
+            .map(|(name, health)| profile_readiness(name, health, now))
+            .collect();
+        Readiness {
+            ready: profiles.iter().all(|p| p.ready),
+            profiles,
+        }
+    }
+}
+
+/// When the refresh token expires, counted from when its file was last written
+fn refresh_token_expiry(health: &ProfileHealth) -> Option<DateTime<Utc>> {
+    let modified = fs::metadata(&health.refresh_token_file_path)
+        .and_then(|m| m.modified())
+        .ok()?;
+    Some(
+        DateTime::<Utc>::from(modified)
+            + TimeDelta::days(health.refresh_token_lifetime_days.into()),
+    )
+}
+
+fn profile_readiness(name: &str, health: &ProfileHealth, now: DateTime<Utc>) -> ProfileReadiness {
+    let mut problems = Vec::new();
+
+    if let Some(error) = &health.config_error {
+        problems.push(format!("invalid configuration: {}", error));
+    }
+
+    match &health.last_sync {
+        None => problems.push("no sync finished yet".to_string()),
+        Some(LastSync {
+            error: Some(error), ..
+        }) => problems.push(format!("last sync failed: {}", error)),
+        Some(_) => {}
+    }
+
+    let expires_at = refresh_token_expiry(health);
+    if health.token_rejected {
+        problems.push("refresh token rejected, run the setup again".to_string());
+    } else if let Some(expires_at) = expires_at {
+        if expires_at - now < TimeDelta::days(REFRESH_TOKEN_WARNING_DAYS) {
+            problems.push(format!(
+                "refresh token expires at {}",
+                expires_at.format("%Y-%m-%d %H:%M UTC")
+            ));
+        }
+    }
+
+    ProfileReadiness {
+        profile: name.to_string(),
+        ready: problems.is_empty(),
+        last_sync_at: health.last_sync.as_ref().map(|s| s.finished_at),
+        refresh_token_expires_at: expires_at,
+        problems,
+    }
+}
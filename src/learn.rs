@@ -0,0 +1,254 @@
+use crate::ynab::{ImportTransaction, TransactionDetail};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Days of YNAB history categories are learned from
+pub const HISTORY_DAYS: i64 = 365;
+
+/// Share of a payee's past transactions that must agree before a category is used
+pub const DEFAULT_CONFIDENCE: f64 = 0.8;
+
+/// Fewest past transactions a payee needs before anything is learned from it
+const MIN_MATCHES: usize = 2;
+
+/// Category YNAB shows on split transactions, the lines hold the real categories
+const SPLIT_CATEGORY_NAME: &str = "Split (Multiple Categories)";
+
+/// Whether categories learned from YNAB history are used on new imports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LearnMode {
+    #[default]
+    Off,
+    /// Set the learned category but leave the transaction unapproved for review
+    Suggest,
+    /// Set the learned category and approve the transaction
+    Apply,
+}
+
+impl FromStr for LearnMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "off" => Ok(LearnMode::Off),
+            "suggest" => Ok(LearnMode::Suggest),
+            "apply" => Ok(LearnMode::Apply),
+            _ => Err(format!(
+                "expected 'off', 'suggest' or 'apply', got '{}'",
+                value
+            )),
+        }
+    }
+}
+
+/// Normalises a bank payee so recurring merchants share a key
+///
+/// Words containing digits, such as store numbers and references, are dropped.
+pub fn payee_key(payee: &str) -> String {
+    payee
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !word.chars().any(|c| c.is_ascii_digit()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A value seen on past transactions of a payee, with how often
+#[derive(Debug, Clone, PartialEq)]
+pub struct Learned {
+    pub value: String,
+    /// Past transactions with this value
+    pub matches: usize,
+    /// Past transactions of the payee
+    pub total: usize,
+}
+
+impl Learned {
+    /// Share of the payee's past transactions with this value
+    pub fn confidence(&self) -> f64 {
+        self.matches as f64 / self.total as f64
+    }
+}
+
+/// Category and payee name learned for a bank payee
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    /// Normalised bank payee the suggestion was learned for
+    pub key: String,
+    pub category: Option<Learned>,
+    /// YNAB payee name the bank payee was renamed to
+    pub payee: Option<Learned>,
+}
+
+impl Suggestion {
+    /// Describes where the suggestion comes from, for previews
+    pub fn explain(&self) -> String {
+        let parts: Vec<String> = [("category", &self.category), ("payee", &self.payee)]
+            .into_iter()
+            .filter_map(|(label, learned)| {
+                let learned = learned.as_ref()?;
+                Some(format!(
+                    "{} {} ({} of {})",
+                    label, learned.value, learned.matches, learned.total
+                ))
+            })
+            .collect();
+        format!("{} from past '{}' transactions", parts.join(", "), self.key)
+    }
+}
+
+/// Most common value among counts, ties broken by name so the result is stable
+fn most_common(counts: &HashMap<String, usize>) -> Option<Learned> {
+    let total = counts.values().sum();
+    counts
+        .iter()
+        .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(value, matches)| Learned {
+            value: value.clone(),
+            matches: *matches,
+            total,
+        })
+}
+
+/// Payee-to-category and payee-to-renamed-payee associations learned from YNAB transactions
+#[derive(Debug, Default)]
+pub struct CategoryHistory {
+    categories: HashMap<String, HashMap<String, usize>>,
+    payees: HashMap<String, HashMap<String, usize>>,
+}
+
+impl CategoryHistory {
+    /// Learns from approved, categorised transactions; transfers and splits are left out
+    pub fn learn(transactions: &[TransactionDetail]) -> Self {
+        let mut history = CategoryHistory::default();
+
+        for transaction in transactions {
+            if transaction.deleted
+                || !transaction.approved
+                || transaction.transfer_account_id.is_some()
+            {
+                continue;
+            }
+            // Imported transactions remember the payee the bank sent
+            let Some(bank_payee) = transaction
+                .import_payee_name_original
+                .as_ref()
+                .or(transaction.payee_name.as_ref())
+            else {
+                continue;
+            };
+            let key = payee_key(bank_payee);
+            if key.is_empty() {
+                continue;
+            }
+
+            // Uncategorised transactions come with a category name but no id
+            if let (Some(_), Some(category)) = (
+                &transaction.category_id,
+                transaction
+                    .category_name
+                    .as_ref()
+                    .filter(|name| name.as_str() != SPLIT_CATEGORY_NAME),
+            ) {
+                *history
+                    .categories
+                    .entry(key.clone())
+                    .or_default()
+                    .entry(category.clone())
+                    .or_default() += 1;
+            }
+            if let (Some(_), Some(payee)) = (
+                &transaction.import_payee_name_original,
+                &transaction.payee_name,
+            ) {
+                *history
+                    .payees
+                    .entry(key)
+                    .or_default()
+                    .entry(payee.clone())
+                    .or_default() += 1;
+            }
+        }
+
+        history
+    }
+
+    /// Category and payee name for a bank payee, if enough past transactions agree
+    pub fn suggest(&self, payee: &str, confidence: f64) -> Option<Suggestion> {
+        let key = payee_key(payee);
+        let confident = |learned: &Learned| {
+            learned.matches >= MIN_MATCHES && learned.confidence() >= confidence
+        };
+
+        let category = self
+            .categories
+            .get(&key)
+            .and_then(most_common)
+            .filter(confident);
+        let renamed = self
+            .payees
+            .get(&key)
+            .and_then(most_common)
+            .filter(confident)
+            .filter(|learned| !learned.value.eq_ignore_ascii_case(payee.trim()));
+
+        if category.is_none() && renamed.is_none() {
+            return None;
+        }
+        Some(Suggestion {
+            key,
+            category,
+            payee: renamed,
+        })
+    }
+}
+
+/// Fills in categories and payee names learned from YNAB history
+///
+/// Only transactions no rule categorised get a learned category, and only payees no rule
+/// renamed get a learned name. A category from the merchant category code fallback is
+/// replaced, since history is specific to the payee. Transfers are left alone. Returns the
+/// number of transactions changed.
+pub fn apply_history(
+    imports: &mut [ImportTransaction],
+    history: &CategoryHistory,
+    mode: LearnMode,
+    confidence: f64,
+) -> usize {
+    if mode == LearnMode::Off {
+        return 0;
+    }
+
+    let mut changed = 0;
+    for import in imports.iter_mut().filter(|i| i.transfer.is_none()) {
+        let Some(suggestion) = history.suggest(&import.transaction.payee, confidence) else {
+            continue;
+        };
+
+        let mut applied = false;
+        let uncategorised = (import.category.is_none() || import.merchant_category)
+            && import.subtransactions.is_empty();
+        if let (true, Some(category)) = (uncategorised, &suggestion.category) {
+            import.category = Some(category.value.clone());
+            import.review = mode == LearnMode::Suggest;
+            import.merchant_category = false;
+            applied = true;
+        }
+        if let (true, Some(payee)) = (
+            import.payee_name == import.transaction.payee,
+            &suggestion.payee,
+        ) {
+            import.payee_name = payee.value.clone();
+            applied = true;
+        }
+
+        if applied {
+            import.learned = Some(suggestion);
+            changed += 1;
+        }
+    }
+
+    changed
+}
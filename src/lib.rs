@@ -1,5 +1,23 @@
 pub mod account_config;
+pub mod alerts;
 pub mod auth_data;
+pub mod balance;
 pub mod config;
+pub mod exit_code;
+pub mod health;
+pub mod learn;
+pub mod logging;
+pub mod mcc;
+pub mod memo;
+pub mod metrics;
+pub mod notify;
+pub mod pending;
+pub mod profile;
+pub mod report;
+pub mod rules;
+pub mod server;
 pub mod sparebanken1;
+pub mod state;
+pub mod sync;
+pub mod transfers;
 pub mod ynab;
@@ -0,0 +1,242 @@
+use clap::ValueEnum;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use tracing_appender::rolling::{self, RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+/// LogError represents failures setting up logging
+#[derive(Debug, thiserror::Error)]
+pub enum LogError {
+    #[error("Invalid log file path: {0}")]
+    InvalidPath(String),
+
+    #[error("Failed to create log directory: {0}")]
+    IoError(#[from] io::Error),
+
+    #[error("Failed to open log file: {0}")]
+    FileError(#[from] rolling::InitError),
+
+    #[error("Failed to set up logging: {0}")]
+    InitError(#[from] tracing_subscriber::util::TryInitError),
+
+    #[cfg(feature = "otel")]
+    #[error("Failed to set up span export: {0}")]
+    ExportError(#[from] opentelemetry_otlp::ExporterBuildError),
+}
+
+/// Format of log lines
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line with the fields of the enclosing spans
+    Json,
+}
+
+/// How often the log file is rotated
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogRotation {
+    /// Start a new file every hour
+    Hourly,
+    /// Start a new file every day
+    #[default]
+    Daily,
+    /// Keep writing to a single file
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// Logging options shared by the binaries
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct LogOptions {
+    /// Format of log lines
+    #[arg(long, value_enum, default_value_t)]
+    pub log_format: LogFormat,
+
+    /// Also write logs to this file, rotated files get the date appended
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// How often the log file is rotated
+    #[arg(long, value_enum, default_value_t, requires = "log_file")]
+    pub log_rotation: LogRotation,
+
+    /// Number of rotated log files to keep, all of them when not set
+    #[arg(
+        long,
+        value_name = "COUNT",
+        requires = "log_file",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    pub log_max_files: Option<u16>,
+
+    /// Export spans to this OpenTelemetry collector over OTLP/HTTP, also enabled by
+    /// OTEL_EXPORTER_OTLP_ENDPOINT
+    #[cfg(feature = "otel")]
+    #[arg(long, value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+}
+
+/// Keeps span export running, exporting the remaining spans when dropped at exit
+#[must_use]
+#[derive(Debug, Default)]
+pub struct LogGuard {
+    #[cfg(feature = "otel")]
+    tracer_provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+#[cfg(feature = "otel")]
+impl Drop for LogGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take() {
+            // Logging is gone by now
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Failed to export spans: {}", e);
+            }
+        }
+    }
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Log level from RUST_LOG, info by default
+fn env_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
+/// Formatting layer writing to `writer` in the given format
+fn fmt_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.with_filter(env_filter()).boxed(),
+        // Closed spans are logged with their fields and timings, which text output leaves out
+        LogFormat::Json => layer
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_filter(env_filter())
+            .boxed(),
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use super::LogOptions;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+    use std::env;
+
+    pub const SERVICE_NAME: &str = "sparebank1-to-ynab";
+
+    /// Whether spans are exported, the OTLP exporter reads the endpoint variables itself
+    pub fn enabled(options: &LogOptions) -> bool {
+        options.otlp_endpoint.is_some()
+            || [
+                "OTEL_EXPORTER_OTLP_ENDPOINT",
+                "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+            ]
+            .iter()
+            .any(|name| env::var(name).is_ok_and(|value| !value.is_empty()))
+    }
+
+    /// Batches spans and sends them to the collector over OTLP/HTTP
+    pub fn tracer_provider(options: &LogOptions) -> Result<SdkTracerProvider, ExporterBuildError> {
+        let mut exporter = SpanExporter::builder().with_http();
+        if let Some(endpoint) = &options.otlp_endpoint {
+            // Like OTEL_EXPORTER_OTLP_ENDPOINT, the flag takes the collector's base URL
+            exporter =
+                exporter.with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')));
+        }
+        let service_name = env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| SERVICE_NAME.into());
+        let resource = Resource::builder()
+            .with_service_name(service_name)
+            .with_attribute(KeyValue::new("service.version", env!("CARGO_PKG_VERSION")))
+            .build();
+
+        Ok(SdkTracerProvider::builder()
+            .with_batch_exporter(exporter.build()?)
+            .with_resource(resource)
+            .build())
+    }
+}
+
+/// Opens the rolling log file
+fn log_file(options: &LogOptions, path: &Path) -> Result<RollingFileAppender, LogError> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| LogError::InvalidPath(path.display().to_string()))?;
+    let directory = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| ".".as_ref());
+    fs::create_dir_all(directory)?;
+
+    let mut builder = RollingFileAppender::builder()
+        .rotation(options.log_rotation.into())
+        .filename_prefix(file_name);
+    if let Some(max_files) = options.log_max_files {
+        builder = builder.max_log_files(max_files.into());
+    }
+    Ok(builder.build(directory)?)
+}
+
+/// Sets up logging to stdout, or stderr when stdout carries output for scripts, and the log file
+///
+/// With the `otel` feature, spans are also exported when a collector is configured. The
+/// returned guard must be held until exit.
+pub fn init(options: &LogOptions, to_stderr: bool) -> Result<LogGuard, LogError> {
+    #[allow(unused_mut)]
+    let mut guard = LogGuard::default();
+    let mut layers: Vec<BoxedLayer> = vec![if to_stderr {
+        fmt_layer(options.log_format, io::stderr, true)
+    } else {
+        fmt_layer(options.log_format, io::stdout, true)
+    }];
+    if let Some(path) = &options.log_file {
+        layers.push(fmt_layer(
+            options.log_format,
+            log_file(options, path)?,
+            false,
+        ));
+    }
+
+    #[cfg(feature = "otel")]
+    if otel::enabled(options) {
+        use opentelemetry::trace::TracerProvider;
+
+        let provider = otel::tracer_provider(options)?;
+        layers.push(
+            tracing_opentelemetry::layer()
+                .with_tracer(provider.tracer(otel::SERVICE_NAME))
+                .with_filter(env_filter())
+                .boxed(),
+        );
+        guard.tracer_provider = Some(provider);
+    }
+
+    tracing_subscriber::registry().with(layers).try_init()?;
+    Ok(guard)
+}
@@ -0,0 +1,81 @@
+use serde::Deserialize;
+use std::fmt;
+
+/// Broad spending group a merchant category code (MCC) belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MerchantGroup {
+    Groceries,
+    Fuel,
+    Restaurants,
+    Transport,
+    Travel,
+    Health,
+    Shopping,
+    Entertainment,
+    Utilities,
+}
+
+impl fmt::Display for MerchantGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MerchantGroup::Groceries => "groceries",
+            MerchantGroup::Fuel => "fuel",
+            MerchantGroup::Restaurants => "restaurants",
+            MerchantGroup::Transport => "transport",
+            MerchantGroup::Travel => "travel",
+            MerchantGroup::Health => "health",
+            MerchantGroup::Shopping => "shopping",
+            MerchantGroup::Entertainment => "entertainment",
+            MerchantGroup::Utilities => "utilities",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Built-in table of merchant category codes, as inclusive ranges
+const MCC_GROUPS: &[(u16, u16, MerchantGroup)] = &[
+    // Airlines, car rental and hotels with their own codes
+    (3000, 3999, MerchantGroup::Travel),
+    (4011, 4011, MerchantGroup::Transport),
+    (4111, 4131, MerchantGroup::Transport),
+    (4411, 4411, MerchantGroup::Travel),
+    (4457, 4468, MerchantGroup::Transport),
+    (4511, 4511, MerchantGroup::Travel),
+    (4582, 4582, MerchantGroup::Travel),
+    (4722, 4723, MerchantGroup::Travel),
+    (4784, 4789, MerchantGroup::Transport),
+    (4812, 4816, MerchantGroup::Utilities),
+    (4899, 4900, MerchantGroup::Utilities),
+    (5200, 5200, MerchantGroup::Shopping),
+    (5251, 5251, MerchantGroup::Shopping),
+    (5261, 5261, MerchantGroup::Shopping),
+    (5309, 5399, MerchantGroup::Shopping),
+    (5411, 5411, MerchantGroup::Groceries),
+    (5422, 5499, MerchantGroup::Groceries),
+    (5541, 5542, MerchantGroup::Fuel),
+    (5611, 5699, MerchantGroup::Shopping),
+    (5712, 5735, MerchantGroup::Shopping),
+    (5812, 5814, MerchantGroup::Restaurants),
+    (5815, 5818, MerchantGroup::Entertainment),
+    (5912, 5912, MerchantGroup::Health),
+    (5940, 5949, MerchantGroup::Shopping),
+    (5977, 5977, MerchantGroup::Shopping),
+    (5983, 5983, MerchantGroup::Fuel),
+    (7011, 7012, MerchantGroup::Travel),
+    (7512, 7512, MerchantGroup::Travel),
+    (7523, 7523, MerchantGroup::Transport),
+    (7832, 7841, MerchantGroup::Entertainment),
+    (7922, 7922, MerchantGroup::Entertainment),
+    (7929, 7999, MerchantGroup::Entertainment),
+    (8011, 8099, MerchantGroup::Health),
+];
+
+/// Group of a merchant category code, None for codes not in the built-in table
+pub fn merchant_group(code: &str) -> Option<MerchantGroup> {
+    let code: u16 = code.trim().parse().ok()?;
+    MCC_GROUPS
+        .iter()
+        .find(|(from, to, _)| (*from..=*to).contains(&code))
+        .map(|(_, _, group)| *group)
+}
@@ -0,0 +1,210 @@
+use crate::sparebanken1::Transaction;
+use chrono::{DateTime, Utc};
+use chrono_tz::Europe::Oslo;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Longest memo YNAB accepts, in characters
+pub const MAX_MEMO_LENGTH: usize = 200;
+
+/// MemoTemplateError is returned for templates with unknown or unclosed placeholders
+#[derive(Debug, thiserror::Error)]
+pub enum MemoTemplateError {
+    #[error("Unknown memo placeholder: {{{0}}}")]
+    UnknownPlaceholder(String),
+
+    #[error("Unclosed placeholder in memo template: {0}")]
+    UnclosedPlaceholder(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Placeholder {
+    Description,
+    CleanedDescription,
+    AccountName,
+    OriginalAmount,
+    OriginalCurrency,
+    ExchangeRate,
+    BookingDate,
+    InterestDate,
+    Id,
+    Reference,
+    TypeText,
+    MerchantCategoryCode,
+    CounterpartyAccount,
+    CounterpartyName,
+}
+
+impl FromStr for Placeholder {
+    type Err = MemoTemplateError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "description" => Ok(Placeholder::Description),
+            "cleaned_description" => Ok(Placeholder::CleanedDescription),
+            "account_name" => Ok(Placeholder::AccountName),
+            "original_amount" => Ok(Placeholder::OriginalAmount),
+            "original_currency" => Ok(Placeholder::OriginalCurrency),
+            "exchange_rate" => Ok(Placeholder::ExchangeRate),
+            "booking_date" => Ok(Placeholder::BookingDate),
+            "interest_date" => Ok(Placeholder::InterestDate),
+            "id" => Ok(Placeholder::Id),
+            "reference" => Ok(Placeholder::Reference),
+            "type" => Ok(Placeholder::TypeText),
+            "mcc" => Ok(Placeholder::MerchantCategoryCode),
+            "counterparty_account" => Ok(Placeholder::CounterpartyAccount),
+            "counterparty_name" => Ok(Placeholder::CounterpartyName),
+            _ => Err(MemoTemplateError::UnknownPlaceholder(name.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Placeholder),
+}
+
+/// MemoTemplate builds the YNAB memo from SpareBank1 transaction fields
+///
+/// Placeholders are written in braces, e.g. `{cleaned_description} ({original_amount})`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct MemoTemplate {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl FromStr for MemoTemplate {
+    type Err = MemoTemplateError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| MemoTemplateError::UnclosedPlaceholder(source.to_string()))?;
+            let name = rest[start + 1..start + end].trim();
+            segments.push(Segment::Field(name.parse()?));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+}
+
+impl TryFrom<String> for MemoTemplate {
+    type Error = MemoTemplateError;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        source.parse()
+    }
+}
+
+impl From<MemoTemplate> for String {
+    fn from(template: MemoTemplate) -> Self {
+        template.source
+    }
+}
+
+impl fmt::Display for MemoTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn format_date(date: &DateTime<Utc>) -> String {
+    date.with_timezone(&Oslo).format("%Y-%m-%d").to_string()
+}
+
+impl MemoTemplate {
+    /// Renders the memo for a transaction, collapsing whitespace and truncating to YNAB's limit
+    ///
+    /// Fields the bank did not provide render as empty text.
+    pub fn render(&self, transaction: &Transaction) -> String {
+        let memo: String = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.clone(),
+                Segment::Field(Placeholder::Description) => transaction.description.clone(),
+                Segment::Field(Placeholder::CleanedDescription) => transaction.payee.clone(),
+                Segment::Field(Placeholder::AccountName) => {
+                    transaction.account_name.clone().unwrap_or_default()
+                }
+                Segment::Field(Placeholder::OriginalAmount) => {
+                    match (&transaction.original_amount, &transaction.original_currency) {
+                        (Some(amount), Some(currency)) => format!("{:.2} {}", amount, currency),
+                        _ => String::new(),
+                    }
+                }
+                Segment::Field(Placeholder::OriginalCurrency) => {
+                    if transaction.is_foreign() {
+                        transaction.currency().to_string()
+                    } else {
+                        String::new()
+                    }
+                }
+                Segment::Field(Placeholder::ExchangeRate) => transaction
+                    .foreign_exchange_rate()
+                    .map(|rate| format!("{:.4}", rate))
+                    .unwrap_or_default(),
+                Segment::Field(Placeholder::BookingDate) => format_date(&transaction.date),
+                Segment::Field(Placeholder::InterestDate) => transaction
+                    .interest_date
+                    .as_ref()
+                    .map(format_date)
+                    .unwrap_or_default(),
+                Segment::Field(Placeholder::Id) => transaction.id.clone(),
+                Segment::Field(Placeholder::Reference) => {
+                    transaction.reference.clone().unwrap_or_default()
+                }
+                Segment::Field(Placeholder::TypeText) => {
+                    transaction.type_text.clone().unwrap_or_default()
+                }
+                Segment::Field(Placeholder::MerchantCategoryCode) => transaction
+                    .merchant_category_code
+                    .clone()
+                    .unwrap_or_default(),
+                Segment::Field(Placeholder::CounterpartyAccount) => {
+                    transaction.counterparty_account.clone().unwrap_or_default()
+                }
+                Segment::Field(Placeholder::CounterpartyName) => {
+                    transaction.counterparty_name.clone().unwrap_or_default()
+                }
+            })
+            .collect();
+
+        truncate_memo(&memo.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+/// Memo for a transaction, the raw description when no template is configured
+pub fn render_memo(template: Option<&MemoTemplate>, transaction: &Transaction) -> String {
+    match template {
+        Some(template) => template.render(transaction),
+        None => truncate_memo(&transaction.description),
+    }
+}
+
+/// Shortens a memo to YNAB's limit on a character boundary, marking the cut with an ellipsis
+pub fn truncate_memo(memo: &str) -> String {
+    if memo.chars().count() <= MAX_MEMO_LENGTH {
+        return memo.to_string();
+    }
+    let mut truncated: String = memo.chars().take(MAX_MEMO_LENGTH - 1).collect();
+    truncated.push('…');
+    truncated
+}
@@ -0,0 +1,322 @@
+use crate::sync::{SyncError, SyncSummary};
+use chrono::Utc;
+use reqwest::{Request, RequestBuilder, Response};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime};
+use tracing::field::Empty;
+use tracing::{info_span, Instrument, Span};
+
+/// External API a request was sent to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Api {
+    Sparebank1,
+    Ynab,
+}
+
+impl Api {
+    fn label(self) -> &'static str {
+        match self {
+            Api::Sparebank1 => "sparebank1",
+            Api::Ynab => "ynab",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct AccountCounters {
+    fetched: u64,
+    imported: u64,
+    duplicates: u64,
+}
+
+/// Name, help text and value of a per-account counter
+type AccountMetric = (&'static str, &'static str, fn(&AccountCounters) -> u64);
+
+#[derive(Debug, Default)]
+struct RequestCounters {
+    requests: u64,
+    errors: u64,
+    duration_seconds: f64,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    /// Keyed by profile and SpareBank1 account
+    accounts: BTreeMap<(String, String), AccountCounters>,
+    /// Keyed by profile and outcome
+    syncs: BTreeMap<(String, &'static str), u64>,
+    /// Unix time of the last successful sync per profile
+    last_success: BTreeMap<String, i64>,
+    /// Refresh token file per profile, its age is read when metrics are scraped
+    token_files: BTreeMap<String, String>,
+    requests: BTreeMap<Api, RequestCounters>,
+}
+
+/// Counters and gauges exposed in Prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+}
+
+/// Metrics shared by the whole process
+pub fn global() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+/// Sends requests while recording their latency and errors
+pub trait RecordedSend {
+    fn send_recorded(
+        self,
+        api: Api,
+    ) -> impl std::future::Future<Output = Result<Response, reqwest::Error>> + Send;
+}
+
+impl RecordedSend for RequestBuilder {
+    async fn send_recorded(self, api: Api) -> Result<Response, reqwest::Error> {
+        let (client, request) = self.build_split();
+        let request = request?;
+        let span = request_span(api, &request);
+        let started = Instant::now();
+        let result = client.execute(request).instrument(span.clone()).await;
+        let failed = match &result {
+            Ok(response) => {
+                let status = response.status();
+                span.record("http.response.status_code", status.as_u16());
+                let failed = status.is_client_error() || status.is_server_error();
+                if failed {
+                    span.record("error.type", status.as_str());
+                }
+                failed
+            }
+            Err(e) => {
+                span.record(
+                    "error.type",
+                    if e.is_timeout() { "timeout" } else { "request" },
+                );
+                true
+            }
+        };
+        if failed {
+            span.record("otel.status_code", "ERROR");
+        }
+        global().record_request(api, started.elapsed().as_secs_f64(), failed);
+        result
+    }
+}
+
+/// Client span of a request, named and attributed after the OpenTelemetry HTTP conventions
+///
+/// The query string is left out of the URL, as it can carry tokens.
+fn request_span(api: Api, request: &Request) -> Span {
+    let url = request.url();
+    let mut full_url = url.clone();
+    full_url.set_query(None);
+    info_span!(
+        "http_request",
+        otel.name = %format_args!("{} {}", request.method(), api.label()),
+        otel.kind = "client",
+        otel.status_code = Empty,
+        api = api.label(),
+        http.request.method = %request.method(),
+        url.full = %full_url,
+        server.address = url.host_str().unwrap_or_default(),
+        http.response.status_code = Empty,
+        error.type = Empty,
+    )
+}
+
+/// Escapes a Prometheus label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    fn registry(&self) -> std::sync::MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Remembers the refresh token file of a profile for the token age gauge
+    pub fn register_profile(&self, profile: &str, refresh_token_file_path: &str) {
+        self.registry()
+            .token_files
+            .insert(profile.to_string(), refresh_token_file_path.to_string());
+    }
+
+    pub fn record_request(&self, api: Api, duration_seconds: f64, failed: bool) {
+        let mut registry = self.registry();
+        let counters = registry.requests.entry(api).or_default();
+        counters.requests += 1;
+        counters.duration_seconds += duration_seconds;
+        if failed {
+            counters.errors += 1;
+        }
+    }
+
+    /// Records the outcome of syncing a profile
+    pub fn record_sync(&self, profile: &str, result: &Result<SyncSummary, SyncError>) {
+        let mut registry = self.registry();
+        let outcome = if result.is_ok() { "ok" } else { "failed" };
+        *registry
+            .syncs
+            .entry((profile.to_string(), outcome))
+            .or_default() += 1;
+
+        if result.is_ok() {
+            registry
+                .last_success
+                .insert(profile.to_string(), Utc::now().timestamp());
+        }
+        let Some(summary) = SyncSummary::of(result) else {
+            return;
+        };
+        for account in &summary.accounts {
+            let counters = registry
+                .accounts
+                .entry((profile.to_string(), account.account.clone()))
+                .or_default();
+            counters.fetched += account.fetched as u64;
+            counters.imported += account.imported as u64;
+            counters.duplicates += account.duplicates as u64;
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let registry = self.registry();
+        let mut out = String::new();
+
+        let account_counters: [AccountMetric; 3] = [
+            (
+                "transactions_fetched_total",
+                "Transactions fetched from SpareBank1",
+                |c| c.fetched,
+            ),
+            (
+                "transactions_imported_total",
+                "Transactions created in YNAB, once per target account",
+                |c| c.imported,
+            ),
+            (
+                "transactions_duplicate_total",
+                "Transactions YNAB skipped as already imported",
+                |c| c.duplicates,
+            ),
+        ];
+        for (name, help, value) in account_counters {
+            let _ = writeln!(out, "# HELP sparebank1_ynab_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE sparebank1_ynab_{} counter", name);
+            for ((profile, account), counters) in &registry.accounts {
+                let _ = writeln!(
+                    out,
+                    "sparebank1_ynab_{}{{profile=\"{}\",account=\"{}\"}} {}",
+                    name,
+                    escape(profile),
+                    escape(account),
+                    value(counters)
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP sparebank1_ynab_syncs_total Profile syncs by outcome"
+        );
+        let _ = writeln!(out, "# TYPE sparebank1_ynab_syncs_total counter");
+        for ((profile, outcome), count) in &registry.syncs {
+            let _ = writeln!(
+                out,
+                "sparebank1_ynab_syncs_total{{profile=\"{}\",outcome=\"{}\"}} {}",
+                escape(profile),
+                outcome,
+                count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP sparebank1_ynab_last_success_timestamp_seconds Unix time of the last successful sync"
+        );
+        let _ = writeln!(
+            out,
+            "# TYPE sparebank1_ynab_last_success_timestamp_seconds gauge"
+        );
+        for (profile, timestamp) in &registry.last_success {
+            let _ = writeln!(
+                out,
+                "sparebank1_ynab_last_success_timestamp_seconds{{profile=\"{}\"}} {}",
+                escape(profile),
+                timestamp
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP sparebank1_ynab_refresh_token_age_seconds Seconds since the refresh token file was written"
+        );
+        let _ = writeln!(
+            out,
+            "# TYPE sparebank1_ynab_refresh_token_age_seconds gauge"
+        );
+        for (profile, path) in &registry.token_files {
+            let Some(age) = fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            else {
+                continue;
+            };
+            let _ = writeln!(
+                out,
+                "sparebank1_ynab_refresh_token_age_seconds{{profile=\"{}\"}} {}",
+                escape(profile),
+                age.as_secs()
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP sparebank1_ynab_api_request_duration_seconds Latency of API requests"
+        );
+        let _ = writeln!(
+            out,
+            "# TYPE sparebank1_ynab_api_request_duration_seconds summary"
+        );
+        for (api, counters) in &registry.requests {
+            let _ = writeln!(
+                out,
+                "sparebank1_ynab_api_request_duration_seconds_sum{{api=\"{}\"}} {}",
+                api.label(),
+                counters.duration_seconds
+            );
+            let _ = writeln!(
+                out,
+                "sparebank1_ynab_api_request_duration_seconds_count{{api=\"{}\"}} {}",
+                api.label(),
+                counters.requests
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP sparebank1_ynab_api_errors_total API requests that failed or returned an error status"
+        );
+        let _ = writeln!(out, "# TYPE sparebank1_ynab_api_errors_total counter");
+        for (api, counters) in &registry.requests {
+            let _ = writeln!(
+                out,
+                "sparebank1_ynab_api_errors_total{{api=\"{}\"}} {}",
+                api.label(),
+                counters.errors
+            );
+        }
+
+        out
+    }
+}
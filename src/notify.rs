@@ -0,0 +1,522 @@
+use crate::alerts::Alert;
+use crate::auth_data::AuthError;
+use crate::config::Config;
+use crate::exit_code::ExitReason;
+use crate::state;
+use crate::sync::{SyncError, SyncSummary};
+use chrono::{DateTime, NaiveDate, Timelike};
+use chrono_tz::Tz;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind};
+use tracing::{debug, error, info};
+
+/// NotifyError represents failures loading notification settings or sending notifications
+#[derive(Debug, thiserror::Error)]
+pub enum NotifyError {
+    #[error("Failed to read notifications file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Failed to parse notifications file: {0}")]
+    ParseError(#[from] serde_json::Error),
+
+    #[error("Invalid notification settings: {0}")]
+    ValidationError(String),
+
+    #[error("Failed to send notification: {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("Invalid email address: {0}")]
+    AddressError(#[from] lettre::address::AddressError),
+
+    #[error("Failed to build email: {0}")]
+    EmailError(#[from] lettre::error::Error),
+
+    #[error("Failed to send email: {0}")]
+    SmtpError(#[from] lettre::transport::smtp::Error),
+}
+
+/// Kind of event a channel can be notified about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Failure,
+    ReauthRequired,
+    Alert,
+    DailySummary,
+}
+
+fn default_events() -> Vec<NotificationKind> {
+    vec![
+        NotificationKind::Failure,
+        NotificationKind::ReauthRequired,
+        NotificationKind::Alert,
+    ]
+}
+
+/// How the connection to the SMTP server is secured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    #[default]
+    Starttls,
+    Tls,
+    None,
+}
+
+/// Where notifications are sent
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChannelKind {
+    /// Notification posted as JSON
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+    },
+    /// ntfy-style push: plain text body with title, priority and tags headers
+    Ntfy { url: String, token: Option<String> },
+    Email {
+        host: String,
+        port: Option<u16>,
+        #[serde(default)]
+        security: SmtpSecurity,
+        username: Option<String>,
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+    },
+}
+
+/// A notification channel and the events it receives
+#[derive(Debug, Clone, Deserialize)]
+pub struct Channel {
+    #[serde(flatten)]
+    pub kind: ChannelKind,
+    /// Events sent to the channel, all but the daily summary by default
+    #[serde(default = "default_events")]
+    pub events: Vec<NotificationKind>,
+}
+
+/// Notification settings as written in the notifications configuration file
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct NotificationConfig {
+    /// Hour of the day (Oslo time) the daily summary is sent
+    pub daily_summary_hour: Option<u32>,
+    /// File the notifier state is kept in between runs, needed for one-shot runs
+    pub state_path: Option<String>,
+    pub channels: Vec<Channel>,
+}
+
+impl NotificationConfig {
+    pub fn validate(&self) -> Result<(), NotifyError> {
+        if self.daily_summary_hour.is_some_and(|hour| hour > 23) {
+            return Err(NotifyError::ValidationError(
+                "daily_summary_hour must be between 0 and 23".to_string(),
+            ));
+        }
+        for channel in &self.channels {
+            match &channel.kind {
+                ChannelKind::Webhook { url, .. } | ChannelKind::Ntfy { url, .. } => {
+                    reqwest::Url::parse(url).map_err(|e| {
+                        NotifyError::ValidationError(format!("invalid url {}: {}", url, e))
+                    })?;
+                }
+                ChannelKind::Email { from, to, .. } => {
+                    if to.is_empty() {
+                        return Err(NotifyError::ValidationError(
+                            "email channel needs at least one recipient".to_string(),
+                        ));
+                    }
+                    for address in to.iter().chain([from]) {
+                        address.parse::<Mailbox>().map_err(|e| {
+                            NotifyError::ValidationError(format!(
+                                "invalid email address {}: {}",
+                                address, e
+                            ))
+                        })?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads notification settings from a JSON file
+pub fn read_notifications_json(path: &str) -> Result<NotificationConfig, NotifyError> {
+    debug!("Reading notification configuration from: {}", path);
+    let file = File::open(path)?;
+    let config: NotificationConfig = serde_json::from_reader(BufReader::new(file))?;
+    config.validate()?;
+    info!(
+        "Loaded {} notification channels from configuration",
+        config.channels.len()
+    );
+    Ok(config)
+}
+
+/// Loads notification settings from NOTIFICATIONS_CONFIG_PATH, none when it is not set
+pub fn load_notifications() -> Result<NotificationConfig, NotifyError> {
+    Config::load_dotenv();
+
+    match env::var("NOTIFICATIONS_CONFIG_PATH") {
+        Ok(path) => read_notifications_json(&path),
+        Err(_) => Ok(NotificationConfig::default()),
+    }
+}
+
+/// Stands in for the profile in notifications of configuration errors found before any profile
+/// could be loaded
+pub const ALL_PROFILES: &str = "all profiles";
+
+/// Sync counts of a profile since the last daily summary
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DailyCounts {
+    pub profile: String,
+    pub syncs: usize,
+    pub failed: usize,
+    pub fetched: usize,
+    pub imported: usize,
+    pub alerts: usize,
+}
+
+/// A notification, posted to webhooks as JSON tagged with its event
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    Failure {
+        profile: String,
+        error: String,
+        exit_code: u8,
+    },
+    ReauthRequired {
+        profile: String,
+        error: String,
+    },
+    Alert {
+        profile: String,
+        alerts: Vec<Alert>,
+    },
+    DailySummary {
+        date: NaiveDate,
+        profiles: Vec<DailyCounts>,
+    },
+}
+
+impl Notification {
+    /// Notification of the alerts raised while syncing a profile, none without alerts
+    pub fn for_alerts(profile: &str, alerts: &[Alert]) -> Option<Self> {
+        (!alerts.is_empty()).then(|| Notification::Alert {
+            profile: profile.to_string(),
+            alerts: alerts.to_vec(),
+        })
+    }
+
+    pub fn kind(&self) -> NotificationKind {
+        match self {
+            Notification::Failure { .. } => NotificationKind::Failure,
+            Notification::ReauthRequired { .. } => NotificationKind::ReauthRequired,
+            Notification::Alert { .. } => NotificationKind::Alert,
+            Notification::DailySummary { .. } => NotificationKind::DailySummary,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match self {
+            Notification::Failure { profile, .. } => {
+                format!("SpareBank1 to YNAB sync failed for {}", profile)
+            }
+            Notification::ReauthRequired { profile, .. } => {
+                format!("SpareBank1 login expired for {}", profile)
+            }
+            Notification::Alert { profile, alerts } => {
+                format!("{} transactions to check for {}", alerts.len(), profile)
+            }
+            Notification::DailySummary { date, .. } => {
+                format!("SpareBank1 to YNAB summary for {}", date)
+            }
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Notification::Failure {
+                error, exit_code, ..
+            } => format!("{} (exit code {})", error, exit_code),
+            // The error already says to run the setup again
+            Notification::ReauthRequired { error, .. } => error.clone(),
+            Notification::Alert { alerts, .. } => alerts
+                .iter()
+                .map(Alert::explain)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Notification::DailySummary { profiles, .. } => profiles
+                .iter()
+                .map(|p| {
+                    let alerts = match p.alerts {
+                        0 => String::new(),
+                        alerts => format!(", {} alerts", alerts),
+                    };
+                    format!(
+                        "{}: imported {} of {} fetched transactions, {} of {} syncs failed{}",
+                        p.profile, p.imported, p.fetched, p.failed, p.syncs, alerts
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+/// What the notifier remembers between syncs, written to the state file when one is configured
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct NotifierState {
+    #[serde(default)]
+    failing: HashMap<String, NotificationKind>,
+    #[serde(default)]
+    counts: BTreeMap<String, DailyCounts>,
+    summary_sent_on: Option<NaiveDate>,
+}
+
+/// Reads the notifier state, none if the file does not exist yet
+fn read_notifier_state(path: &str) -> Result<Option<NotifierState>, NotifyError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Some(serde_json::from_reader(BufReader::new(file))?))
+}
+
+/// Writes the notifier state, an interrupted write leaves the old state
+fn write_notifier_state(path: &str, state: &NotifierState) -> Result<(), NotifyError> {
+    state::write_atomic(path, &serde_json::to_string_pretty(state)?)?;
+    Ok(())
+}
+
+/// Decides which notifications to send and sends them to the configured channels
+///
+/// A profile that keeps failing the same way is only notified about once, until it syncs again.
+/// Without a state file this is only remembered for the lifetime of the process.
+#[derive(Debug)]
+pub struct Notifier {
+    config: NotificationConfig,
+    state: NotifierState,
+}
+
+impl Notifier {
+    /// Picks up the state file if there is one, otherwise the first daily summary is sent at
+    /// the configured hour after `now`
+    pub fn new(config: NotificationConfig, now: DateTime<Tz>) -> Self {
+        let saved = match config.state_path.as_deref().map(read_notifier_state) {
+            Some(Ok(saved)) => saved,
+            Some(Err(e)) => {
+                error!("Failed to read notifier state, starting over: {}", e);
+                None
+            }
+            None => None,
+        };
+        let state = saved.unwrap_or_else(|| NotifierState {
+            summary_sent_on: config
+                .daily_summary_hour
+                .filter(|hour| now.hour() >= *hour)
+                .map(|_| now.date_naive()),
+            ..Default::default()
+        });
+        Self { config, state }
+    }
+
+    /// Writes the state file, failures are only logged
+    fn save(&self) {
+        let Some(path) = &self.config.state_path else {
+            return;
+        };
+        match write_notifier_state(path, &self.state) {
+            Ok(()) => debug!("Saved notifier state to {}", path),
+            Err(e) => error!("Failed to save notifier state to {}: {}", path, e),
+        }
+    }
+
+    /// Records the outcome of syncing a profile, returning the notification it calls for
+    pub fn record_sync(
+        &mut self,
+        profile: &str,
+        result: &Result<SyncSummary, SyncError>,
+    ) -> Option<Notification> {
+        let notification = self.notification_for(profile, result);
+        self.save();
+        notification
+    }
+
+    fn notification_for(
+        &mut self,
+        profile: &str,
+        result: &Result<SyncSummary, SyncError>,
+    ) -> Option<Notification> {
+        let counts = self
+            .state
+            .counts
+            .entry(profile.to_string())
+            .or_insert_with(|| DailyCounts {
+                profile: profile.to_string(),
+                ..Default::default()
+            });
+        counts.syncs += 1;
+
+        if let Some(summary) = SyncSummary::of(result) {
+            counts.fetched += summary.fetched;
+            counts.imported += summary.imported;
+            counts.alerts += summary.alerts.len();
+        }
+        // The profiles loaded, so an earlier configuration error is fixed
+        self.state.failing.remove(ALL_PROFILES);
+        let Err(error) = result else {
+            self.state.failing.remove(profile);
+            return None;
+        };
+        counts.failed += 1;
+
+        let notification = match error {
+            SyncError::AuthError(AuthError::TokenRejected(_)) => Notification::ReauthRequired {
+                profile: profile.to_string(),
+                error: error.to_string(),
+            },
+            _ => Notification::Failure {
+                profile: profile.to_string(),
+                error: error.to_string(),
+                exit_code: ExitReason::from(error).code(),
+            },
+        };
+        self.unless_still_failing(profile, notification)
+    }
+
+    /// Records a configuration error that kept the profiles from being loaded at all
+    pub fn record_config_error(&mut self, error: &str) -> Option<Notification> {
+        let notification = Notification::Failure {
+            profile: ALL_PROFILES.to_string(),
+            error: error.to_string(),
+            exit_code: ExitReason::Config.code(),
+        };
+        let notification = self.unless_still_failing(ALL_PROFILES, notification);
+        self.save();
+        notification
+    }
+
+    /// The notification, unless the profile already failed the same way last time
+    fn unless_still_failing(
+        &mut self,
+        profile: &str,
+        notification: Notification,
+    ) -> Option<Notification> {
+        let kind = notification.kind();
+        if self.state.failing.insert(profile.to_string(), kind) == Some(kind) {
+            debug!("Profile {} is still failing, not notifying again", profile);
+            return None;
+        }
+        Some(notification)
+    }
+
+    /// The daily summary, once the configured hour has passed on a day it was not sent yet
+    pub fn daily_summary_due(&mut self, now: DateTime<Tz>) -> Option<Notification> {
+        let hour = self.config.daily_summary_hour?;
+        let today = now.date_naive();
+        if now.hour() < hour || self.state.summary_sent_on == Some(today) {
+            return None;
+        }
+        self.state.summary_sent_on = Some(today);
+        let profiles = std::mem::take(&mut self.state.counts)
+            .into_values()
+            .collect();
+        self.save();
+        Some(Notification::DailySummary {
+            date: today,
+            profiles,
+        })
+    }
+
+    /// Sends a notification to every channel subscribed to it, failures are only logged
+    pub async fn send(&self, notification: &Notification) {
+        for channel in &self.config.channels {
+            if !channel.events.contains(&notification.kind()) {
+                continue;
+            }
+            match send_to_channel(&channel.kind, notification).await {
+                Ok(()) => info!("Sent notification: {}", notification.title()),
+                Err(e) => error!("{}", e),
+            }
+        }
+    }
+}
+
+async fn send_to_channel(
+    channel: &ChannelKind,
+    notification: &Notification,
+) -> Result<(), NotifyError> {
+    match channel {
+        ChannelKind::Webhook { url, headers } => {
+            let mut request = reqwest::Client::new().post(url).json(notification);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            request.send().await?.error_for_status()?;
+        }
+        ChannelKind::Ntfy { url, token } => {
+            let (priority, tags) = match notification.kind() {
+                NotificationKind::Failure => ("high", "warning"),
+                NotificationKind::ReauthRequired => ("urgent", "key"),
+                NotificationKind::Alert => ("high", "mag"),
+                NotificationKind::DailySummary => ("default", "bank"),
+            };
+            let mut request = reqwest::Client::new()
+                .post(url)
+                .header("Title", notification.title())
+                .header("Priority", priority)
+                .header("Tags", tags)
+                .body(notification.message());
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            request.send().await?.error_for_status()?;
+        }
+        ChannelKind::Email {
+            host,
+            port,
+            security,
+            username,
+            password,
+            from,
+            to,
+        } => {
+            let mut builder = Message::builder()
+                .from(from.parse()?)
+                .subject(notification.title());
+            for address in to {
+                builder = builder.to(address.parse()?);
+            }
+            let email = builder.body(notification.message())?;
+
+            let mut transport = match security {
+                SmtpSecurity::Starttls => {
+                    AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+                }
+                SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+                SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            };
+            if let Some(port) = port {
+                transport = transport.port(*port);
+            }
+            if let (Some(username), Some(password)) = (username, password) {
+                transport =
+                    transport.credentials(Credentials::new(username.clone(), password.clone()));
+            }
+            transport.build().send(email).await?;
+        }
+    }
+    Ok(())
+}
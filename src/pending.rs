@@ -0,0 +1,194 @@
+use crate::ynab::{self, ImportTransaction, TransactionDetail, UpdateYnabTransaction};
+use chrono::{NaiveDate, TimeDelta};
+use chrono_tz::Europe::Oslo;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+/// Flag color set on pending transactions that disappeared from the bank
+pub const VANISHED_FLAG_COLOR: &str = "red";
+
+/// How far before a pending transaction's date the booked one may be dated
+const BOOKED_DAYS_BEFORE: i64 = 1;
+
+/// How far after a pending transaction's date the booked one may be dated
+const BOOKED_DAYS_AFTER: i64 = 7;
+
+/// What to do with a pending transaction in YNAB that is no longer reported by the bank
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VanishedPending {
+    /// Leave it in YNAB with a red flag for review
+    #[default]
+    Flag,
+    /// Delete it from YNAB
+    Delete,
+}
+
+impl FromStr for VanishedPending {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "flag" => Ok(VanishedPending::Flag),
+            "delete" => Ok(VanishedPending::Delete),
+            _ => Err(format!("expected 'flag' or 'delete', got '{}'", value)),
+        }
+    }
+}
+
+/// An import posted to a YNAB account, as seen when settling pending transactions
+#[derive(Debug, Clone, Copy)]
+pub struct PostedImport<'a> {
+    /// Index of the import in the list being synced
+    pub index: usize,
+    pub import: &'a ImportTransaction,
+    /// YNAB account the import is posted to
+    pub account_id: &'a str,
+}
+
+/// Changes needed in one budget to bring pending transactions up to date
+#[derive(Debug, Default, PartialEq)]
+pub struct PendingPlan {
+    /// Settled transactions marked cleared, and vanished ones flagged
+    pub updates: Vec<UpdateYnabTransaction>,
+    /// Vanished transactions to delete
+    pub deletes: Vec<String>,
+    /// Booked imports that settled a pending transaction, with the YNAB account they settled in
+    pub settled: Vec<(usize, String)>,
+    /// Number of pending transactions newly found to have vanished
+    pub vanished: usize,
+}
+
+impl PendingPlan {
+    /// Number of pending transactions marked cleared by this plan
+    pub fn cleared(&self) -> usize {
+        self.updates.iter().filter(|u| u.cleared.is_some()).count()
+    }
+}
+
+/// Outcome of settling pending transactions for a profile
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PendingSummary {
+    pub settled: usize,
+    pub vanished: usize,
+}
+
+fn is_pending_import_id(import_id: Option<&str>) -> bool {
+    import_id.is_some_and(|id| id.starts_with(&format!("{}:", ynab::PENDING_IMPORT_PREFIX)))
+}
+
+/// Matches pending transactions already in YNAB against what the bank reports now
+///
+/// A pending transaction that the bank still reports as pending is left alone. One that has
+/// been booked is matched to the booked transaction on the same account with the same amount,
+/// or the same payee if the amount changed, dated shortly after it; it is marked cleared with
+/// the booked amount and date, and the booked import is not posted again. Booked imports already
+/// in YNAB are never matched. Pending transactions with no match that are dated on or after
+/// `window_start` have vanished.
+pub fn plan_pending(
+    ynab_transactions: &[TransactionDetail],
+    posted: &[PostedImport],
+    window_start: NaiveDate,
+    vanished_pending: VanishedPending,
+) -> PendingPlan {
+    let mut plan = PendingPlan::default();
+
+    let still_pending: HashSet<(&str, &str)> = posted
+        .iter()
+        .filter(|p| p.import.transaction.is_pending())
+        .map(|p| (p.account_id, p.import.import_id.as_str()))
+        .collect();
+    // Booked transactions imported normally on an earlier run cannot settle anything
+    let imported: HashSet<(&str, &str)> = ynab_transactions
+        .iter()
+        .filter_map(|t| Some((t.account_id.as_str(), t.import_id.as_deref()?)))
+        .collect();
+    let mut matched: HashSet<usize> = HashSet::new();
+
+    let mut pending: Vec<&TransactionDetail> = ynab_transactions
+        .iter()
+        .filter(|t| !t.deleted && is_pending_import_id(t.import_id.as_deref()))
+        .collect();
+    pending.sort_by_key(|t| t.date);
+
+    for transaction in pending {
+        let import_id = transaction.import_id.as_deref().unwrap_or_default();
+        if still_pending.contains(&(transaction.account_id.as_str(), import_id)) {
+            continue;
+        }
+
+        let booked = posted
+            .iter()
+            .enumerate()
+            .filter(|(position, p)| {
+                !matched.contains(position)
+                    && !p.import.transaction.is_pending()
+                    && p.account_id == transaction.account_id
+                    && !imported.contains(&(p.account_id, p.import.import_id.as_str()))
+            })
+            .filter_map(|(position, p)| {
+                let date = p.import.transaction.date.with_timezone(&Oslo).date_naive();
+                let days = (date - transaction.date).num_days();
+                if !(-BOOKED_DAYS_BEFORE..=BOOKED_DAYS_AFTER).contains(&days) {
+                    return None;
+                }
+                let amount = ynab::to_milliunits(p.import.transaction.amount);
+                let same_amount = amount == transaction.amount;
+                let same_payee = transaction.payee_name.as_ref().is_some_and(|payee| {
+                    payee.to_lowercase() == p.import.payee_name.to_lowercase()
+                });
+                (same_amount || same_payee).then_some((position, !same_amount, days.abs()))
+            })
+            // Prefer an exact amount, then the closest date
+            .min_by_key(|(_, amount_changed, days)| (*amount_changed, *days))
+            .map(|(position, _, _)| position);
+
+        match booked {
+            Some(position) => {
+                matched.insert(position);
+                let p = &posted[position];
+                plan.settled.push((p.index, p.account_id.to_string()));
+
+                if transaction.is_uncleared() {
+                    let date = p.import.transaction.date.with_timezone(&Oslo).date_naive();
+                    let amount = ynab::to_milliunits(p.import.transaction.amount);
+                    plan.updates.push(UpdateYnabTransaction {
+                        id: transaction.id.clone(),
+                        date: (date != transaction.date).then_some(date),
+                        amount: (amount != transaction.amount).then_some(amount),
+                        cleared: Some("cleared".to_string()),
+                        ..Default::default()
+                    });
+                }
+            }
+            None if transaction.is_uncleared() && transaction.date >= window_start => {
+                match vanished_pending {
+                    // Already flagged on an earlier run
+                    VanishedPending::Flag
+                        if transaction.flag_color.as_deref() == Some(VANISHED_FLAG_COLOR) => {}
+                    VanishedPending::Flag => {
+                        plan.updates.push(UpdateYnabTransaction {
+                            id: transaction.id.clone(),
+                            flag_color: Some(VANISHED_FLAG_COLOR.to_string()),
+                            ..Default::default()
+                        });
+                        plan.vanished += 1;
+                    }
+                    VanishedPending::Delete => {
+                        plan.deletes.push(transaction.id.clone());
+                        plan.vanished += 1;
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    plan
+}
+
+/// Start of the YNAB lookup window, early enough to find pending transactions booked later
+pub fn lookup_start(window_start: NaiveDate) -> NaiveDate {
+    window_start - TimeDelta::days(BOOKED_DAYS_AFTER)
+}
@@ -0,0 +1,179 @@
+use crate::config::{Config, ConfigError};
+use crate::learn::LearnMode;
+use crate::memo::MemoTemplate;
+use crate::pending::VanishedPending;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Component, PathBuf};
+use tracing::{debug, info};
+
+/// Name used for the profile built from plain environment variables
+pub const DEFAULT_PROFILE_NAME: &str = "default";
+
+/// Profile is a named set of credentials, budget and account mapping synced together
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub config: Config,
+}
+
+/// A single profile as written in the profiles configuration file
+#[derive(Debug, Deserialize)]
+struct ProfileEntry {
+    sparebank1_client_id: String,
+    sparebank1_client_secret: String,
+    sparebank1_fin_inst: String,
+    ynab_access_token: String,
+    ynab_budget_id: String,
+    account_config_path: String,
+    refresh_token_file_path: Option<String>,
+    initial_refresh_token: String,
+    rules_config_path: Option<String>,
+    alerts_config_path: Option<String>,
+    memo_template: Option<MemoTemplate>,
+    #[serde(default)]
+    pending_vanished: VanishedPending,
+    sync_state_path: Option<String>,
+    #[serde(default)]
+    balance_tolerance: f64,
+    #[serde(default)]
+    fail_on_balance_mismatch: bool,
+    #[serde(default)]
+    reconcile: bool,
+    #[serde(default)]
+    learn_categories: LearnMode,
+    learn_confidence: Option<f64>,
+    refresh_token_lifetime_days: Option<u32>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Normalised absolute path used to tell whether two profiles write the same file
+///
+/// Relative paths are resolved against the current directory and `.` and `..` components
+/// removed, then symlinks are resolved when the file already exists.
+fn file_key(path: &str) -> PathBuf {
+    let absolute = env::current_dir().unwrap_or_default().join(path);
+    let mut normalised = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalised.pop();
+            }
+            component => normalised.push(component),
+        }
+    }
+    normalised.canonicalize().unwrap_or(normalised)
+}
+
+/// Reads named profiles from a JSON file keyed by profile name
+pub fn read_profiles_json(profiles_config_path: &str) -> Result<Vec<Profile>, ConfigError> {
+    debug!(
+        "Reading profile configuration from: {}",
+        profiles_config_path
+    );
+    let file = File::open(profiles_config_path)?;
+    let reader = BufReader::new(file);
+    let entries: BTreeMap<String, ProfileEntry> = serde_json::from_reader(reader)?;
+
+    if entries.is_empty() {
+        return Err(ConfigError::ValidationError(format!(
+            "No profiles defined in {}",
+            profiles_config_path
+        )));
+    }
+
+    let mut profiles = Vec::with_capacity(entries.len());
+    for (name, entry) in entries {
+        // Each profile gets its own token store unless given explicitly
+        let refresh_token_file_path = entry
+            .refresh_token_file_path
+            .unwrap_or_else(|| format!("refresh_token_{}.txt", name));
+
+        let mut config = Config::with_values(
+            entry.sparebank1_client_id,
+            entry.sparebank1_client_secret,
+            entry.sparebank1_fin_inst,
+            entry.ynab_access_token,
+            entry.ynab_budget_id,
+            entry.account_config_path,
+            Some(refresh_token_file_path),
+            entry.initial_refresh_token,
+        )
+        .map_err(|e| ConfigError::ValidationError(format!("profile '{}': {}", name, e)))?;
+        config.rules_config_path = entry.rules_config_path;
+        config.alerts_config_path = entry.alerts_config_path;
+        config.memo_template = entry.memo_template;
+        config.pending_vanished = entry.pending_vanished;
+        config.sync_state_path = entry.sync_state_path;
+        config.balance_tolerance = entry.balance_tolerance;
+        config.fail_on_balance_mismatch = entry.fail_on_balance_mismatch;
+        config.reconcile = entry.reconcile;
+        config.learn_categories = entry.learn_categories;
+        if let Some(learn_confidence) = entry.learn_confidence {
+            config.learn_confidence = learn_confidence;
+        }
+        if let Some(lifetime) = entry.refresh_token_lifetime_days {
+            config.refresh_token_lifetime_days = lifetime;
+        }
+        config
+            .validate()
+            .map_err(|e| ConfigError::ValidationError(format!("profile '{}': {}", name, e)))?;
+        config.dry_run = entry.dry_run;
+
+        profiles.push(Profile { name, config });
+    }
+
+    // Refresh tokens are rotated on every use, so two profiles must never share a file
+    let mut token_paths = HashSet::new();
+    let mut state_paths = HashSet::new();
+    for profile in &profiles {
+        if !token_paths.insert(file_key(&profile.config.refresh_token_file_path)) {
+            return Err(ConfigError::ValidationError(format!(
+                "profile '{}' shares refresh token file {} with another profile",
+                profile.name, profile.config.refresh_token_file_path
+            )));
+        }
+        // Sync state is rewritten at the end of every run as well, concurrent runs would race
+        if let Some(state_path) = &profile.config.sync_state_path {
+            if !state_paths.insert(file_key(state_path)) {
+                return Err(ConfigError::ValidationError(format!(
+                    "profile '{}' shares sync state file {} with another profile",
+                    profile.name, state_path
+                )));
+            }
+        }
+    }
+
+    info!("Loaded {} profiles from configuration", profiles.len());
+    Ok(profiles)
+}
+
+/// Loads profiles from PROFILES_CONFIG_PATH, or a single default profile from the environment
+pub fn load_profiles() -> Result<Vec<Profile>, ConfigError> {
+    Config::load_dotenv();
+
+    match env::var("PROFILES_CONFIG_PATH") {
+        Ok(path) => {
+            let dry_run = Config::get_env_bool("DRY_RUN");
+            let fail_on_balance_mismatch = Config::get_env_bool("FAIL_ON_BALANCE_MISMATCH");
+            let reconcile = Config::get_env_bool("RECONCILE");
+            let mut profiles = read_profiles_json(&path)?;
+            for profile in profiles.iter_mut() {
+                profile.config.dry_run |= dry_run;
+                profile.config.fail_on_balance_mismatch |= fail_on_balance_mismatch;
+                profile.config.reconcile |= reconcile;
+            }
+            Ok(profiles)
+        }
+        Err(env::VarError::NotPresent) => Ok(vec![Profile {
+            name: DEFAULT_PROFILE_NAME.to_string(),
+            config: Config::new()?,
+        }]),
+        Err(e) => Err(ConfigError::EnvVarError(e)),
+    }
+}
@@ -0,0 +1,264 @@
+use crate::balance::BalanceCheck;
+use crate::sparebanken1::Transaction;
+use crate::sync::SyncSummary;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Balance of a YNAB account next to the SpareBank1 account it is synced from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BalanceReport {
+    pub budget_id: String,
+    pub ynab_account_id: String,
+    pub ynab_account_name: String,
+    /// Balance reported by SpareBank1, in milliunits
+    pub bank_balance: i64,
+    /// Cleared balance in YNAB, in milliunits
+    pub ynab_cleared_balance: i64,
+    /// Amount YNAB is off by in milliunits, positive when YNAB is above the bank
+    pub difference: i64,
+    /// Whether the difference is within the balance tolerance
+    pub matches: bool,
+    pub balance_only: bool,
+}
+
+/// Outcome of a sync for a single SpareBank1 account
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AccountReport {
+    /// SpareBank1 account key
+    pub account: String,
+    pub name: Option<String>,
+    pub fetched: usize,
+    pub skipped: usize,
+    /// Transactions created in YNAB, once per target account
+    pub imported: usize,
+    pub duplicates: usize,
+    pub updated: usize,
+    /// Alerts on transactions of the account
+    pub alerts: usize,
+    pub balances: Vec<BalanceReport>,
+    pub errors: Vec<String>,
+}
+
+/// Per-account reports collected while a profile is synced
+#[derive(Debug, Default)]
+pub struct AccountReports {
+    accounts: BTreeMap<String, AccountReport>,
+}
+
+impl AccountReports {
+    /// Starts an empty report for every mapped account
+    pub fn new<'a>(accounts: impl IntoIterator<Item = &'a String>) -> Self {
+        let accounts = accounts
+            .into_iter()
+            .map(|account| {
+                let report = AccountReport {
+                    account: account.clone(),
+                    ..Default::default()
+                };
+                (account.clone(), report)
+            })
+            .collect();
+        Self { accounts }
+    }
+
+    /// Report for an account, started if the account was not mapped
+    pub fn account(&mut self, account: &str) -> &mut AccountReport {
+        self.accounts
+            .entry(account.to_string())
+            .or_insert_with(|| AccountReport {
+                account: account.to_string(),
+                ..Default::default()
+            })
+    }
+
+    /// Counts fetched transactions and picks up account names
+    pub fn add_fetched(&mut self, transactions: &[Transaction]) {
+        for transaction in transactions {
+            let report = self.account(&transaction.account);
+            report.fetched += 1;
+            if report.name.is_none() {
+                report.name = transaction.account_name.clone();
+            }
+        }
+    }
+
+    /// Adds a balance check, the tolerance is in milliunits
+    pub fn add_balance(&mut self, check: &BalanceCheck, tolerance: i64) {
+        let report = self.account(&check.account_key);
+        report.name = Some(check.account_name.clone());
+        report.balances.push(BalanceReport {
+            budget_id: check.budget_id.clone(),
+            ynab_account_id: check.ynab_account_id.clone(),
+            ynab_account_name: check.ynab_account_name.clone(),
+            bank_balance: check.bank_balance,
+            ynab_cleared_balance: check.ynab_cleared_balance,
+            difference: check.difference(),
+            matches: check.balance_only || check.matches(tolerance),
+            balance_only: check.balance_only,
+        });
+    }
+
+    /// Finished reports sorted by account key, accounts without a balance check get an error
+    pub fn finish(self) -> Vec<AccountReport> {
+        self.accounts
+            .into_values()
+            .map(|mut report| {
+                if report.balances.is_empty() {
+                    report
+                        .errors
+                        .push("balance not checked, account not found".to_string());
+                }
+                report
+            })
+            .collect()
+    }
+}
+
+/// Whether a profile synced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileStatus {
+    Ok,
+    Failed,
+}
+
+/// Outcome of a sync for one profile
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileReport {
+    pub profile: String,
+    pub status: ProfileStatus,
+    pub dry_run: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(flatten)]
+    pub summary: Option<SyncSummary>,
+}
+
+impl ProfileReport {
+    pub fn ok(profile: &str, dry_run: bool, summary: SyncSummary) -> Self {
+        Self {
+            profile: profile.to_string(),
+            status: ProfileStatus::Ok,
+            dry_run,
+            error: None,
+            summary: Some(summary),
+        }
+    }
+
+    /// The summary is kept when the profile failed after syncing, such as on a balance mismatch
+    pub fn failed(
+        profile: &str,
+        dry_run: bool,
+        error: String,
+        summary: Option<SyncSummary>,
+    ) -> Self {
+        Self {
+            profile: profile.to_string(),
+            status: ProfileStatus::Failed,
+            dry_run,
+            error: Some(error),
+            summary,
+        }
+    }
+}
+
+/// SyncReport is the structured outcome of a sync run over all profiles
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncReport {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub profiles: Vec<ProfileReport>,
+}
+
+/// Balance column of the table
+fn balance_label(balances: &[BalanceReport]) -> String {
+    if balances.is_empty() {
+        return "-".to_string();
+    }
+    match balances.iter().find(|b| !b.matches) {
+        Some(mismatch) => format!("off {:.2}", mismatch.difference as f64 / 1000.0),
+        None => "ok".to_string(),
+    }
+}
+
+impl SyncReport {
+    /// Number of profiles that failed to sync
+    pub fn failed(&self) -> usize {
+        self.profiles
+            .iter()
+            .filter(|p| p.status == ProfileStatus::Failed)
+            .count()
+    }
+
+    /// Renders the report as a table with one row per account
+    pub fn to_table(&self) -> String {
+        let mut rows = vec![[
+            "PROFILE",
+            "ACCOUNT",
+            "FETCHED",
+            "SKIPPED",
+            "IMPORTED",
+            "DUPLICATES",
+            "UPDATED",
+            "ALERTS",
+            "BALANCE",
+        ]
+        .map(String::from)
+        .to_vec()];
+
+        for profile in &self.profiles {
+            let accounts = profile.summary.iter().flat_map(|s| &s.accounts);
+            for account in accounts {
+                rows.push(vec![
+                    profile.profile.clone(),
+                    account
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| account.account.clone()),
+                    account.fetched.to_string(),
+                    account.skipped.to_string(),
+                    account.imported.to_string(),
+                    account.duplicates.to_string(),
+                    account.updated.to_string(),
+                    account.alerts.to_string(),
+                    balance_label(&account.balances),
+                ]);
+            }
+            if let Some(error) = &profile.error {
+                rows.push(vec![profile.profile.clone(), format!("failed: {}", error)]);
+            }
+        }
+
+        let columns = rows[0].len();
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                rows.iter()
+                    .filter(|row| row.len() == columns)
+                    .map(|row| &row[column])
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        rows.iter()
+            .map(|row| {
+                let line: Vec<String> = row
+                    .iter()
+                    .enumerate()
+                    .map(|(column, cell)| {
+                        // The last cell of a row, such as a failure message, is not padded
+                        if column + 1 == row.len() {
+                            cell.clone()
+                        } else {
+                            format!("{:width$}", cell, width = widths[column])
+                        }
+                    })
+                    .collect();
+                line.join("  ")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
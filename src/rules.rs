@@ -0,0 +1,417 @@
+use crate::mcc::{self, MerchantGroup};
+use crate::sparebanken1::Transaction;
+use chrono::NaiveDate;
+use chrono_tz::Europe::Oslo;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use tracing::{debug, info};
+
+/// RuleError represents all possible errors when loading a rules file
+#[derive(Debug, thiserror::Error)]
+pub enum RuleError {
+    #[error("Failed to read rules file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Failed to parse rules file: {0}")]
+    ParseError(#[from] serde_json::Error),
+
+    #[error("Invalid rule {0}: {1}")]
+    InvalidRule(String, String),
+}
+
+/// Text matcher applied to a transaction field
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextMatcher {
+    /// Regular expression search
+    Regex(#[serde(deserialize_with = "deserialize_regex")] Regex),
+    /// Case-insensitive substring match
+    Contains(String),
+    /// Case-insensitive prefix match
+    Prefix(String),
+    /// Case-insensitive exact match
+    Equals(String),
+}
+
+impl TextMatcher {
+    pub fn is_match(&self, value: &str) -> bool {
+        match self {
+            TextMatcher::Regex(regex) => regex.is_match(value),
+            TextMatcher::Contains(needle) => value.to_lowercase().contains(&needle.to_lowercase()),
+            TextMatcher::Prefix(prefix) => value.to_lowercase().starts_with(&prefix.to_lowercase()),
+            TextMatcher::Equals(expected) => value.to_lowercase() == expected.to_lowercase(),
+        }
+    }
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+/// Inclusive amount range in NOK, negative amounts are expenses
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AmountRange {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl AmountRange {
+    pub fn contains(&self, amount: f64) -> bool {
+        self.min.is_none_or(|min| amount >= min) && self.max.is_none_or(|max| amount <= max)
+    }
+}
+
+/// Inclusive date range, compared against the transaction date in Oslo time
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+}
+
+/// Conditions that must all hold for a rule to match
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConditions {
+    /// Matched against SpareBank1's cleaned description
+    pub payee: Option<TextMatcher>,
+    /// Matched against SpareBank1's raw description
+    pub description: Option<TextMatcher>,
+    /// SpareBank1 account key
+    pub account: Option<String>,
+    pub amount: Option<AmountRange>,
+    pub date: Option<DateRange>,
+    /// Matched against the purchase currency, NOK for domestic transactions
+    pub currency: Option<TextMatcher>,
+    /// Whether the purchase was made in a currency other than NOK
+    pub foreign: Option<bool>,
+    /// Matched against the KID number or message
+    pub reference: Option<TextMatcher>,
+    /// Matched against SpareBank1's transaction type code
+    pub type_code: Option<TextMatcher>,
+    /// Matched against the merchant category code of card purchases
+    pub mcc: Option<TextMatcher>,
+    /// Matched against the account number on the other side
+    pub counterparty_account: Option<TextMatcher>,
+}
+
+/// Whether an optional field matches, a missing field never matches a condition
+fn is_optional_match(matcher: &Option<TextMatcher>, value: &Option<String>) -> bool {
+    match (matcher, value) {
+        (None, _) => true,
+        (Some(matcher), Some(value)) => matcher.is_match(value),
+        (Some(_), None) => false,
+    }
+}
+
+impl RuleConditions {
+    pub fn is_match(&self, transaction: &Transaction) -> bool {
+        self.payee
+            .as_ref()
+            .is_none_or(|m| m.is_match(&transaction.payee))
+            && self
+                .description
+                .as_ref()
+                .is_none_or(|m| m.is_match(&transaction.description))
+            && self
+                .account
+                .as_ref()
+                .is_none_or(|account| account == &transaction.account)
+            && self
+                .amount
+                .as_ref()
+                .is_none_or(|range| range.contains(transaction.amount))
+            && self.date.as_ref().is_none_or(|range| {
+                range.contains(transaction.date.with_timezone(&Oslo).date_naive())
+            })
+            && self
+                .currency
+                .as_ref()
+                .is_none_or(|m| m.is_match(transaction.currency()))
+            && self
+                .foreign
+                .is_none_or(|foreign| foreign == transaction.is_foreign())
+            && is_optional_match(&self.reference, &transaction.reference)
+            && is_optional_match(&self.type_code, &transaction.type_code)
+            && is_optional_match(&self.mcc, &transaction.merchant_category_code)
+            && is_optional_match(
+                &self.counterparty_account,
+                &transaction.counterparty_account,
+            )
+    }
+}
+
+/// YNAB flag color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlagColor {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+}
+
+impl fmt::Display for FlagColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FlagColor::Red => "red",
+            FlagColor::Orange => "orange",
+            FlagColor::Yellow => "yellow",
+            FlagColor::Green => "green",
+            FlagColor::Blue => "blue",
+            FlagColor::Purple => "purple",
+        };
+        f.write_str(name)
+    }
+}
+
+/// One line of a split transaction
+///
+/// A line has either a fixed NOK `amount` (given as a positive number, it takes the sign of
+/// the transaction), a `percent` of the transaction, or neither to take what remains.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SplitLine {
+    pub amount: Option<f64>,
+    pub percent: Option<f64>,
+    /// YNAB category name, optionally qualified as "Group: Category"
+    pub category: Option<String>,
+    pub memo: Option<String>,
+}
+
+/// Largest rounding difference in milliunits a split without a remainder line may absorb
+const SPLIT_ROUNDING_TOLERANCE: i64 = 1000;
+
+/// Divides a transaction amount in milliunits over split lines
+///
+/// Returns None when the lines cannot add up to the total: fixed amounts exceeding it,
+/// or lines without a remainder line not summing to the total within rounding.
+pub fn split_amounts(lines: &[SplitLine], total: i64) -> Option<Vec<i64>> {
+    let sign = if total < 0 { -1 } else { 1 };
+    let mut amounts: Vec<Option<i64>> = lines
+        .iter()
+        .map(|line| match (line.amount, line.percent) {
+            (Some(amount), _) => Some(sign * (amount.abs() * 1000.0).round() as i64),
+            (None, Some(percent)) => Some((total as f64 * percent / 100.0).round() as i64),
+            (None, None) => None,
+        })
+        .collect();
+
+    let allocated: i64 = amounts.iter().flatten().sum();
+    let remainder = total - allocated;
+
+    match amounts.iter().position(|amount| amount.is_none()) {
+        Some(index) => {
+            // The remainder line must not flip the sign of the transaction
+            if remainder != 0 && remainder.signum() != sign {
+                return None;
+            }
+            amounts[index] = Some(remainder);
+        }
+        None => {
+            if remainder.abs() > SPLIT_ROUNDING_TOLERANCE {
+                return None;
+            }
+            if let Some(Some(last)) = amounts.last_mut() {
+                *last += remainder;
+            }
+        }
+    }
+
+    amounts.into_iter().collect()
+}
+
+/// A single rule: conditions plus the changes to apply when they match
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: Option<String>,
+    #[serde(rename = "match", default)]
+    pub conditions: RuleConditions,
+    /// Payee name sent to YNAB instead of the cleaned description
+    pub payee: Option<String>,
+    /// YNAB category name, optionally qualified as "Group: Category"
+    pub category: Option<String>,
+    /// Leave transactions categorised by this rule unapproved for review
+    #[serde(default)]
+    pub review: bool,
+    /// Never import matching transactions
+    #[serde(default)]
+    pub skip: bool,
+    /// Import matching transactions as a split over several categories
+    pub split: Option<Vec<SplitLine>>,
+    /// Flag matching transactions in YNAB
+    pub flag: Option<FlagColor>,
+}
+
+impl Rule {
+    /// Rule name, or its position in the rules file when unnamed
+    fn display_name(&self, index: usize) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("rule #{}", index + 1))
+    }
+
+    fn validate(&self, name: &str) -> Result<(), RuleError> {
+        let Some(split) = &self.split else {
+            return Ok(());
+        };
+        let invalid =
+            |reason: &str| Err(RuleError::InvalidRule(name.to_string(), reason.to_string()));
+
+        if split.len() < 2 {
+            return invalid("a split needs at least two lines");
+        }
+        if split
+            .iter()
+            .any(|line| line.amount.is_some() && line.percent.is_some())
+        {
+            return invalid("a split line cannot have both amount and percent");
+        }
+        if split
+            .iter()
+            .filter(|line| line.amount.is_none() && line.percent.is_none())
+            .count()
+            > 1
+        {
+            return invalid("only one split line can take the remainder");
+        }
+        if self.category.is_some() {
+            return invalid("a rule cannot set both category and split");
+        }
+        Ok(())
+    }
+}
+
+/// Changes collected from all rules matching a transaction
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleOutcome {
+    pub payee: Option<String>,
+    pub category: Option<String>,
+    /// Whether the rule that set the category or split asked for review
+    pub review: bool,
+    /// Whether the category comes from the merchant category code fallback
+    pub merchant_category: bool,
+    pub split: Option<Vec<SplitLine>>,
+    pub flag: Option<FlagColor>,
+    /// Name of the skip rule that matched, if any
+    pub skipped_by: Option<String>,
+    /// Names of the rules that contributed to the outcome
+    pub matched_rules: Vec<String>,
+}
+
+/// RuleSet is an ordered list of rules, earlier rules take precedence
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSet {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// YNAB category for each merchant group, used when no rule sets a category
+    #[serde(default)]
+    pub merchant_categories: HashMap<MerchantGroup, String>,
+}
+
+impl RuleSet {
+    /// Checks rules for combinations that cannot be applied
+    pub fn validate(&self) -> Result<(), RuleError> {
+        for (index, rule) in self.rules.iter().enumerate() {
+            let name = rule.display_name(index);
+            rule.validate(&name)?;
+        }
+        Ok(())
+    }
+
+    /// Evaluates all rules against a transaction
+    ///
+    /// Each change is taken from the first matching rule that sets it.
+    pub fn evaluate(&self, transaction: &Transaction) -> RuleOutcome {
+        let mut outcome = RuleOutcome::default();
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.conditions.is_match(transaction) {
+                continue;
+            }
+
+            let name = rule.display_name(index);
+
+            let mut applied = false;
+            if outcome.skipped_by.is_none() && rule.skip {
+                outcome.skipped_by = Some(name.clone());
+                applied = true;
+            }
+            if outcome.payee.is_none() && rule.payee.is_some() {
+                outcome.payee = rule.payee.clone();
+                applied = true;
+            }
+            // A split replaces the category, so only the first of the two applies
+            let categorised = outcome.category.is_some() || outcome.split.is_some();
+            if !categorised && rule.category.is_some() {
+                outcome.category = rule.category.clone();
+                outcome.review = rule.review;
+                applied = true;
+            }
+            if !categorised && rule.split.is_some() {
+                outcome.split = rule.split.clone();
+                outcome.review = rule.review;
+                applied = true;
+            }
+
+            if outcome.flag.is_none() && rule.flag.is_some() {
+                outcome.flag = rule.flag;
+                applied = true;
+            }
+
+            if applied {
+                outcome.matched_rules.push(name);
+            }
+        }
+
+        // Categorised by merchant category code, left for review since the groups are broad
+        let categorised = outcome.category.is_some() || outcome.split.is_some();
+        if !categorised && outcome.skipped_by.is_none() {
+            let mapped = transaction
+                .merchant_category_code
+                .as_deref()
+                .and_then(mcc::merchant_group)
+                .and_then(|group| Some((group, self.merchant_categories.get(&group)?)));
+            if let Some((group, category)) = mapped {
+                outcome.category = Some(category.clone());
+                outcome.review = true;
+                outcome.merchant_category = true;
+                outcome
+                    .matched_rules
+                    .push(format!("merchant group {}", group));
+            }
+        }
+
+        outcome
+    }
+}
+
+pub fn read_rules_json(rules_config_path: &str) -> Result<RuleSet, RuleError> {
+    debug!("Reading rules from: {}", rules_config_path);
+    let file = File::open(rules_config_path)?;
+    let reader = BufReader::new(file);
+    let rule_set: RuleSet = serde_json::from_reader(reader)?;
+    rule_set.validate()?;
+
+    info!("Loaded {} rules from configuration", rule_set.rules.len());
+    Ok(rule_set)
+}
@@ -0,0 +1,147 @@
+use crate::{health, metrics};
+use chrono::Utc;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, Take};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Most bytes read of a request line and headers, larger requests are dropped
+const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+
+/// Time a client gets to send its request line and headers
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Response to a request on the daemon's HTTP server
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl HttpResponse {
+    fn text(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.to_string(),
+        }
+    }
+
+    fn json(status: u16, body: &impl serde::Serialize) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_string(body).unwrap_or_default(),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Service Unavailable",
+        }
+    }
+}
+
+/// Answers a request by method and path
+pub fn route(method: &str, path: &str) -> HttpResponse {
+    if method != "GET" {
+        return HttpResponse::text(405, "method not allowed\n");
+    }
+    // Query strings are ignored
+    match path.split('?').next().unwrap_or_default() {
+        "/metrics" => HttpResponse {
+            status: 200,
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body: metrics::global().render(),
+        },
+        // Liveness only fails when the daemon stopped syncing, restarting will not fix the rest
+        "/healthz" => {
+            let alive = health::global().is_alive(Utc::now());
+            let status = if alive { "ok" } else { "stalled" };
+            HttpResponse::json(
+                if alive { 200 } else { 503 },
+                &serde_json::json!({ "status": status }),
+            )
+        }
+        "/readyz" => {
+            let readiness = health::global().readiness(Utc::now());
+            HttpResponse::json(if readiness.ready { 200 } else { 503 }, &readiness)
+        }
+        _ => HttpResponse::text(404, "not found\n"),
+    }
+}
+
+/// Reads the request line, and the headers so the client is not cut off
+async fn read_request(reader: &mut BufReader<Take<TcpStream>>) -> io::Result<String> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+
+    let mut header = String::new();
+    while reader.read_line(&mut header).await? > 2 {
+        header.clear();
+    }
+    if reader.get_ref().limit() == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request too large",
+        ));
+    }
+    Ok(request_line)
+}
+
+async fn handle_connection(stream: TcpStream) -> io::Result<()> {
+    // Neither a slow nor an endless request may hold the connection open
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_BYTES));
+    let request_line = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut reader))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "request not received in time"))??;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let response = route(method, path);
+    debug!("{} {} {}", method, path, response.status);
+
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len()
+    );
+    let mut stream = reader.into_inner().into_inner();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Binds the HTTP server, serving it in the background
+pub async fn start(addr: SocketAddr) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!(
+        "Serving metrics and health checks on http://{}",
+        listener.local_addr()?
+    );
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_connection(stream).await {
+                            debug!("HTTP connection failed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept HTTP connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
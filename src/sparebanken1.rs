@@ -1,9 +1,34 @@
+use crate::config::Config;
+use crate::metrics::{Api, RecordedSend};
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
 use std::error::Error;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, instrument};
 
 const BASE_API_URL: &str = "https://api.sparebank1.no/personal/banking";
+const AUTH_URL: &str = "https://api-auth.sparebank1.no/oauth/token";
+
+/// Base URL of the banking API, overridden by SPAREBANK1_API_URL
+fn base_api_url() -> String {
+    Config::api_url("SPAREBANK1_API_URL", BASE_API_URL)
+}
+
+/// Token endpoint, overridden by SPAREBANK1_AUTH_URL
+pub fn auth_url() -> String {
+    Config::api_url("SPAREBANK1_AUTH_URL", AUTH_URL)
+}
+
+/// Sparebank1Error represents failures fetching data from the SpareBank1 API
+#[derive(Debug, thiserror::Error)]
+pub enum Sparebank1Error {
+    #[error("SpareBank1 request failed: {0}")]
+    RequestError(#[from] reqwest::Error),
+
+    #[error("Failed to parse SpareBank1 response: {0}")]
+    ParseError(#[from] serde_json::Error),
+}
 
 #[derive(Debug, Deserialize)]
 struct TransactionsResponse {
@@ -13,23 +38,192 @@ struct TransactionsResponse {
 #[derive(Debug, Deserialize)]
 struct TransactionResponse {
     id: String,
-    amount: f32,
+    amount: f64,
     description: Option<String>,
     #[serde(rename = "cleanedDescription")]
     cleaned_description: Option<String>,
     #[serde(rename = "accountKey")]
     account_key: String,
+    #[serde(rename = "accountName")]
+    account_name: Option<String>,
     date: i64,
+    #[serde(rename = "interestDate")]
+    interest_date: Option<i64>,
+    /// Amount in the currency the purchase was made in, for foreign transactions
+    #[serde(rename = "originalAmount")]
+    original_amount: Option<f64>,
+    #[serde(rename = "originalCurrency")]
+    original_currency: Option<String>,
+    /// NOK per unit of the original currency
+    #[serde(rename = "exchangeRate")]
+    exchange_rate: Option<f64>,
+    #[serde(rename = "bookingStatus")]
+    booking_status: Option<String>,
+    /// KID number or message to the recipient
+    #[serde(rename = "kidOrMessage")]
+    kid_or_message: Option<String>,
+    #[serde(rename = "typeCode", default, deserialize_with = "deserialize_text")]
+    type_code: Option<String>,
+    #[serde(rename = "typeText")]
+    type_text: Option<String>,
+    #[serde(
+        rename = "merchantCategoryCode",
+        default,
+        deserialize_with = "deserialize_text"
+    )]
+    merchant_category_code: Option<String>,
+    #[serde(
+        rename = "remoteAccountNumber",
+        default,
+        deserialize_with = "deserialize_text"
+    )]
+    remote_account_number: Option<String>,
+    #[serde(rename = "remoteAccountName")]
+    remote_account_name: Option<String>,
+    source: Option<String>,
+    /// Fields not listed above, kept for debugging
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+/// Reads a code or number the API may send either as a string or as a number
+fn deserialize_text<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Value>::deserialize(deserializer)? {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(code)) => Ok(Some(code)),
+        Some(Value::Number(code)) => Ok(Some(code.to_string())),
+        Some(other) => Err(serde::de::Error::custom(format!(
+            "expected a string or number, got {}",
+            other
+        ))),
+    }
+}
+
+/// Whether a transaction is booked or only reserved on the account
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BookingStatus {
+    #[default]
+    Booked,
+    /// Reserved card transaction, may still change amount or disappear
+    Pending,
+}
+
+impl BookingStatus {
+    fn from_api(status: Option<&str>) -> Self {
+        match status.map(|s| s.to_uppercase()).as_deref() {
+            Some("PENDING") | Some("RESERVED") => BookingStatus::Pending,
+            _ => BookingStatus::Booked,
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Serialize)]
 pub struct Transaction {
     pub id: String,
     pub description: String,
     pub payee: String,
-    pub amount: f32,
+    pub amount: f64,
+    /// Booking date
     pub date: DateTime<Utc>,
     pub account: String,
+    pub account_name: Option<String>,
+    pub interest_date: Option<DateTime<Utc>>,
+    pub original_amount: Option<f64>,
+    pub original_currency: Option<String>,
+    /// NOK per unit of the original currency, as reported by the bank
+    pub exchange_rate: Option<f64>,
+    pub booking_status: BookingStatus,
+    /// KID number or message to the recipient
+    pub reference: Option<String>,
+    /// SpareBank1 transaction type, e.g. `VARER` for card purchases
+    pub type_code: Option<String>,
+    pub type_text: Option<String>,
+    /// Merchant category code (MCC) of card purchases
+    pub merchant_category_code: Option<String>,
+    /// Account number on the other side of the transaction
+    pub counterparty_account: Option<String>,
+    pub counterparty_name: Option<String>,
+    /// Where the bank sourced the transaction from
+    pub source: Option<String>,
+    /// Fields returned by the API that are not mapped above
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub extra: Map<String, Value>,
+}
+
+impl Transaction {
+    pub fn is_pending(&self) -> bool {
+        self.booking_status == BookingStatus::Pending
+    }
+
+    /// Currency the purchase was made in, NOK when the bank reports none
+    pub fn currency(&self) -> &str {
+        self.original_currency
+            .as_deref()
+            .filter(|currency| !currency.trim().is_empty())
+            .unwrap_or("NOK")
+    }
+
+    /// Whether the purchase was made in a currency other than NOK
+    pub fn is_foreign(&self) -> bool {
+        !self.currency().eq_ignore_ascii_case("NOK")
+    }
+
+    /// Exchange rate of a foreign purchase, derived from the two amounts when the bank
+    /// reports none
+    pub fn foreign_exchange_rate(&self) -> Option<f64> {
+        if !self.is_foreign() {
+            return None;
+        }
+        self.exchange_rate.or_else(|| {
+            let original_amount = self.original_amount.filter(|amount| *amount != 0.0)?;
+            Some((self.amount / original_amount).abs())
+        })
+    }
+}
+
+impl From<TransactionResponse> for Transaction {
+    fn from(transaction: TransactionResponse) -> Self {
+        let transaction_date = DateTime::from_timestamp(transaction.date / 1000, 0).unwrap();
+
+        Transaction {
+            id: transaction.id,
+            account: transaction.account_key,
+            description: transaction.description.unwrap_or_default(),
+            payee: transaction.cleaned_description.unwrap_or_default(),
+            amount: transaction.amount,
+            date: transaction_date,
+            account_name: transaction.account_name,
+            interest_date: transaction
+                .interest_date
+                .and_then(|date| DateTime::from_timestamp(date / 1000, 0)),
+            original_amount: transaction.original_amount,
+            original_currency: transaction.original_currency,
+            exchange_rate: transaction.exchange_rate,
+            booking_status: BookingStatus::from_api(transaction.booking_status.as_deref()),
+            reference: transaction.kid_or_message,
+            type_code: transaction.type_code,
+            type_text: transaction.type_text,
+            merchant_category_code: transaction.merchant_category_code,
+            counterparty_account: transaction.remote_account_number,
+            counterparty_name: transaction.remote_account_name,
+            source: transaction.source,
+            extra: transaction.extra,
+        }
+    }
+}
+
+/// Parses a transactions response body from the SpareBank1 API
+pub fn parse_transactions(body: &str) -> Result<Vec<Transaction>, serde_json::Error> {
+    let response: TransactionsResponse = serde_json::from_str(body)?;
+    Ok(response
+        .transactions
+        .into_iter()
+        .map(Transaction::from)
+        .collect())
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,10 +231,17 @@ struct AccountsResponse {
     accounts: Vec<Account>,
 }
 
+/// Parses an accounts response body from the SpareBank1 API
+pub fn parse_accounts(body: &str) -> Result<Vec<Account>, serde_json::Error> {
+    let response: AccountsResponse = serde_json::from_str(body)?;
+    Ok(response.accounts)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Account {
     pub name: String,
-    pub balance: f32,
+    /// Booked balance, f64 so large balances convert to milliunits exactly
+    pub balance: f64,
     pub key: String,
     #[serde(rename = "accountNumber")]
     pub account_number: String,
@@ -85,6 +286,7 @@ impl Sparebanken1Client {
     }
 
     /// Refresh access token using the refresh token flow
+    #[instrument(name = "refresh_token", skip_all)]
     pub async fn refresh_access_token(
         client_id: &str,
         client_secret: &str,
@@ -92,7 +294,7 @@ impl Sparebanken1Client {
     ) -> Result<(String, String), Box<dyn Error>> {
         debug!("Refreshing SpareBank1 access token");
         let client = reqwest::Client::new();
-        let url = "https://api-auth.sparebank1.no/oauth/token";
+        let url = auth_url();
 
         let body = format!(
             "grant_type=refresh_token&refresh_token={}&client_id={}&client_secret={}",
@@ -100,10 +302,10 @@ impl Sparebanken1Client {
         );
 
         let response: AuthResponse = client
-            .post(url)
+            .post(&url)
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
-            .send()
+            .send_recorded(Api::Sparebank1)
             .await?
             .json::<AuthResponse>()
             .await?;
@@ -113,68 +315,64 @@ impl Sparebanken1Client {
         Ok((response.access_token, response.refresh_token))
     }
 
-    /// Parse a transaction response into a Transaction struct
-    fn parse_transaction(&self, transaction: &TransactionResponse) -> Transaction {
-        let transaction_date = DateTime::from_timestamp(transaction.date / 1000, 0).unwrap();
-
-        Transaction {
-            id: transaction.id.clone(),
-            account: transaction.account_key.clone(),
-            description: transaction.description.clone().unwrap_or_default(),
-            payee: transaction.cleaned_description.clone().unwrap_or_default(),
-            amount: transaction.amount,
-            date: transaction_date,
-        }
-    }
-
     /// Get transactions for the specified accounts
     pub async fn get_transactions(
         &self,
         accounts: Vec<String>,
-    ) -> Result<Vec<Transaction>, reqwest::Error> {
+    ) -> Result<Vec<Transaction>, Sparebank1Error> {
         debug!("Fetching transactions for {} accounts", accounts.len());
-        let url = format!("{}/transactions", BASE_API_URL);
+        let url = format!("{}/transactions", base_api_url());
         let params: Vec<(&str, &str)> = accounts
             .iter()
             .map(|account| ("accountKey", account.as_str()))
             .collect();
 
         let client = reqwest::Client::new();
-        let transaction_response: TransactionsResponse = client
+        let body = client
             .get(&url)
             .header("Authorization", &format!("Bearer {}", self.access_token))
             .header("Accept", "application/vnd.sparebank1.v1+json")
             .query(&params)
-            .send()
+            .send_recorded(Api::Sparebank1)
             .await?
             .error_for_status()
             .map_err(|e| {
                 error!("Failed to fetch transactions from SpareBank1: {}", e);
                 e
             })?
-            .json::<TransactionsResponse>()
+            .text()
             .await?;
 
-        let transactions: Vec<Transaction> = transaction_response
-            .transactions
+        let transactions = parse_transactions(&body).map_err(|e| {
+            error!("Failed to parse transactions from SpareBank1: {}", e);
+            e
+        })?;
+
+        let unmapped: BTreeSet<&str> = transactions
             .iter()
-            .map(|txn| self.parse_transaction(txn))
+            .flat_map(|t| t.extra.keys().map(String::as_str))
             .collect();
+        if !unmapped.is_empty() {
+            debug!(
+                "Unmapped SpareBank1 transaction fields: {}",
+                unmapped.into_iter().collect::<Vec<_>>().join(", ")
+            );
+        }
 
         info!("Successfully fetched {} transactions", transactions.len());
         Ok(transactions)
     }
 
     /// Get accounts for the authenticated user
-    pub async fn get_accounts(&self) -> Result<Vec<Account>, reqwest::Error> {
+    pub async fn get_accounts(&self) -> Result<Vec<Account>, Sparebank1Error> {
         debug!("Fetching accounts from SpareBank1");
-        let url = format!("{}/accounts?includeCreditCardAccounts=true", BASE_API_URL);
+        let url = format!("{}/accounts?includeCreditCardAccounts=true", base_api_url());
 
         let accounts_response = reqwest::Client::new()
             .get(&url)
             .header("Authorization", &format!("Bearer {}", self.access_token))
             .header("accept", "application/vnd.sparebank1.v1+json")
-            .send()
+            .send_recorded(Api::Sparebank1)
             .await?
             .error_for_status()
             .map_err(|e| {
@@ -184,27 +382,26 @@ impl Sparebanken1Client {
             .text()
             .await?;
 
-        let accounts_json: AccountsResponse =
-            serde_json::from_str(&accounts_response).expect("Failed to parse accounts response");
+        let accounts = parse_accounts(&accounts_response).map_err(|e| {
+            error!("Failed to parse accounts from SpareBank1: {}", e);
+            e
+        })?;
 
-        info!(
-            "Successfully fetched {} accounts",
-            accounts_json.accounts.len()
-        );
-        Ok(accounts_json.accounts)
+        info!("Successfully fetched {} accounts", accounts.len());
+        Ok(accounts)
     }
 }
 
 // Legacy functions for backward compatibility
 pub async fn get_transactions(
-    access_token: &String,
+    access_token: &str,
     accounts: Vec<String>,
-) -> Result<Vec<Transaction>, reqwest::Error> {
-    let client = Sparebanken1Client::new(access_token.clone());
+) -> Result<Vec<Transaction>, Sparebank1Error> {
+    let client = Sparebanken1Client::new(access_token.to_string());
     client.get_transactions(accounts).await
 }
 
-pub async fn get_accounts(access_token: &String) -> Result<Vec<Account>, reqwest::Error> {
-    let client = Sparebanken1Client::new(access_token.clone());
+pub async fn get_accounts(access_token: &str) -> Result<Vec<Account>, Sparebank1Error> {
+    let client = Sparebanken1Client::new(access_token.to_string());
     client.get_accounts().await
 }
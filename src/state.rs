@@ -0,0 +1,213 @@
+use crate::sparebanken1::Transaction;
+use crate::ynab::{self, TransactionDetail, UpdateYnabTransaction};
+use chrono::NaiveDate;
+use chrono_tz::Europe::Oslo;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, ErrorKind};
+use tracing::{debug, info};
+
+/// StateError represents all possible errors when reading or writing the sync state file
+#[derive(Debug, thiserror::Error)]
+pub enum StateError {
+    #[error("Failed to access sync state file: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Failed to parse sync state file: {0}")]
+    ParseError(#[from] serde_json::Error),
+}
+
+/// Fields of a transaction as SpareBank1 reported them, before rules, learning and memo templates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BankValues {
+    pub date: NaiveDate,
+    /// Amount in milliunits
+    pub amount: i64,
+    pub payee: String,
+    pub description: String,
+}
+
+impl BankValues {
+    pub fn of(transaction: &Transaction) -> Self {
+        Self {
+            date: transaction.date.with_timezone(&Oslo).date_naive(),
+            amount: ynab::to_milliunits(transaction.amount),
+            payee: transaction.payee.clone(),
+            description: transaction.description.clone(),
+        }
+    }
+}
+
+/// Values a SpareBank1 transaction was last written to a YNAB account with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushedTransaction {
+    /// SpareBank1 transaction id
+    pub bank_id: String,
+    pub budget_id: String,
+    pub account_id: String,
+    pub import_id: String,
+    pub date: NaiveDate,
+    /// Amount in milliunits
+    pub amount: i64,
+    /// Not set for transfers, whose payee is managed by YNAB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payee_name: Option<String>,
+    pub memo: String,
+    /// What the bank reported when the values were written, missing in older state files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bank: Option<BankValues>,
+}
+
+/// SyncState remembers what was written to YNAB, to tell bank corrections from manual edits
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    #[serde(default)]
+    pub transactions: Vec<PushedTransaction>,
+}
+
+impl SyncState {
+    /// Last values written for a bank transaction to a YNAB account
+    pub fn find(&self, bank_id: &str, account_id: &str) -> Option<&PushedTransaction> {
+        self.transactions
+            .iter()
+            .find(|t| t.bank_id == bank_id && t.account_id == account_id)
+    }
+
+    /// Records values written to YNAB, replacing earlier values for the same account
+    pub fn record(&mut self, pushed: PushedTransaction) {
+        match self
+            .transactions
+            .iter_mut()
+            .find(|t| t.bank_id == pushed.bank_id && t.account_id == pushed.account_id)
+        {
+            Some(existing) => *existing = pushed,
+            None => self.transactions.push(pushed),
+        }
+    }
+
+    /// Forgets transactions dated before the given date
+    pub fn prune(&mut self, before: NaiveDate) {
+        self.transactions.retain(|t| t.date >= before);
+    }
+}
+
+/// Reads the sync state, empty if the file does not exist yet
+pub fn read_state(state_path: &str) -> Result<SyncState, StateError> {
+    debug!("Reading sync state from: {}", state_path);
+    let file = match File::open(state_path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            info!("No sync state at {}, starting empty", state_path);
+            return Ok(SyncState::default());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let state: SyncState = serde_json::from_reader(BufReader::new(file))?;
+
+    debug!(
+        "Loaded {} transactions from sync state",
+        state.transactions.len()
+    );
+    Ok(state)
+}
+
+/// Writes a file through a temporary file so an interrupted write leaves the old contents
+pub fn write_atomic(path: &str, contents: &str) -> std::io::Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path)
+}
+
+/// Writes the sync state, an interrupted write leaves the old state
+pub fn write_state(state_path: &str, state: &SyncState) -> Result<(), StateError> {
+    write_atomic(state_path, &serde_json::to_string_pretty(state)?)?;
+
+    debug!(
+        "Saved {} transactions to sync state",
+        state.transactions.len()
+    );
+    Ok(())
+}
+
+/// Changes to bring a YNAB transaction in line with the bank
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdatePlan {
+    /// Fields to change in YNAB, None when nothing needs changing
+    pub update: Option<UpdateYnabTransaction>,
+    /// Values to record as written after the update
+    pub recorded: PushedTransaction,
+    /// Fields the bank changed but the user had edited in YNAB, left as they are
+    pub user_edited: Vec<&'static str>,
+}
+
+/// Compares the bank's current values with what was written and what YNAB holds now
+///
+/// Only fields the bank changed since they were written are considered, so changed rules or
+/// memo templates never touch earlier imports. Such a field is updated when it still has the
+/// written value in YNAB; fields edited in YNAB since are never overwritten. Records without
+/// bank values are taken as up to date and get the bank's current values.
+pub fn plan_update(
+    recorded: &PushedTransaction,
+    current: &PushedTransaction,
+    in_ynab: &TransactionDetail,
+) -> UpdatePlan {
+    let no_update = UpdateYnabTransaction {
+        id: in_ynab.id.clone(),
+        ..Default::default()
+    };
+    let mut update = no_update.clone();
+    let mut next = recorded.clone();
+    next.bank = current.bank.clone();
+    let mut user_edited = Vec::new();
+
+    let (Some(bank), Some(current_bank)) = (&recorded.bank, &current.bank) else {
+        return UpdatePlan {
+            update: None,
+            recorded: next,
+            user_edited,
+        };
+    };
+
+    if current_bank.date != bank.date && current.date != recorded.date {
+        if in_ynab.date == recorded.date {
+            update.date = Some(current.date);
+            next.date = current.date;
+        } else {
+            user_edited.push("date");
+        }
+    }
+    if current_bank.amount != bank.amount && current.amount != recorded.amount {
+        if in_ynab.amount == recorded.amount {
+            update.amount = Some(current.amount);
+            next.amount = current.amount;
+        } else {
+            user_edited.push("amount");
+        }
+    }
+    if let (Some(current_payee), Some(recorded_payee)) = (&current.payee_name, &recorded.payee_name)
+    {
+        if current_bank.payee != bank.payee && current_payee != recorded_payee {
+            if in_ynab.payee_name.as_ref() == Some(recorded_payee) {
+                update.payee_name = Some(current_payee.clone());
+                next.payee_name = Some(current_payee.clone());
+            } else {
+                user_edited.push("payee");
+            }
+        }
+    }
+    // Memo templates can show any of the bank's fields
+    if current_bank != bank && current.memo != recorded.memo {
+        if in_ynab.memo.as_deref().unwrap_or_default() == recorded.memo {
+            update.memo = Some(current.memo.clone());
+            next.memo = current.memo.clone();
+        } else {
+            user_edited.push("memo");
+        }
+    }
+
+    UpdatePlan {
+        update: (update != no_update).then_some(update),
+        recorded: next,
+        user_edited,
+    }
+}
@@ -0,0 +1,881 @@
+use crate::account_config::{self, AccountConfig};
+use crate::alerts::{self, Alert, AlertConfig, AlertError, PayeeHistory};
+use crate::auth_data::{self, AuthError};
+use crate::balance::{self, BalanceCheck};
+use crate::config::{Config, ConfigError};
+use crate::learn::{self, CategoryHistory, LearnMode};
+use crate::profile::Profile;
+use crate::report::{AccountReport, AccountReports};
+use crate::rules::{self, RuleError, RuleSet, SplitLine};
+use crate::sparebanken1::{self, Sparebank1Error, Transaction};
+use crate::state::{self, StateError};
+use crate::transfers::{self, TransferSide};
+use crate::ynab::{
+    self, ImportSubTransaction, ImportTransaction, UpdateYnabTransaction, YnabClient,
+};
+use chrono::{NaiveDate, TimeDelta};
+use chrono_tz::Europe::Oslo;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::Instant;
+use tracing::{error, field, info, info_span, warn, Instrument};
+
+/// SyncError represents everything that can stop a profile from syncing
+#[derive(Debug, thiserror::Error)]
+pub enum SyncError {
+    #[error(transparent)]
+    ConfigError(#[from] ConfigError),
+
+    #[error(transparent)]
+    RuleError(#[from] RuleError),
+
+    #[error(transparent)]
+    AlertError(#[from] AlertError),
+
+    #[error(transparent)]
+    StateError(#[from] StateError),
+
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+
+    #[error(transparent)]
+    Sparebank1Error(#[from] Sparebank1Error),
+
+    #[error("YNAB request failed: {0}")]
+    YnabError(#[from] reqwest::Error),
+
+    /// Raised after the import, the summary holds what was synced before the balance check failed
+    #[error("{count} accounts differ from SpareBank1 by more than {tolerance} NOK")]
+    BalanceMismatch {
+        count: usize,
+        tolerance: f64,
+        summary: Box<SyncSummary>,
+    },
+}
+
+impl SyncError {
+    /// Summary of a sync that completed before the error was raised
+    pub fn summary(&self) -> Option<&SyncSummary> {
+        match self {
+            SyncError::BalanceMismatch { summary, .. } => Some(summary),
+            _ => None,
+        }
+    }
+}
+
+/// SyncSummary holds the outcome of syncing a single profile
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SyncSummary {
+    pub fetched: usize,
+    pub skipped: usize,
+    pub imported: usize,
+    pub duplicates: usize,
+    /// Pending transactions marked cleared after being booked
+    pub settled: usize,
+    /// Pending transactions flagged or deleted after disappearing from the bank
+    pub vanished: usize,
+    /// Earlier imports updated after the bank changed them
+    pub updated: usize,
+    /// Accounts whose YNAB cleared balance differs from the bank beyond the tolerance
+    pub balance_mismatches: usize,
+    /// Transactions marked reconciled in YNAB
+    pub reconciled: usize,
+    /// Balance adjustments posted to balance-only accounts
+    pub adjusted: usize,
+    /// Imports categorised or renamed from YNAB history
+    pub learned: usize,
+    /// Imported transactions worth a closer look, or that would be in dry-run mode
+    pub alerts: Vec<Alert>,
+    /// Outcome per SpareBank1 account
+    pub accounts: Vec<AccountReport>,
+}
+
+impl SyncSummary {
+    /// Summary of a sync result, also of a failed one when the sync completed before failing
+    ///
+    /// A failed balance check comes after the import, so its transactions still count.
+    pub fn of(result: &Result<SyncSummary, SyncError>) -> Option<&SyncSummary> {
+        match result {
+            Ok(summary) => Some(summary),
+            Err(e) => e.summary(),
+        }
+    }
+}
+
+/// Days transactions are kept in the sync state
+const STATE_RETENTION_DAYS: i64 = 90;
+
+/// A transaction left out of the import by a skip rule
+#[derive(Debug)]
+pub struct SkippedTransaction {
+    pub transaction: Transaction,
+    pub rule: String,
+}
+
+/// Fetched transactions split into those to import and those skipped by rules
+#[derive(Debug, Default)]
+pub struct PreparedTransactions {
+    pub imports: Vec<ImportTransaction>,
+    pub skipped: Vec<SkippedTransaction>,
+    /// Transactions left out because their account is not imported, such as balance-only accounts
+    pub not_imported: usize,
+}
+
+/// Loads the rules configured for a profile, or an empty rule set if none are configured
+pub fn load_rules(config: &Config) -> Result<RuleSet, SyncError> {
+    match &config.rules_config_path {
+        Some(path) => {
+            info!("Loading rules from {}", path);
+            Ok(rules::read_rules_json(path)?)
+        }
+        None => Ok(RuleSet::default()),
+    }
+}
+
+/// Loads the alert settings of a profile, alerting on nothing if none are configured
+pub fn load_alerts(config: &Config) -> Result<AlertConfig, SyncError> {
+    match &config.alerts_config_path {
+        Some(path) => {
+            info!("Loading alerts from {}", path);
+            Ok(alerts::read_alerts_json(path)?)
+        }
+        None => Ok(AlertConfig::default()),
+    }
+}
+
+/// Checks that the account mapping, rules and alerts of a profile can be loaded
+pub fn validate_profile(config: &Config) -> Result<(), SyncError> {
+    account_config::read_accounts_json(&config.account_config_path)?;
+    load_rules(config)?;
+    load_alerts(config)?;
+    Ok(())
+}
+
+/// Applies rules to fetched transactions and links transfers between mapped accounts
+///
+/// Transactions for which `imported` is false are dropped without being evaluated.
+pub fn prepare_transactions(
+    transactions: Vec<Transaction>,
+    rule_set: &RuleSet,
+    imported: impl Fn(&Transaction) -> bool,
+) -> PreparedTransactions {
+    // Import ids are numbered over everything fetched so skipping never shifts them
+    let import_ids = ynab::generate_import_ids(&transactions);
+    let mut prepared = PreparedTransactions::default();
+
+    for (transaction, import_id) in transactions.into_iter().zip(import_ids) {
+        if !imported(&transaction) {
+            prepared.not_imported += 1;
+            continue;
+        }
+        let outcome = rule_set.evaluate(&transaction);
+        if let Some(rule) = outcome.skipped_by {
+            prepared
+                .skipped
+                .push(SkippedTransaction { transaction, rule });
+            continue;
+        }
+
+        let mut import = ImportTransaction::new(transaction, import_id);
+        if let Some(payee) = outcome.payee {
+            import.payee_name = payee;
+        }
+        import.category = outcome.category;
+        import.review = outcome.review;
+        import.merchant_category = outcome.merchant_category;
+        import.flag = outcome.flag;
+        if let Some(split) = outcome.split {
+            import.subtransactions = split_transaction(&import.transaction, &split);
+        }
+        prepared.imports.push(import);
+    }
+
+    let linked = transfers::link_transfers(&mut prepared.imports);
+    if linked > 0 {
+        info!("Found {} transfers between mapped accounts", linked);
+    }
+
+    prepared
+}
+
+/// Category shown for a transaction in previews
+fn category_label(import: &ImportTransaction) -> &str {
+    match &import.category {
+        Some(category) => category,
+        None if !import.subtransactions.is_empty() => "split",
+        None => "uncategorised",
+    }
+}
+
+/// Original amount and exchange rate of a foreign purchase, shown in previews
+fn foreign_label(transaction: &Transaction) -> Option<String> {
+    if !transaction.is_foreign() {
+        return None;
+    }
+    let amount = transaction
+        .original_amount
+        .map(|amount| format!("{:.2} ", amount))
+        .unwrap_or_default();
+    let rate = transaction
+        .foreign_exchange_rate()
+        .map(|rate| format!(" at {:.4}", rate))
+        .unwrap_or_default();
+    Some(format!(
+        "foreign: {}{}{}",
+        amount,
+        transaction.currency(),
+        rate
+    ))
+}
+
+/// Builds split lines for a transaction, empty if the split does not add up
+fn split_transaction(transaction: &Transaction, split: &[SplitLine]) -> Vec<ImportSubTransaction> {
+    let total = ynab::to_milliunits(transaction.amount);
+    match rules::split_amounts(split, total) {
+        Some(amounts) => split
+            .iter()
+            .zip(amounts)
+            .map(|(line, amount)| ImportSubTransaction {
+                amount,
+                category: line.category.clone(),
+                memo: line.memo.clone(),
+            })
+            .collect(),
+        None => {
+            warn!(
+                "Split does not add up to {} NOK for {}, importing unsplit",
+                transaction.amount, transaction.payee
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// Data fetched from SpareBank1 for a profile
+struct FetchedTransactions {
+    access_token: String,
+    account_config: AccountConfig,
+    transactions: Vec<Transaction>,
+}
+
+/// Refreshes the access token and fetches transactions for all mapped accounts
+async fn fetch_transactions(config: &Config) -> Result<FetchedTransactions, SyncError> {
+    info!("Fetching access token");
+    let access_token = match auth_data::get_access_token(config).await {
+        Ok(token) => {
+            info!("Successfully obtained access token");
+            token
+        }
+        Err(e) => {
+            error!("Failed to get access token: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    info!(
+        "Loading account configuration from {}",
+        config.account_config_path
+    );
+    let account_config = account_config::read_accounts_json(&config.account_config_path)?;
+    let accounts: Vec<String> = account_config.keys().cloned().collect();
+    info!("Configured accounts: {}", accounts.len());
+
+    info!("Fetching transactions from SpareBank1");
+    let transactions = sparebanken1::get_transactions(&access_token, accounts).await?;
+    info!("Retrieved {} transactions", transactions.len());
+
+    Ok(FetchedTransactions {
+        access_token,
+        account_config,
+        transactions,
+    })
+}
+
+/// Formats milliunits as NOK
+fn format_nok(milliunits: i64) -> String {
+    format!("{:.2} NOK", milliunits as f64 / 1000.0)
+}
+
+/// Compares SpareBank1 balances with YNAB cleared balances
+async fn check_balances(
+    config: &Config,
+    access_token: &str,
+    account_config: &AccountConfig,
+    ynab_client: &YnabClient,
+) -> Result<Vec<BalanceCheck>, SyncError> {
+    info!("Checking balances against SpareBank1");
+    let bank_accounts = sparebanken1::get_accounts(access_token).await?;
+
+    let budgets: BTreeSet<&str> = account_config
+        .values()
+        .flatten()
+        .map(|target| target.budget_id(&config.ynab_budget_id))
+        .collect();
+    let mut ynab_accounts = HashMap::new();
+    for budget_id in budgets {
+        let accounts = ynab_client.get_accounts_for_budget(budget_id).await?;
+        ynab_accounts.insert(budget_id.to_string(), accounts);
+    }
+
+    let checks = balance::compare_balances(
+        account_config,
+        &config.ynab_budget_id,
+        &bank_accounts,
+        &ynab_accounts,
+    );
+    Ok(checks)
+}
+
+/// Logs how a YNAB account's cleared balance compares with its SpareBank1 account
+fn log_balance_check(check: &BalanceCheck, tolerance: i64) {
+    if check.balance_only && !check.matches(0) {
+        info!(
+            "  [balance adjustment] {} -> {} | bank {} | YNAB cleared {} | adjustment {}",
+            check.account_name,
+            check.ynab_account_name,
+            format_nok(check.bank_balance),
+            format_nok(check.ynab_cleared_balance),
+            format_nok(-check.difference())
+        );
+    } else if check.matches(tolerance) {
+        info!(
+            "  [balance ok] {} -> {} | {}",
+            check.account_name,
+            check.ynab_account_name,
+            format_nok(check.bank_balance)
+        );
+    } else {
+        warn!(
+            "  [balance mismatch] {} -> {} | bank {} | YNAB cleared {} | difference {}",
+            check.account_name,
+            check.ynab_account_name,
+            format_nok(check.bank_balance),
+            format_nok(check.ynab_cleared_balance),
+            format_nok(check.difference())
+        );
+    }
+}
+
+/// Posts an adjustment to every balance-only account that differs from the bank
+///
+/// Checks are updated to the bank balance only when their adjustment was posted, not when YNAB
+/// already had it from an earlier run today. Returns the number of adjustments posted, or that
+/// would be in dry-run mode.
+async fn adjust_balances(
+    checks: &mut [BalanceCheck],
+    ynab_client: &YnabClient,
+    dry_run: bool,
+) -> Result<usize, SyncError> {
+    let today = chrono::Utc::now().with_timezone(&Oslo).date_naive();
+    let adjustments = balance::adjustments(checks, today);
+    if dry_run {
+        for adjustment in &adjustments {
+            info!(
+                "  [adjust] {}: would post {}",
+                adjustment.account_name,
+                format_nok(adjustment.amount)
+            );
+        }
+        return Ok(adjustments.len());
+    }
+
+    let mut posted = 0;
+    for adjustment in &adjustments {
+        if !ynab_client.add_balance_adjustment(adjustment).await? {
+            warn!(
+                "  [adjust] {}: adjustment of {} already posted today",
+                adjustment.account_name,
+                format_nok(adjustment.amount)
+            );
+            continue;
+        }
+        info!(
+            "  [adjust] {}: posted {}",
+            adjustment.account_name,
+            format_nok(adjustment.amount)
+        );
+        posted += 1;
+        // Only a posted adjustment brings YNAB in line with the bank
+        for check in checks.iter_mut().filter(|c| {
+            c.balance_only
+                && c.budget_id == adjustment.budget_id
+                && c.ynab_account_id == adjustment.account_id
+        }) {
+            check.ynab_cleared_balance = check.bank_balance;
+        }
+    }
+
+    Ok(posted)
+}
+
+/// Date of the latest booked transaction on each SpareBank1 account, in Oslo time
+fn latest_booked_dates(transactions: &[Transaction]) -> HashMap<String, NaiveDate> {
+    let mut latest: HashMap<String, NaiveDate> = HashMap::new();
+    for transaction in transactions.iter().filter(|t| !t.is_pending()) {
+        let date = transaction.date.with_timezone(&Oslo).date_naive();
+        let entry = latest.entry(transaction.account.clone()).or_insert(date);
+        *entry = (*entry).max(date);
+    }
+    latest
+}
+
+/// Marks cleared transactions reconciled in accounts whose balance matches the bank exactly
+///
+/// Transactions are reconciled up to the latest booked date fetched from SpareBank1 for the
+/// account. Returns the number of transactions reconciled, or that would be in dry-run mode.
+async fn reconcile_accounts(
+    checks: &[BalanceCheck],
+    latest_booked: &HashMap<String, NaiveDate>,
+    ynab_client: &YnabClient,
+    dry_run: bool,
+) -> Result<usize, SyncError> {
+    let mut reconciled = 0;
+
+    for check in checks {
+        // A tolerated difference would be locked in by reconciling, so only exact matches count
+        if !check.matches(0) {
+            info!(
+                "  [reconcile] {}: balance differs, not reconciled",
+                check.ynab_account_name
+            );
+            continue;
+        }
+        let Some(&up_to) = latest_booked.get(&check.account_key) else {
+            info!(
+                "  [reconcile] {}: no booked transactions fetched, not reconciled",
+                check.ynab_account_name
+            );
+            continue;
+        };
+
+        let transactions = ynab_client
+            .get_account_transactions(&check.budget_id, &check.ynab_account_id)
+            .await?;
+        let updates: Vec<UpdateYnabTransaction> = balance::reconcilable(&transactions, up_to)
+            .into_iter()
+            .map(|t| UpdateYnabTransaction {
+                id: t.id.clone(),
+                cleared: Some("reconciled".to_string()),
+                ..Default::default()
+            })
+            .collect();
+        if updates.is_empty() {
+            continue;
+        }
+
+        let verb = if dry_run {
+            "would reconcile"
+        } else {
+            "reconciled"
+        };
+        info!(
+            "  [reconcile] {}: {} {} transactions up to {}",
+            check.ynab_account_name,
+            verb,
+            updates.len(),
+            up_to
+        );
+        reconciled += updates.len();
+        if !dry_run {
+            ynab_client
+                .update_transactions(&check.budget_id, updates)
+                .await?;
+        }
+    }
+
+    Ok(reconciled)
+}
+
+/// Runs a full sync for one profile: refresh token, fetch transactions and import to YNAB
+pub async fn sync_profile(profile: &Profile, dry_run: bool) -> Result<SyncSummary, SyncError> {
+    let config = &profile.config;
+    let rule_set = load_rules(config)?;
+    let alert_config = load_alerts(config)?;
+    let FetchedTransactions {
+        access_token,
+        account_config,
+        transactions,
+    } = fetch_transactions(config).await?;
+
+    let mut summary = SyncSummary {
+        fetched: transactions.len(),
+        ..Default::default()
+    };
+    let latest_booked = latest_booked_dates(&transactions);
+    let mut reports = AccountReports::new(account_config.keys());
+    reports.add_fetched(&transactions);
+
+    // Balance-only accounts are kept in sync by adjustments, their transactions are not imported
+    let import_config = account_config::import_targets(&account_config);
+    let PreparedTransactions {
+        imports: mut transactions,
+        skipped,
+        not_imported,
+    } = prepare_transactions(transactions, &rule_set, |t| {
+        import_config.contains_key(&t.account)
+    });
+    if not_imported > 0 {
+        info!(
+            "Not importing {} transactions from balance-only accounts",
+            not_imported
+        );
+    }
+    summary.skipped = skipped.len();
+    for skipped_transaction in &skipped {
+        reports
+            .account(&skipped_transaction.transaction.account)
+            .skipped += 1;
+    }
+
+    if !skipped.is_empty() {
+        info!("Skipped {} transactions by rule", skipped.len());
+        for skipped_transaction in &skipped {
+            let transaction = &skipped_transaction.transaction;
+            info!(
+                "  [skip] {} | {} | {} NOK | {} | {}",
+                transaction.date.format("%Y-%m-%d"),
+                transaction.payee,
+                transaction.amount,
+                transaction.description,
+                skipped_transaction.rule
+            );
+        }
+    }
+
+    // Create YnabClient instance
+    let ynab_client = YnabClient::new(
+        import_config,
+        config.ynab_access_token.clone(),
+        config.ynab_budget_id.clone(),
+    )
+    .with_memo_template(config.memo_template.clone());
+
+    let learning = config.learn_categories != LearnMode::Off;
+    let history = if learning || alert_config.needs_history() {
+        let today = chrono::Utc::now().with_timezone(&Oslo).date_naive();
+        ynab_client
+            .get_transactions_for_budget(
+                &config.ynab_budget_id,
+                today - TimeDelta::days(learn::HISTORY_DAYS),
+            )
+            .await?
+    } else {
+        Vec::new()
+    };
+    if learning {
+        summary.learned = learn::apply_history(
+            &mut transactions,
+            &CategoryHistory::learn(&history),
+            config.learn_categories,
+            config.learn_confidence,
+        );
+        info!(
+            "Categorised {} transactions from YNAB history",
+            summary.learned
+        );
+    }
+
+    // Dry runs only look, settling and updating earlier imports changes YNAB
+    let recent = ynab_client.get_recent_transactions(&transactions).await?;
+    let mut sync_state = match (&config.sync_state_path, dry_run) {
+        (Some(path), false) => Some(state::read_state(path)?),
+        _ => None,
+    };
+    if !dry_run {
+        let pending = ynab_client
+            .settle_pending(&mut transactions, &recent, config.pending_vanished)
+            .await?;
+        summary.settled = pending.settled;
+        summary.vanished = pending.vanished;
+    }
+    if let Some(sync_state) = sync_state.as_mut() {
+        let updated = ynab_client
+            .update_changed(&mut transactions, &recent, sync_state)
+            .await?;
+        summary.updated = updated.len();
+        for id in &updated {
+            if let Some(import) = transactions.iter().find(|i| &i.transaction.id == id) {
+                reports.account(&import.transaction.account).updated += 1;
+            }
+        }
+    }
+
+    // A transfer is only posted as one unless a side is in YNAB as a plain transaction
+    let unlinked = transfers::unlink_imported(&mut transactions, |import| {
+        ynab_client.imported_as_plain(import, &recent)
+    });
+    if unlinked > 0 {
+        info!(
+            "Importing {} transfers as plain transactions, one side was imported earlier",
+            unlinked
+        );
+    }
+
+    let mut alerts = alerts::check_transactions(
+        &mut transactions,
+        &PayeeHistory::learn(&history),
+        &alert_config,
+    );
+
+    if dry_run {
+        // Dry-run mode: display transactions without importing
+        info!(
+            "DRY-RUN: Would import {} transactions to YNAB",
+            transactions.len()
+        );
+
+        for (index, import) in transactions.iter().enumerate() {
+            let transaction = &import.transaction;
+            info!(
+                "  [{}] {} | {} | {} NOK | {} | {}",
+                index + 1,
+                transaction.date.format("%Y-%m-%d"),
+                import.payee_name,
+                transaction.amount,
+                transaction.description,
+                category_label(import)
+            );
+            if transaction.is_pending() {
+                info!("      pending, imported uncleared");
+            }
+            if let Some(foreign) = foreign_label(transaction) {
+                info!("      {}", foreign);
+            }
+            if let Some(flag) = import.flag {
+                info!("      flagged {}", flag);
+            }
+            if let Some(learned) = &import.learned {
+                info!("      learned {}", learned.explain());
+            }
+            for alert in alerts
+                .iter()
+                .filter(|a| a.transaction_id == import.transaction.id)
+            {
+                info!("      alert: {}", alert.reason());
+            }
+            if let Some(link) = &import.transfer {
+                let direction = match link.side {
+                    TransferSide::Outgoing => "to",
+                    TransferSide::Incoming => "from",
+                };
+                info!("      transfer {} {}", direction, link.account);
+            }
+            for line in &import.subtransactions {
+                info!(
+                    "      {} NOK | {} | {}",
+                    line.amount as f64 / 1000.0,
+                    line.category.as_deref().unwrap_or("uncategorised"),
+                    line.memo.as_deref().unwrap_or("")
+                );
+            }
+        }
+
+        let now = chrono::offset::Local::now();
+        info!("Dry-run completed at {}", now);
+        warn!("DRY-RUN MODE: No transactions were actually sent to YNAB");
+    } else {
+        // Normal mode: import transactions to YNAB
+        let accounts: HashMap<String, String> = transactions
+            .iter()
+            .map(|i| (i.transaction.id.clone(), i.transaction.account.clone()))
+            .collect();
+
+        info!("Importing transactions to YNAB");
+        let ynab_response = ynab_client.add_transactions(transactions).await?;
+
+        if let (Some(path), Some(mut sync_state)) = (&config.sync_state_path, sync_state) {
+            for pushed in ynab_response.pushed.iter().cloned() {
+                sync_state.record(pushed);
+            }
+            let today = chrono::offset::Local::now().date_naive();
+            sync_state.prune(today - TimeDelta::days(STATE_RETENTION_DAYS));
+            state::write_state(path, &sync_state)?;
+        }
+
+        let now = chrono::offset::Local::now();
+        info!("Sync completed at {}", now);
+        info!(
+            "Added {} new transactions",
+            ynab_response.transaction_ids.len()
+        );
+        info!(
+            "Skipped {} duplicate transactions",
+            ynab_response.duplicate_import_ids.len()
+        );
+
+        summary.imported = ynab_response.transaction_ids.len();
+        summary.duplicates = ynab_response.duplicate_import_ids.len();
+        for pushed in &ynab_response.pushed {
+            if let Some(account) = accounts.get(&pushed.bank_id) {
+                reports.account(account).imported += 1;
+            }
+        }
+        for id in &ynab_response.duplicate_bank_ids {
+            if let Some(account) = accounts.get(id) {
+                reports.account(account).duplicates += 1;
+            }
+        }
+
+        // Transactions already in YNAB were alerted on when they were imported
+        let pushed: HashSet<&str> = ynab_response
+            .pushed
+            .iter()
+            .map(|p| p.bank_id.as_str())
+            .collect();
+        alerts.retain(|alert| pushed.contains(alert.transaction_id.as_str()));
+        for alert in &alerts {
+            warn!("  [alert] {}", alert.explain());
+        }
+    }
+
+    for alert in &alerts {
+        reports.account(&alert.account).alerts += 1;
+    }
+    summary.alerts = alerts;
+
+    let mut checks = check_balances(config, &access_token, &account_config, &ynab_client).await?;
+    let tolerance = (config.balance_tolerance * 1000.0).round() as i64;
+
+    // Balances are checked, adjusted and reconciled in a span per account
+    checks.sort_by(|a, b| a.account_key.cmp(&b.account_key));
+    let mut account_spans = BTreeMap::new();
+    for account in account_config.keys().collect::<BTreeSet<_>>() {
+        let span = info_span!(
+            "account",
+            account = %account,
+            account_name = field::Empty,
+            fetched = field::Empty,
+            skipped = field::Empty,
+            imported = field::Empty,
+            duplicates = field::Empty,
+            updated = field::Empty,
+            alerts = field::Empty,
+            duration_ms = field::Empty,
+        );
+        let started = Instant::now();
+        let start = checks.partition_point(|c| c.account_key < *account);
+        let end = checks.partition_point(|c| c.account_key <= *account);
+        let account_checks = &mut checks[start..end];
+
+        async {
+            for check in account_checks.iter() {
+                log_balance_check(check, tolerance);
+            }
+            summary.adjusted += adjust_balances(account_checks, &ynab_client, dry_run).await?;
+            if config.reconcile {
+                summary.reconciled +=
+                    reconcile_accounts(account_checks, &latest_booked, &ynab_client, dry_run)
+                        .await?;
+            }
+            Ok::<(), SyncError>(())
+        }
+        .instrument(span.clone())
+        .await?;
+
+        span.record("duration_ms", started.elapsed().as_millis() as u64);
+        account_spans.insert(account.clone(), span);
+    }
+
+    summary.balance_mismatches = checks
+        .iter()
+        .filter(|c| !c.balance_only && !c.matches(tolerance))
+        .count();
+    for check in &checks {
+        reports.add_balance(check, tolerance);
+    }
+    summary.accounts = reports.finish();
+    // The spans close with the counts of their account when the profile is done
+    for account in &summary.accounts {
+        let Some(span) = account_spans.get(&account.account) else {
+            continue;
+        };
+        if let Some(name) = &account.name {
+            span.record("account_name", name.as_str());
+        }
+        span.record("fetched", account.fetched);
+        span.record("skipped", account.skipped);
+        span.record("imported", account.imported);
+        span.record("duplicates", account.duplicates);
+        span.record("updated", account.updated);
+        span.record("alerts", account.alerts);
+    }
+    if summary.balance_mismatches > 0 && config.fail_on_balance_mismatch && !dry_run {
+        return Err(SyncError::BalanceMismatch {
+            count: summary.balance_mismatches,
+            tolerance: config.balance_tolerance,
+            summary: Box::new(summary),
+        });
+    }
+
+    Ok(summary)
+}
+
+/// Previews which rules match the profile's recent transactions without importing anything
+pub async fn test_rules(
+    profile: &Profile,
+    rules_config_path: Option<&str>,
+    matched_only: bool,
+) -> Result<(), SyncError> {
+    let rule_set = match rules_config_path {
+        Some(path) => rules::read_rules_json(path)?,
+        None => load_rules(&profile.config)?,
+    };
+    let FetchedTransactions { transactions, .. } = fetch_transactions(&profile.config).await?;
+
+    let mut matched = 0;
+    for transaction in &transactions {
+        let outcome = rule_set.evaluate(transaction);
+        if outcome.matched_rules.is_empty() {
+            if !matched_only {
+                println!(
+                    "{} | {} | {} NOK | no rule matched",
+                    transaction.date.format("%Y-%m-%d"),
+                    transaction.payee,
+                    transaction.amount
+                );
+            }
+            continue;
+        }
+
+        matched += 1;
+        println!(
+            "{} | {} | {} NOK | {}",
+            transaction.date.format("%Y-%m-%d"),
+            transaction.payee,
+            transaction.amount,
+            outcome.matched_rules.join(", ")
+        );
+        if outcome.skipped_by.is_some() {
+            println!("    skipped");
+        }
+        if let Some(payee) = &outcome.payee {
+            println!("    payee: {} -> {}", transaction.payee, payee);
+        }
+        if let Some(flag) = outcome.flag {
+            println!("    flag: {}", flag);
+        }
+        let review = if outcome.review { " (review)" } else { "" };
+        if let Some(category) = &outcome.category {
+            println!("    category: {}{}", category, review);
+        }
+        if let Some(split) = &outcome.split {
+            println!("    split{}:", review);
+            for line in split_transaction(transaction, split) {
+                println!(
+                    "      {} NOK | {} | {}",
+                    line.amount as f64 / 1000.0,
+                    line.category.as_deref().unwrap_or("uncategorised"),
+                    line.memo.as_deref().unwrap_or("")
+                );
+            }
+        }
+    }
+
+    println!(
+        "Profile {}: {} of {} transactions matched a rule",
+        profile.name,
+        matched,
+        transactions.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use crate::sparebanken1::Transaction;
+use crate::ynab::{self, ImportTransaction};
+use std::collections::HashSet;
+use tracing::debug;
+
+/// Words in a SpareBank1 description that mark a transaction as a transfer between own accounts
+const TRANSFER_KEYWORDS: &[&str] = &["overføring", "overført", "overf.", "transfer", "nettbank"];
+
+/// Largest number of days between the two sides of a transfer
+const MAX_TRANSFER_DAYS: i64 = 3;
+
+/// Which side of a transfer a transaction is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferSide {
+    /// Money leaving the account, imported as the YNAB transfer
+    Outgoing,
+    /// Money arriving, created by YNAB from the outgoing side
+    Incoming,
+}
+
+/// Link from one side of a transfer to the SpareBank1 account on the other side
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferLink {
+    pub account: String,
+    /// SpareBank1 id of the transaction on the other side
+    pub transaction_id: String,
+    pub side: TransferSide,
+}
+
+/// Whether a transaction's description looks like a transfer to or from the counterpart account
+fn is_transfer_like(transaction: &Transaction, counterpart: &Transaction) -> bool {
+    let description = transaction.description.to_lowercase();
+    let payee = transaction.payee.to_lowercase();
+    let mentions = |needle: &str| description.contains(needle) || payee.contains(needle);
+
+    TRANSFER_KEYWORDS.iter().any(|keyword| mentions(keyword))
+        || counterpart
+            .account_name
+            .as_ref()
+            .is_some_and(|name| !name.trim().is_empty() && mentions(&name.to_lowercase()))
+}
+
+/// Whether two transactions are the outgoing and incoming side of the same transfer
+fn is_transfer_pair(outgoing: &Transaction, incoming: &Transaction) -> bool {
+    let days_apart = (outgoing.date - incoming.date).num_days().abs();
+
+    // Reserved amounts may still change, so only booked transactions are paired
+    outgoing.account != incoming.account
+        && !outgoing.is_pending()
+        && !incoming.is_pending()
+        && outgoing.amount < 0.0
+        && ynab::to_milliunits(outgoing.amount) == -ynab::to_milliunits(incoming.amount)
+        && days_apart <= MAX_TRANSFER_DAYS
+        && (is_transfer_like(outgoing, incoming) || is_transfer_like(incoming, outgoing))
+}
+
+/// Pairs outgoing and incoming transactions between mapped accounts and links them as transfers
+///
+/// Each outgoing transaction is paired with the closest matching incoming one by date.
+/// Returns the number of transfers found.
+pub fn link_transfers(imports: &mut [ImportTransaction]) -> usize {
+    let mut linked = 0;
+
+    for outgoing in 0..imports.len() {
+        if imports[outgoing].transfer.is_some() || imports[outgoing].transaction.amount >= 0.0 {
+            continue;
+        }
+
+        let incoming = (0..imports.len())
+            .filter(|&candidate| imports[candidate].transfer.is_none())
+            .filter(|&candidate| {
+                is_transfer_pair(
+                    &imports[outgoing].transaction,
+                    &imports[candidate].transaction,
+                )
+            })
+            .min_by_key(|&candidate| {
+                (imports[outgoing].transaction.date - imports[candidate].transaction.date)
+                    .num_seconds()
+                    .abs()
+            });
+
+        if let Some(incoming) = incoming {
+            let outgoing_account = imports[outgoing].transaction.account.clone();
+            let incoming_account = imports[incoming].transaction.account.clone();
+            let outgoing_id = imports[outgoing].transaction.id.clone();
+            let incoming_id = imports[incoming].transaction.id.clone();
+            debug!(
+                "Linked transfer of {} NOK from {} to {}",
+                imports[incoming].transaction.amount, outgoing_account, incoming_account
+            );
+
+            imports[outgoing].transfer = Some(TransferLink {
+                account: incoming_account,
+                transaction_id: incoming_id,
+                side: TransferSide::Outgoing,
+            });
+            imports[incoming].transfer = Some(TransferLink {
+                account: outgoing_account,
+                transaction_id: outgoing_id,
+                side: TransferSide::Incoming,
+            });
+            linked += 1;
+        }
+    }
+
+    linked
+}
+
+/// Unlinks transfers where either side was imported as a plain transaction on an earlier run
+///
+/// Posting a transfer would then create the imported side in YNAB a second time, or leave
+/// the side YNAB should create out. Both sides are imported as ordinary transactions
+/// instead. A transfer posted as one on an earlier run stays linked, so YNAB's side of it is
+/// not posted again. Returns the number of transfers unlinked.
+pub fn unlink_imported(
+    imports: &mut [ImportTransaction],
+    imported_as_plain: impl Fn(&ImportTransaction) -> bool,
+) -> usize {
+    let imported: HashSet<String> = imports
+        .iter()
+        .filter(|import| import.transfer.is_some() && imported_as_plain(import))
+        .map(|import| import.transaction.id.clone())
+        .collect();
+
+    let mut unlinked = 0;
+    for import in imports.iter_mut() {
+        let Some(link) = &import.transfer else {
+            continue;
+        };
+        if imported.contains(&import.transaction.id) || imported.contains(&link.transaction_id) {
+            debug!(
+                "Importing {} NOK on {} as a plain transaction, one side is in YNAB as one",
+                import.transaction.amount, import.transaction.account
+            );
+            if link.side == TransferSide::Outgoing {
+                unlinked += 1;
+            }
+            import.transfer = None;
+        }
+    }
+
+    unlinked
+}
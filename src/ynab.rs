@@ -1,25 +1,162 @@
+use crate::account_config::{AccountConfig, AccountTarget};
+use crate::balance::{self, BalanceAdjustment};
+use crate::config::Config;
+use crate::learn::Suggestion;
+use crate::memo::{self, MemoTemplate};
+use crate::metrics::{Api, RecordedSend};
+use crate::pending::{self, PendingSummary, PostedImport, VanishedPending};
+use crate::rules::FlagColor;
 use crate::sparebanken1;
+use crate::state::{self, BankValues, PushedTransaction, SyncState};
+use crate::transfers::{TransferLink, TransferSide};
+use chrono::NaiveDate;
 use chrono_tz::Europe::Oslo;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tracing::{debug, error, info};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tracing::{debug, error, info, warn};
 
 const BASE_API_URL: &str = "https://api.ynab.com/v1";
 
+/// Base URL of the YNAB API, overridden by YNAB_API_URL
+fn base_api_url() -> String {
+    Config::api_url("YNAB_API_URL", BASE_API_URL)
+}
+
+#[derive(Debug, Serialize)]
+struct CreateYnabTransactionRequest<'a> {
+    transactions: &'a [CreateYnabTransaction],
+}
+
+/// A transaction as posted to a YNAB budget
 #[derive(Debug, Serialize)]
-struct CreateYnabTransactionRequest {
-    transactions: Vec<CreateYnabTransaction>,
+pub struct CreateYnabTransaction {
+    /// SpareBank1 transaction id, recorded in the sync state but not sent
+    #[serde(skip)]
+    pub bank_id: String,
+    /// SpareBank1's values, recorded in the sync state but not sent
+    #[serde(skip)]
+    pub bank: Option<BankValues>,
+    pub date: NaiveDate,
+    pub account_id: String,
+    pub amount: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payee_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payee_name: Option<String>,
+    pub cleared: String,
+    pub memo: String,
+    pub import_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub approved: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flag_color: Option<FlagColor>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subtransactions: Vec<CreateYnabSubTransaction>,
 }
 
 #[derive(Debug, Serialize)]
-struct CreateYnabTransaction {
-    date: String,
-    account_id: String,
-    amount: i64,
-    payee_name: String,
-    cleared: String,
-    memo: String,
-    import_id: String,
+pub struct CreateYnabSubTransaction {
+    pub amount: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+/// One line of a split transaction, amount in milliunits
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportSubTransaction {
+    pub amount: i64,
+    pub category: Option<String>,
+    pub memo: Option<String>,
+}
+
+/// A SpareBank1 transaction together with the values it is imported to YNAB with
+#[derive(Debug)]
+pub struct ImportTransaction {
+    pub transaction: sparebanken1::Transaction,
+    pub import_id: String,
+    pub payee_name: String,
+    /// YNAB category name, resolved to an id in each budget the transaction is posted to
+    pub category: Option<String>,
+    /// Leave a categorised transaction unapproved so it shows up for review in YNAB
+    pub review: bool,
+    /// Category set from the merchant category code, a learned category replaces it
+    pub merchant_category: bool,
+    /// Split lines, the transaction is imported as a split when not empty
+    pub subtransactions: Vec<ImportSubTransaction>,
+    pub flag: Option<FlagColor>,
+    /// Category or payee learned from YNAB history
+    pub learned: Option<Suggestion>,
+    /// Set when the transaction is one side of a transfer between mapped accounts
+    pub transfer: Option<TransferLink>,
+    /// YNAB accounts where this transaction is already imported under another import id,
+    /// as the pending transaction it settled or before the bank changed it
+    pub already_imported: Vec<String>,
+    /// Set when the transaction was posted as a transfer under another import id
+    pub already_imported_as_transfer: bool,
+}
+
+impl ImportTransaction {
+    pub fn new(transaction: sparebanken1::Transaction, import_id: String) -> Self {
+        Self {
+            payee_name: transaction.payee.clone(),
+            import_id,
+            category: None,
+            review: false,
+            merchant_category: false,
+            subtransactions: Vec::new(),
+            flag: None,
+            learned: None,
+            transfer: None,
+            already_imported: Vec::new(),
+            already_imported_as_transfer: false,
+            transaction,
+        }
+    }
+}
+
+/// Import id prefix of pending transactions, kept until they are settled
+pub const PENDING_IMPORT_PREFIX: &str = "SB1P";
+
+/// Import id prefix of balance adjustments
+pub const ADJUSTMENT_IMPORT_PREFIX: &str = "SB1B";
+
+/// Converts a NOK amount to YNAB milliunits, rounded to the nearest milliunit
+pub fn to_milliunits(amount: f64) -> i64 {
+    (amount * 1000.0).round() as i64
+}
+
+/// Builds YNAB import ids in the form `SB1:{amount}:{date}:{occurrence}`
+///
+/// The occurrence counts earlier transactions with the same amount and date, so ids
+/// must be generated from the full list fetched from SpareBank1 to stay stable.
+/// Pending transactions use the `SB1P` prefix and are counted separately.
+pub fn generate_import_ids(transactions: &[sparebanken1::Transaction]) -> Vec<String> {
+    let mut imported_prefixes: Vec<String> = Vec::new();
+
+    transactions
+        .iter()
+        .map(|t| {
+            let formated_date = t.date.with_timezone(&Oslo).format("%Y-%m-%d");
+
+            // Check if same transactions has been imported before
+            let prefix = if t.is_pending() {
+                PENDING_IMPORT_PREFIX
+            } else {
+                "SB1"
+            };
+            let import_prefix = format!("{}:{}:{}", prefix, t.amount, formated_date);
+            imported_prefixes.push(import_prefix.clone());
+            let import_count = imported_prefixes
+                .iter()
+                .filter(|id| id.starts_with(&import_prefix))
+                .count();
+            format!("{}:{}", import_prefix, import_count)
+        })
+        .collect()
 }
 
 #[derive(Debug, Deserialize)]
@@ -27,22 +164,51 @@ struct CreateYnabTransactionResponse {
     data: CreateYnabTransactionResponseData,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct CreateYnabTransactionResponseData {
     pub transaction_ids: Vec<String>,
     pub duplicate_import_ids: Vec<String>,
 }
 
+/// Outcome of importing transactions to all budgets
+#[derive(Debug, Default)]
+pub struct ImportResult {
+    pub transaction_ids: Vec<String>,
+    pub duplicate_import_ids: Vec<String>,
+    /// Values of the transactions created in YNAB
+    pub pushed: Vec<PushedTransaction>,
+    /// SpareBank1 ids of transactions YNAB skipped as duplicates, once per target account
+    pub duplicate_bank_ids: Vec<String>,
+}
+
+/// Recent YNAB transactions in every budget a sync writes to
+#[derive(Debug, Default)]
+pub struct RecentTransactions {
+    /// Date of the oldest transaction fetched from SpareBank1
+    pub window_start: Option<NaiveDate>,
+    pub budgets: BTreeMap<String, Vec<TransactionDetail>>,
+}
+
 pub struct YnabClient {
     ynab_token: String,
     ynab_budget: String,
-    account_config: HashMap<String, String>,
+    account_config: AccountConfig,
+    memo_template: Option<MemoTemplate>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Account {
     pub id: String,
     pub name: String,
+    /// Payee that creates a transfer into this account
+    #[serde(default)]
+    pub transfer_payee_id: Option<String>,
+    /// Balance in milliunits, including uncleared transactions
+    #[serde(default)]
+    pub balance: i64,
+    /// Balance of cleared and reconciled transactions in milliunits
+    #[serde(default)]
+    pub cleared_balance: i64,
     closed: bool,
 }
 
@@ -56,6 +222,130 @@ struct YnabAccountsResponse {
     data: YnabAccountResponse,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct Category {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CategoryGroup {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub deleted: bool,
+    pub categories: Vec<Category>,
+}
+
+#[derive(Debug, Deserialize)]
+struct YnabCategoriesResponse {
+    category_groups: Vec<CategoryGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+struct YnabCategoriesDataResponse {
+    data: YnabCategoriesResponse,
+}
+
+/// Finds a category by name, case-insensitive
+///
+/// The name may be qualified with its group as "Group: Category" to tell apart
+/// categories with the same name in different groups.
+pub fn resolve_category<'a>(
+    category_groups: &'a [CategoryGroup],
+    name: &str,
+) -> Option<&'a Category> {
+    let (group_name, category_name) = match name.split_once(':') {
+        Some((group, category)) => (Some(group.trim()), category.trim()),
+        None => (None, name.trim()),
+    };
+
+    let find = |group_name: Option<&str>, category_name: &str| {
+        category_groups
+            .iter()
+            .filter(|group| !group.deleted)
+            .filter(|group| {
+                group_name.is_none_or(|g| group.name.to_lowercase() == g.to_lowercase())
+            })
+            .flat_map(|group| group.categories.iter())
+            .find(|category| {
+                !category.deleted && category.name.to_lowercase() == category_name.to_lowercase()
+            })
+    };
+
+    // Category names may themselves contain a colon
+    find(group_name, category_name).or_else(|| find(None, name.trim()))
+}
+
+/// A transaction as stored in YNAB
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TransactionDetail {
+    pub id: String,
+    pub date: NaiveDate,
+    /// Amount in milliunits
+    pub amount: i64,
+    pub account_id: String,
+    pub payee_name: Option<String>,
+    pub memo: Option<String>,
+    pub cleared: String,
+    pub approved: bool,
+    pub flag_color: Option<String>,
+    pub category_id: Option<String>,
+    #[serde(default)]
+    pub category_name: Option<String>,
+    pub import_id: Option<String>,
+    /// Payee name the transaction was imported with, before any renaming in YNAB
+    #[serde(default)]
+    pub import_payee_name_original: Option<String>,
+    #[serde(default)]
+    pub transfer_account_id: Option<String>,
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+impl TransactionDetail {
+    pub fn is_uncleared(&self) -> bool {
+        self.cleared == "uncleared"
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct YnabTransactionsResponse {
+    transactions: Vec<TransactionDetail>,
+}
+
+#[derive(Debug, Deserialize)]
+struct YnabTransactionsDataResponse {
+    data: YnabTransactionsResponse,
+}
+
+/// Changes to an existing YNAB transaction, fields left as None are not changed
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UpdateYnabTransaction {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payee_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cleared: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flag_color: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct UpdateYnabTransactionsRequest {
+    transactions: Vec<UpdateYnabTransaction>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Budget {
     pub id: String,
@@ -73,61 +363,527 @@ struct YnabBudgetsDataResponse {
 }
 
 impl YnabClient {
-    pub fn new(
-        account_config: HashMap<String, String>,
-        ynab_token: String,
-        ynab_budget: String,
-    ) -> Self {
+    pub fn new(account_config: AccountConfig, ynab_token: String, ynab_budget: String) -> Self {
         YnabClient {
             ynab_token,
             ynab_budget,
             account_config,
+            memo_template: None,
         }
     }
 
-    fn parse_transactions(
+    /// Sets the memo template used for accounts without their own
+    pub fn with_memo_template(mut self, memo_template: Option<MemoTemplate>) -> Self {
+        self.memo_template = memo_template;
+        self
+    }
+
+    /// Target of the other side of a transfer in the given budget
+    fn transfer_counterpart(&self, link: &TransferLink, budget_id: &str) -> Option<&AccountTarget> {
+        self.account_config
+            .get(&link.account)?
+            .iter()
+            .find(|target| target.budget_id(&self.ynab_budget) == budget_id)
+    }
+
+    /// Memo for a transaction in a target account
+    fn memo_for(&self, import: &ImportTransaction, target: &AccountTarget) -> String {
+        let template = target
+            .memo_template
+            .as_ref()
+            .or(self.memo_template.as_ref());
+        memo::render_memo(template, &import.transaction)
+    }
+
+    /// Values a transaction is written to a target account with
+    fn pushed_values(
         &self,
-        transactions: &[sparebanken1::Transaction],
-    ) -> Vec<CreateYnabTransaction> {
+        import: &ImportTransaction,
+        target: &AccountTarget,
+    ) -> PushedTransaction {
+        PushedTransaction {
+            bank_id: import.transaction.id.clone(),
+            budget_id: target.budget_id(&self.ynab_budget).to_string(),
+            account_id: target.account_id.clone(),
+            import_id: import.import_id.clone(),
+            date: import.transaction.date.with_timezone(&Oslo).date_naive(),
+            amount: to_milliunits(import.transaction.amount),
+            payee_name: import.transfer.is_none().then(|| import.payee_name.clone()),
+            memo: self.memo_for(import, target),
+            bank: Some(BankValues::of(&import.transaction)),
+        }
+    }
+
+    /// Imports posted to accounts in a budget, one per target account
+    fn posted_imports<'a>(
+        &'a self,
+        transactions: &'a [ImportTransaction],
+        budget_id: &'a str,
+    ) -> Vec<PostedImport<'a>> {
         transactions
             .iter()
-            .scan(Vec::new(), |state, t| {
-                let oslo_time = t.date.with_timezone(&Oslo);
-                let formated_date = oslo_time.format("%Y-%m-%d").to_string();
-                let account_id = self.account_config.get(&t.account).unwrap();
-
-                // Check if same transactions has been imported before
-                let import_prefix = format!("SB1:{}:{}", t.amount, formated_date);
-                state.push(import_prefix.clone());
-                let import_count = state
-                    .iter()
-                    .filter(|id| id.starts_with(&import_prefix))
-                    .count();
-                let import_id = format!("{}:{}", import_prefix, import_count);
-
-                Some(CreateYnabTransaction {
-                    date: formated_date,
-                    account_id: account_id.clone(),
-                    amount: (t.amount * 1000.0) as i64,
-                    payee_name: t.payee.clone(),
-                    cleared: String::from("cleared"),
-                    memo: t.description.clone(),
-                    import_id,
-                })
+            .enumerate()
+            .flat_map(|(index, import)| {
+                self.account_config
+                    .get(&import.transaction.account)
+                    .into_iter()
+                    .flatten()
+                    .filter(move |target| target.budget_id(&self.ynab_budget) == budget_id)
+                    .map(move |target| PostedImport {
+                        index,
+                        import,
+                        account_id: &target.account_id,
+                    })
             })
             .collect()
     }
 
+    /// Target for a SpareBank1 account and YNAB account
+    fn find_target(&self, account: &str, account_id: &str) -> Option<&AccountTarget> {
+        self.account_config
+            .get(account)?
+            .iter()
+            .find(|target| target.account_id == account_id)
+    }
+
+    /// Builds YNAB transactions grouped by the budget they are posted to
+    ///
+    /// `transfer_payees` maps budget to YNAB account id to the account's transfer payee.
+    pub fn parse_transactions(
+        &self,
+        transactions: &[ImportTransaction],
+        categories: &HashMap<String, Vec<CategoryGroup>>,
+        transfer_payees: &HashMap<String, HashMap<String, String>>,
+    ) -> BTreeMap<String, Vec<CreateYnabTransaction>> {
+        let mut budgets: BTreeMap<String, Vec<CreateYnabTransaction>> = BTreeMap::new();
+
+        for import in transactions {
+            let t = &import.transaction;
+            let date = t.date.with_timezone(&Oslo).date_naive();
+            let targets = self.account_config.get(&t.account).unwrap();
+
+            // Import ids are unique per budget, so every target can share the same id
+            for target in targets {
+                if import.already_imported.contains(&target.account_id) {
+                    continue;
+                }
+
+                let budget_id = target.budget_id(&self.ynab_budget);
+                let transfer_payee = |account_id: &str| {
+                    transfer_payees
+                        .get(budget_id)
+                        .and_then(|payees| payees.get(account_id))
+                        .cloned()
+                };
+
+                // Both sides of a transfer in the same budget are posted once, as a transfer
+                let mut payee_id = None;
+                if let Some(link) = &import.transfer {
+                    if let Some(counterpart) = self.transfer_counterpart(link, budget_id) {
+                        match link.side {
+                            TransferSide::Outgoing => {
+                                payee_id = transfer_payee(&counterpart.account_id);
+                                if payee_id.is_none() {
+                                    warn!(
+                                        "No transfer payee for account {} in budget {}",
+                                        counterpart.account_id, budget_id
+                                    );
+                                }
+                            }
+                            // YNAB creates this side from the outgoing transfer
+                            TransferSide::Incoming
+                                if transfer_payee(&target.account_id).is_some() =>
+                            {
+                                continue;
+                            }
+                            TransferSide::Incoming => {}
+                        }
+                    }
+                }
+
+                let resolve = |name: &String| {
+                    let category = categories
+                        .get(budget_id)
+                        .and_then(|groups| resolve_category(groups, name));
+                    if category.is_none() {
+                        warn!("Category '{}' not found in budget {}", name, budget_id);
+                    }
+                    category.map(|c| c.id.clone())
+                };
+
+                let category_id = import.category.as_ref().and_then(resolve);
+                let subtransactions: Vec<CreateYnabSubTransaction> = import
+                    .subtransactions
+                    .iter()
+                    .map(|line| CreateYnabSubTransaction {
+                        amount: line.amount,
+                        category_id: line.category.as_ref().and_then(resolve),
+                        memo: line.memo.clone(),
+                    })
+                    .collect();
+
+                // Categorised transactions are approved unless marked for review
+                let categorised = if subtransactions.is_empty() {
+                    category_id.is_some()
+                } else {
+                    subtransactions
+                        .iter()
+                        .all(|line| line.category_id.is_some())
+                };
+                let approved = (categorised && !import.review).then_some(true);
+
+                budgets
+                    .entry(budget_id.to_string())
+                    .or_default()
+                    .push(CreateYnabTransaction {
+                        bank_id: t.id.clone(),
+                        bank: Some(BankValues::of(t)),
+                        date,
+                        account_id: target.account_id.clone(),
+                        amount: to_milliunits(t.amount),
+                        payee_name: payee_id.is_none().then(|| import.payee_name.clone()),
+                        payee_id,
+                        cleared: if t.is_pending() {
+                            String::from("uncleared")
+                        } else {
+                            String::from("cleared")
+                        },
+                        memo: self.memo_for(import, target),
+                        import_id: import.import_id.clone(),
+                        category_id,
+                        approved,
+                        flag_color: import.flag,
+                        subtransactions,
+                    });
+            }
+        }
+
+        budgets
+    }
+
+    /// Fetches recent YNAB transactions in every budget the given transactions are mapped to
+    ///
+    /// The lookup starts a week before the oldest transaction, so pending transactions that
+    /// were booked later are found too.
+    pub async fn get_recent_transactions(
+        &self,
+        transactions: &[ImportTransaction],
+    ) -> Result<RecentTransactions, reqwest::Error> {
+        let mut recent = RecentTransactions {
+            window_start: transactions
+                .iter()
+                .map(|t| t.transaction.date.with_timezone(&Oslo).date_naive())
+                .min(),
+            ..Default::default()
+        };
+        let Some(window_start) = recent.window_start else {
+            return Ok(recent);
+        };
+
+        let budgets: BTreeSet<&str> = transactions
+            .iter()
+            .filter_map(|t| self.account_config.get(&t.transaction.account))
+            .flatten()
+            .map(|target| target.budget_id(&self.ynab_budget))
+            .collect();
+        for budget_id in budgets {
+            let ynab_transactions = self
+                .get_transactions_for_budget(budget_id, pending::lookup_start(window_start))
+                .await?;
+            recent
+                .budgets
+                .insert(budget_id.to_string(), ynab_transactions);
+        }
+
+        Ok(recent)
+    }
+
+    /// Whether a transaction was imported on an earlier run as a plain transaction, not a transfer
+    ///
+    /// Looks for its import id among the recent YNAB transactions of its target accounts, and
+    /// counts transactions already imported under another import id. The side of a transfer
+    /// YNAB created has no import id and is never found.
+    pub fn imported_as_plain(
+        &self,
+        import: &ImportTransaction,
+        recent: &RecentTransactions,
+    ) -> bool {
+        if !import.already_imported.is_empty() {
+            return !import.already_imported_as_transfer;
+        }
+        self.account_config
+            .get(&import.transaction.account)
+            .into_iter()
+            .flatten()
+            .any(|target| {
+                recent
+                    .budgets
+                    .get(target.budget_id(&self.ynab_budget))
+                    .is_some_and(|transactions| {
+                        transactions.iter().any(|t| {
+                            !t.deleted
+                                && t.account_id == target.account_id
+                                && t.import_id.as_deref() == Some(import.import_id.as_str())
+                                && t.transfer_account_id.is_none()
+                        })
+                    })
+            })
+    }
+
+    /// Settles pending transactions imported on earlier runs
+    ///
+    /// Pending transactions that have been booked are marked cleared and the booked import is
+    /// not posted again in that account. Pending transactions the bank no longer reports are
+    /// flagged or deleted.
+    pub async fn settle_pending(
+        &self,
+        transactions: &mut [ImportTransaction],
+        recent: &RecentTransactions,
+        vanished_pending: VanishedPending,
+    ) -> Result<PendingSummary, reqwest::Error> {
+        let mut summary = PendingSummary::default();
+        let Some(window_start) = recent.window_start else {
+            return Ok(summary);
+        };
+
+        let mut settled = Vec::new();
+        for (budget_id, ynab_transactions) in &recent.budgets {
+            let posted = self.posted_imports(transactions, budget_id);
+            let plan =
+                pending::plan_pending(ynab_transactions, &posted, window_start, vanished_pending);
+            summary.settled += plan.cleared();
+            summary.vanished += plan.vanished;
+            settled.extend(plan.settled);
+
+            self.update_transactions(budget_id, plan.updates).await?;
+            for transaction_id in &plan.deletes {
+                self.delete_transaction(budget_id, transaction_id).await?;
+            }
+        }
+
+        for (index, account_id) in settled {
+            transactions[index].already_imported.push(account_id);
+        }
+
+        if summary.settled > 0 || summary.vanished > 0 {
+            info!(
+                "Settled {} pending transactions, {} pending transactions vanished",
+                summary.settled, summary.vanished
+            );
+        }
+        Ok(summary)
+    }
+
+    /// Updates transactions imported on earlier runs that the bank has changed since
+    ///
+    /// Only transactions recorded in the sync state are considered. Fields edited in YNAB are
+    /// left alone, and a transaction whose import id changed with the bank's correction is not
+    /// posted again. Returns the SpareBank1 ids of the transactions updated.
+    pub async fn update_changed(
+        &self,
+        transactions: &mut [ImportTransaction],
+        recent: &RecentTransactions,
+        state: &mut SyncState,
+    ) -> Result<Vec<String>, reqwest::Error> {
+        let mut already_imported = Vec::new();
+        let mut recorded = Vec::new();
+        let mut updated = Vec::new();
+
+        for (budget_id, ynab_transactions) in &recent.budgets {
+            let mut updates = Vec::new();
+
+            for posted in self.posted_imports(transactions, budget_id) {
+                let import = posted.import;
+                let Some(pushed) = state.find(&import.transaction.id, posted.account_id) else {
+                    continue;
+                };
+                let in_ynab = ynab_transactions.iter().find(|t| {
+                    !t.deleted
+                        && t.account_id == posted.account_id
+                        && t.import_id.as_deref() == Some(pushed.import_id.as_str())
+                });
+                if pushed.import_id != import.import_id {
+                    let transfer = in_ynab.is_some_and(|t| t.transfer_account_id.is_some());
+                    already_imported.push((posted.index, posted.account_id.to_string(), transfer));
+                }
+
+                let Some(in_ynab) = in_ynab else {
+                    continue;
+                };
+                let Some(target) = self.find_target(&import.transaction.account, posted.account_id)
+                else {
+                    continue;
+                };
+
+                let mut current = self.pushed_values(import, target);
+                // Changing the amount of a split would leave its lines out of balance
+                if !import.subtransactions.is_empty() {
+                    current.amount = pushed.amount;
+                }
+
+                let plan = state::plan_update(pushed, &current, in_ynab);
+                if !plan.user_edited.is_empty() {
+                    info!(
+                        "Not updating {} of {} in YNAB, edited manually",
+                        plan.user_edited.join(", "),
+                        import.payee_name
+                    );
+                }
+                if let Some(update) = plan.update {
+                    debug!("Updating {} in YNAB: {:?}", import.payee_name, update);
+                    updates.push(update);
+                    updated.push(import.transaction.id.clone());
+                }
+                if plan.recorded != *pushed {
+                    recorded.push(plan.recorded);
+                }
+            }
+
+            self.update_transactions(budget_id, updates).await?;
+        }
+
+        for pushed in recorded {
+            state.record(pushed);
+        }
+        for (index, account_id, transfer) in already_imported {
+            transactions[index].already_imported.push(account_id);
+            transactions[index].already_imported_as_transfer |= transfer;
+        }
+
+        if !updated.is_empty() {
+            info!("Updated {} transactions changed by the bank", updated.len());
+        }
+        Ok(updated)
+    }
+
+    /// Imports transactions to every budget their accounts are mapped to
     pub async fn add_transactions(
         &self,
-        transactions: Vec<sparebanken1::Transaction>,
-    ) -> Result<CreateYnabTransactionResponseData, reqwest::Error> {
+        transactions: Vec<ImportTransaction>,
+    ) -> Result<ImportResult, reqwest::Error> {
         debug!(
             "Preparing to add {} transactions to YNAB",
             transactions.len()
         );
-        let url = format!("{}/budgets/{}/transactions", BASE_API_URL, self.ynab_budget);
-        let ynab_transactions = self.parse_transactions(&transactions);
+
+        // Category names are resolved per budget, so fetch categories for every budget that needs them
+        let category_budgets: BTreeSet<&str> = transactions
+            .iter()
+            .filter(|t| {
+                t.category.is_some() || t.subtransactions.iter().any(|l| l.category.is_some())
+            })
+            .filter_map(|t| self.account_config.get(&t.transaction.account))
+            .flatten()
+            .map(|target| target.budget_id(&self.ynab_budget))
+            .collect();
+        let mut categories = HashMap::new();
+        for budget_id in category_budgets {
+            let groups = self.get_categories_for_budget(budget_id).await?;
+            categories.insert(budget_id.to_string(), groups);
+        }
+
+        // Transfers are posted with the transfer payee of the receiving account
+        let transfer_budgets: BTreeSet<&str> = transactions
+            .iter()
+            .filter(|t| t.transfer.is_some())
+            .filter_map(|t| self.account_config.get(&t.transaction.account))
+            .flatten()
+            .map(|target| target.budget_id(&self.ynab_budget))
+            .collect();
+        let mut transfer_payees = HashMap::new();
+        for budget_id in transfer_budgets {
+            let payees: HashMap<String, String> = self
+                .get_accounts_for_budget(budget_id)
+                .await?
+                .into_iter()
+                .filter_map(|account| Some((account.id, account.transfer_payee_id?)))
+                .collect();
+            transfer_payees.insert(budget_id.to_string(), payees);
+        }
+
+        let budgets = self.parse_transactions(&transactions, &categories, &transfer_payees);
+
+        let mut result = ImportResult::default();
+        for (budget_id, ynab_transactions) in budgets {
+            let data = self
+                .post_transactions(&budget_id, &ynab_transactions)
+                .await?;
+
+            // Transactions that were duplicates may hold other values in YNAB
+            let (duplicates, created): (Vec<_>, Vec<_>) = ynab_transactions
+                .into_iter()
+                .partition(|t| data.duplicate_import_ids.contains(&t.import_id));
+            result
+                .duplicate_bank_ids
+                .extend(duplicates.into_iter().map(|t| t.bank_id));
+            result
+                .pushed
+                .extend(created.into_iter().map(|t| PushedTransaction {
+                    bank_id: t.bank_id,
+                    budget_id: budget_id.clone(),
+                    account_id: t.account_id,
+                    import_id: t.import_id,
+                    date: t.date,
+                    amount: t.amount,
+                    payee_name: t.payee_name,
+                    memo: t.memo,
+                    bank: t.bank,
+                }));
+            result.transaction_ids.extend(data.transaction_ids);
+            result
+                .duplicate_import_ids
+                .extend(data.duplicate_import_ids);
+        }
+
+        Ok(result)
+    }
+
+    /// Posts a balance adjustment, returns false if the same adjustment was already posted
+    ///
+    /// The import id holds the amount and date, so an adjustment is not posted twice when
+    /// syncs overlap.
+    pub async fn add_balance_adjustment(
+        &self,
+        adjustment: &BalanceAdjustment,
+    ) -> Result<bool, reqwest::Error> {
+        let transaction = CreateYnabTransaction {
+            bank_id: String::new(),
+            bank: None,
+            date: adjustment.date,
+            account_id: adjustment.account_id.clone(),
+            amount: adjustment.amount,
+            payee_id: None,
+            payee_name: Some(balance::ADJUSTMENT_PAYEE.to_string()),
+            cleared: String::from("cleared"),
+            memo: format!(
+                "SpareBank1 balance {:.2} NOK",
+                adjustment.bank_balance as f64 / 1000.0
+            ),
+            import_id: format!(
+                "{}:{}:{}:1",
+                ADJUSTMENT_IMPORT_PREFIX,
+                adjustment.amount,
+                adjustment.date.format("%Y-%m-%d")
+            ),
+            category_id: None,
+            approved: None,
+            flag_color: None,
+            subtransactions: Vec::new(),
+        };
+
+        let data = self
+            .post_transactions(&adjustment.budget_id, &[transaction])
+            .await?;
+        Ok(data.duplicate_import_ids.is_empty())
+    }
+
+    /// Posts transactions to a single budget
+    async fn post_transactions(
+        &self,
+        budget_id: &str,
+        ynab_transactions: &[CreateYnabTransaction],
+    ) -> Result<CreateYnabTransactionResponseData, reqwest::Error> {
+        let url = format!("{}/budgets/{}/transactions", base_api_url(), budget_id);
 
         let data = CreateYnabTransactionRequest {
             transactions: ynab_transactions,
@@ -138,7 +894,7 @@ impl YnabClient {
             .post(url)
             .header("Authorization", &format!("Bearer {}", self.ynab_token))
             .json(&data)
-            .send()
+            .send_recorded(Api::Ynab)
             .await
             .map_err(|e| {
                 error!("Failed to send transactions to YNAB: {}", e);
@@ -152,8 +908,9 @@ impl YnabClient {
             })?;
 
         info!(
-            "Successfully added {} transactions to YNAB",
-            response.data.transaction_ids.len()
+            "Successfully added {} transactions to YNAB budget {}",
+            response.data.transaction_ids.len(),
+            budget_id
         );
         debug!(
             "Skipped {} duplicate transactions",
@@ -163,19 +920,162 @@ impl YnabClient {
         Ok(response.data)
     }
 
+    /// Get transactions in a budget dated on or after `since`
+    pub async fn get_transactions_for_budget(
+        &self,
+        budget_id: &str,
+        since: NaiveDate,
+    ) -> Result<Vec<TransactionDetail>, reqwest::Error> {
+        debug!(
+            "Fetching transactions since {} for budget {} from YNAB",
+            since, budget_id
+        );
+        let url = format!("{}/budgets/{}/transactions", base_api_url(), budget_id);
+
+        let response = reqwest::Client::new()
+            .get(url)
+            .header("Authorization", &format!("Bearer {}", self.ynab_token))
+            .query(&[("since_date", since.format("%Y-%m-%d").to_string())])
+            .send_recorded(Api::Ynab)
+            .await?
+            .error_for_status()
+            .map_err(|e| {
+                error!("Failed to fetch transactions from YNAB: {}", e);
+                e
+            })?
+            .json::<YnabTransactionsDataResponse>()
+            .await?;
+
+        info!(
+            "Successfully fetched {} transactions from YNAB budget {}",
+            response.data.transactions.len(),
+            budget_id
+        );
+
+        Ok(response.data.transactions)
+    }
+
+    /// Get all transactions in a single account
+    pub async fn get_account_transactions(
+        &self,
+        budget_id: &str,
+        account_id: &str,
+    ) -> Result<Vec<TransactionDetail>, reqwest::Error> {
+        debug!(
+            "Fetching transactions for account {} in budget {} from YNAB",
+            account_id, budget_id
+        );
+        let url = format!(
+            "{}/budgets/{}/accounts/{}/transactions",
+            base_api_url(),
+            budget_id,
+            account_id
+        );
+
+        let response = reqwest::Client::new()
+            .get(url)
+            .header("Authorization", &format!("Bearer {}", self.ynab_token))
+            .send_recorded(Api::Ynab)
+            .await?
+            .error_for_status()
+            .map_err(|e| {
+                error!("Failed to fetch account transactions from YNAB: {}", e);
+                e
+            })?
+            .json::<YnabTransactionsDataResponse>()
+            .await?;
+
+        Ok(response.data.transactions)
+    }
+
+    /// Updates existing transactions in a budget
+    pub async fn update_transactions(
+        &self,
+        budget_id: &str,
+        updates: Vec<UpdateYnabTransaction>,
+    ) -> Result<(), reqwest::Error> {
+        if updates.is_empty() {
+            return Ok(());
+        }
+        let count = updates.len();
+        let url = format!("{}/budgets/{}/transactions", base_api_url(), budget_id);
+
+        reqwest::Client::new()
+            .patch(url)
+            .header("Authorization", &format!("Bearer {}", self.ynab_token))
+            .json(&UpdateYnabTransactionsRequest {
+                transactions: updates,
+            })
+            .send_recorded(Api::Ynab)
+            .await?
+            .error_for_status()
+            .map_err(|e| {
+                error!("Failed to update transactions in YNAB: {}", e);
+                e
+            })?;
+
+        info!(
+            "Successfully updated {} transactions in YNAB budget {}",
+            count, budget_id
+        );
+        Ok(())
+    }
+
+    /// Deletes a transaction from a budget
+    pub async fn delete_transaction(
+        &self,
+        budget_id: &str,
+        transaction_id: &str,
+    ) -> Result<(), reqwest::Error> {
+        let url = format!(
+            "{}/budgets/{}/transactions/{}",
+            base_api_url(),
+            budget_id,
+            transaction_id
+        );
+
+        reqwest::Client::new()
+            .delete(url)
+            .header("Authorization", &format!("Bearer {}", self.ynab_token))
+            .send_recorded(Api::Ynab)
+            .await?
+            .error_for_status()
+            .map_err(|e| {
+                error!("Failed to delete transaction from YNAB: {}", e);
+                e
+            })?;
+
+        debug!(
+            "Deleted transaction {} from YNAB budget {}",
+            transaction_id, budget_id
+        );
+        Ok(())
+    }
+
     pub async fn get_accounts(&self) -> Result<Vec<Account>, reqwest::Error> {
-        debug!("Fetching accounts from YNAB");
-        let url = format!("{BASE_API_URL}/budgets/{}/accounts", self.ynab_budget);
+        self.get_accounts_for_budget(&self.ynab_budget).await
+    }
+
+    /// Get open accounts for a specific budget
+    pub async fn get_accounts_for_budget(
+        &self,
+        budget_id: &str,
+    ) -> Result<Vec<Account>, reqwest::Error> {
+        debug!("Fetching accounts for budget {} from YNAB", budget_id);
+        let url = format!("{}/budgets/{}/accounts", base_api_url(), budget_id);
 
         let response = reqwest::Client::new()
             .get(url)
             .header("Authorization", &format!("Bearer {}", self.ynab_token))
-            .send()
-            .await
-            .expect("Failed to get account from YNAB")
+            .send_recorded(Api::Ynab)
+            .await?
+            .error_for_status()
+            .map_err(|e| {
+                error!("Failed to fetch accounts from YNAB: {}", e);
+                e
+            })?
             .json::<YnabAccountsResponse>()
-            .await
-            .expect("Could not parse data");
+            .await?;
 
         let filtered_accounts: Vec<Account> = response
             .data
@@ -193,13 +1093,47 @@ impl YnabClient {
         Ok(filtered_accounts)
     }
 
+    /// Get category groups and their categories for the client's budget
+    pub async fn get_categories(&self) -> Result<Vec<CategoryGroup>, reqwest::Error> {
+        self.get_categories_for_budget(&self.ynab_budget).await
+    }
+
+    /// Get category groups and their categories for a specific budget
+    pub async fn get_categories_for_budget(
+        &self,
+        budget_id: &str,
+    ) -> Result<Vec<CategoryGroup>, reqwest::Error> {
+        debug!("Fetching categories for budget {} from YNAB", budget_id);
+        let url = format!("{}/budgets/{}/categories", base_api_url(), budget_id);
+
+        let response = reqwest::Client::new()
+            .get(url)
+            .header("Authorization", &format!("Bearer {}", self.ynab_token))
+            .send_recorded(Api::Ynab)
+            .await?
+            .error_for_status()
+            .map_err(|e| {
+                error!("Failed to fetch categories from YNAB: {}", e);
+                e
+            })?
+            .json::<YnabCategoriesDataResponse>()
+            .await?;
+
+        info!(
+            "Successfully fetched {} category groups from YNAB",
+            response.data.category_groups.len()
+        );
+
+        Ok(response.data.category_groups)
+    }
+
     pub async fn get_budgets(&self) -> Result<Vec<Budget>, reqwest::Error> {
         debug!("Fetching budgets from YNAB");
-        let url = format!("{BASE_API_URL}/budgets/");
+        let url = format!("{}/budgets/", base_api_url());
         let response = reqwest::Client::new()
             .get(url)
             .header("Authorization", &format!("Bearer {}", self.ynab_token))
-            .send()
+            .send_recorded(Api::Ynab)
             .await?
             .json::<YnabBudgetsDataResponse>()
             .await?;
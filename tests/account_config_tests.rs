@@ -1,4 +1,5 @@
 use sparebank1_to_ynab::account_config;
+use sparebank1_to_ynab::account_config::AccountTarget;
 use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
@@ -25,15 +26,15 @@ mod account_config_tests {
         assert_eq!(accounts.len(), 3);
         assert_eq!(
             accounts.get("account_key_1"),
-            Some(&"ynab_id_1".to_string())
+            Some(&vec![AccountTarget::new("ynab_id_1")])
         );
         assert_eq!(
             accounts.get("account_key_2"),
-            Some(&"ynab_id_2".to_string())
+            Some(&vec![AccountTarget::new("ynab_id_2")])
         );
         assert_eq!(
             accounts.get("account_key_3"),
-            Some(&"ynab_id_3".to_string())
+            Some(&vec![AccountTarget::new("ynab_id_3")])
         );
 
         fs::remove_file(temp_file).ok();
@@ -90,7 +91,7 @@ mod account_config_tests {
         assert_eq!(accounts.len(), 1);
         assert_eq!(
             accounts.get("single_account"),
-            Some(&"single_ynab_id".to_string())
+            Some(&vec![AccountTarget::new("single_ynab_id")])
         );
 
         fs::remove_file(temp_file).ok();
@@ -114,15 +115,15 @@ mod account_config_tests {
         assert_eq!(accounts.len(), 3);
         assert_eq!(
             accounts.get("account-with-dash"),
-            Some(&"ynab-id-1".to_string())
+            Some(&vec![AccountTarget::new("ynab-id-1")])
         );
         assert_eq!(
             accounts.get("account_with_underscore"),
-            Some(&"ynab_id_2".to_string())
+            Some(&vec![AccountTarget::new("ynab_id_2")])
         );
         assert_eq!(
             accounts.get("account.with.dots"),
-            Some(&"ynab.id.3".to_string())
+            Some(&vec![AccountTarget::new("ynab.id.3")])
         );
 
         fs::remove_file(temp_file).ok();
@@ -171,7 +172,7 @@ mod account_config_tests {
         for i in 1..=10 {
             assert_eq!(
                 accounts.get(&format!("account_{}", i)),
-                Some(&format!("ynab_id_{}", i))
+                Some(&vec![AccountTarget::new(&format!("ynab_id_{}", i))])
             );
         }
 
@@ -208,4 +209,123 @@ mod account_config_tests {
 
         fs::remove_file(temp_file).ok();
     }
+
+    #[test]
+    fn test_read_accounts_with_budget_targets() {
+        let temp_file = "/tmp/test_accounts_budget_targets.json";
+        let mut file = fs::File::create(temp_file).unwrap();
+        let json_content = r#"{
+            "joint_account": [
+                { "account_id": "household_checking" },
+                { "budget_id": "personal_budget", "account_id": "personal_tracking" }
+            ],
+            "savings_account": { "budget_id": "personal_budget", "account_id": "savings" },
+            "card_account": "card_ynab_id"
+        }"#;
+        file.write_all(json_content.as_bytes()).unwrap();
+
+        let accounts = account_config::read_accounts_json(temp_file).unwrap();
+        assert_eq!(accounts.len(), 3);
+        assert_eq!(
+            accounts.get("joint_account"),
+            Some(&vec![
+                AccountTarget::new("household_checking"),
+                AccountTarget::in_budget("personal_budget", "personal_tracking"),
+            ])
+        );
+        assert_eq!(
+            accounts.get("savings_account"),
+            Some(&vec![AccountTarget::in_budget(
+                "personal_budget",
+                "savings"
+            )])
+        );
+        assert_eq!(
+            accounts.get("card_account"),
+            Some(&vec![AccountTarget::new("card_ynab_id")])
+        );
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_account_target_budget_fallback() {
+        let default_target = AccountTarget::new("ynab_id");
+        let budget_target = AccountTarget::in_budget("other_budget", "ynab_id");
+
+        assert_eq!(default_target.budget_id("default_budget"), "default_budget");
+        assert_eq!(budget_target.budget_id("default_budget"), "other_budget");
+    }
+
+    #[test]
+    fn test_read_accounts_target_missing_account_id() {
+        let temp_file = "/tmp/test_accounts_missing_account_id.json";
+        let mut file = fs::File::create(temp_file).unwrap();
+        let json_content = r#"{
+            "account1": { "budget_id": "budget" }
+        }"#;
+        file.write_all(json_content.as_bytes()).unwrap();
+
+        let result = account_config::read_accounts_json(temp_file);
+        assert!(result.is_err());
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_read_accounts_with_memo_template() {
+        let temp_file = "/tmp/test_accounts_memo_template.json";
+        let mut file = fs::File::create(temp_file).unwrap();
+        let json_content = r#"{
+            "account1": { "account_id": "ynab_1", "memo_template": "{cleaned_description} ({id})" },
+            "account2": { "account_id": "ynab_2", "memo_template": "{unknown}" }
+        }"#;
+        file.write_all(json_content.as_bytes()).unwrap();
+
+        // An unknown placeholder makes the whole file invalid
+        let result = account_config::read_accounts_json(temp_file);
+        assert!(result.is_err());
+
+        let json_content = r#"{
+            "account1": { "account_id": "ynab_1", "memo_template": "{cleaned_description} ({id})" }
+        }"#;
+        fs::write(temp_file, json_content).unwrap();
+
+        let accounts = account_config::read_accounts_json(temp_file).unwrap();
+        let target = &accounts.get("account1").unwrap()[0];
+        assert_eq!(
+            target.memo_template.as_ref().unwrap().to_string(),
+            "{cleaned_description} ({id})"
+        );
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_balance_only_targets_not_imported() {
+        let temp_file = "/tmp/test_accounts_balance_only.json";
+        let json_content = r#"{
+            "checking": "ynab_checking",
+            "bsu": { "account_id": "ynab_bsu", "balance_only": true },
+            "joint": [
+                { "account_id": "ynab_joint" },
+                { "budget_id": "other", "account_id": "ynab_joint_balance", "balance_only": true }
+            ]
+        }"#;
+        fs::write(temp_file, json_content).unwrap();
+
+        let accounts = account_config::read_accounts_json(temp_file).unwrap();
+        assert!(accounts.get("bsu").unwrap()[0].balance_only);
+        assert!(!accounts.get("checking").unwrap()[0].balance_only);
+
+        let import_config = account_config::import_targets(&accounts);
+        assert_eq!(import_config.len(), 2);
+        assert!(!import_config.contains_key("bsu"));
+        assert_eq!(
+            import_config.get("joint"),
+            Some(&vec![AccountTarget::new("ynab_joint")])
+        );
+
+        fs::remove_file(temp_file).ok();
+    }
 }
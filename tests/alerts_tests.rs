@@ -0,0 +1,193 @@
+use chrono::{DateTime, NaiveDate};
+use sparebank1_to_ynab::alerts::{self, AlertConfig, AlertKind, PayeeHistory};
+use sparebank1_to_ynab::rules::FlagColor;
+use sparebank1_to_ynab::sparebanken1::Transaction as Sparebank1Transaction;
+use sparebank1_to_ynab::transfers::{TransferLink, TransferSide};
+use sparebank1_to_ynab::ynab::{ImportTransaction, TransactionDetail};
+use std::collections::HashMap;
+use std::fs;
+
+#[cfg(test)]
+mod alerts_tests {
+    use super::*;
+
+    fn create_ynab_transaction(bank_payee: &str, amount: i64) -> TransactionDetail {
+        TransactionDetail {
+            id: "y1".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            amount,
+            account_id: "ynab-account".to_string(),
+            payee_name: Some(bank_payee.to_string()),
+            cleared: "cleared".to_string(),
+            approved: true,
+            import_payee_name_original: Some(bank_payee.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn create_import(id: &str, payee: &str, amount: f64, timestamp: i64) -> ImportTransaction {
+        let transaction = Sparebank1Transaction {
+            id: id.to_string(),
+            payee: payee.to_string(),
+            amount,
+            date: DateTime::from_timestamp(timestamp, 0).unwrap(),
+            account: "account1".to_string(),
+            ..Default::default()
+        };
+        ImportTransaction::new(transaction, format!("SB1:{}", id))
+    }
+
+    fn grocery_history() -> PayeeHistory {
+        PayeeHistory::learn(&[
+            create_ynab_transaction("KIWI 123 OSLO", -300_000),
+            create_ynab_transaction("KIWI 456 OSLO", -400_000),
+            create_ynab_transaction("KIWI 789 OSLO", -500_000),
+            create_ynab_transaction("NARVESEN 12", -50_000),
+        ])
+    }
+
+    #[test]
+    fn test_read_alerts_json() {
+        let temp_file = "/tmp/test_alerts.json";
+        fs::write(
+            temp_file,
+            r#"{
+                "large_amount": 5000,
+                "account_large_amount": { "savings": 50000 },
+                "new_payee": true,
+                "duplicate_minutes": 10,
+                "deviation_factor": 3,
+                "flag": "orange"
+            }"#,
+        )
+        .unwrap();
+        let config = alerts::read_alerts_json(temp_file).unwrap();
+        assert_eq!(config.large_amount, Some(5000.0));
+        assert_eq!(config.account_large_amount["savings"], 50000.0);
+        assert!(config.new_payee);
+        assert_eq!(config.duplicate_minutes, Some(10));
+        assert_eq!(config.deviation_factor, Some(3.0));
+        assert_eq!(config.flag, Some(FlagColor::Orange));
+        assert!(config.needs_history());
+
+        for invalid in [
+            r#"{ "large_amount": 0 }"#,
+            r#"{ "account_large_amount": { "savings": -1 } }"#,
+            r#"{ "duplicate_minutes": -5 }"#,
+            r#"{ "deviation_factor": 0.5 }"#,
+            r#"{ "new_payees": true }"#,
+        ] {
+            fs::write(temp_file, invalid).unwrap();
+            assert!(
+                alerts::read_alerts_json(temp_file).is_err(),
+                "accepted {}",
+                invalid
+            );
+        }
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_large_amount_per_account() {
+        let config = AlertConfig {
+            large_amount: Some(1000.0),
+            account_large_amount: HashMap::from([("account2".to_string(), 10000.0)]),
+            flag: Some(FlagColor::Red),
+            ..Default::default()
+        };
+        let mut imports = vec![
+            create_import("t1", "ELKJOP", -1500.0, 1704067200),
+            create_import("t2", "SALARY", 25000.0, 1704067200),
+            create_import("t3", "ELKJOP", -1500.0, 1704067200),
+            create_import("t4", "KIWI", -999.0, 1704067200),
+        ];
+        imports[2].transaction.account = "account2".to_string();
+        imports[1].flag = Some(FlagColor::Blue);
+
+        let alerts = alerts::check_transactions(&mut imports, &PayeeHistory::default(), &config);
+        let ids: Vec<&str> = alerts.iter().map(|a| a.transaction_id.as_str()).collect();
+        assert_eq!(ids, vec!["t1", "t2"]);
+        assert_eq!(alerts[0].kind, AlertKind::LargeAmount { threshold: 1000.0 });
+        assert_eq!(
+            alerts[0].explain(),
+            "2024-01-01 | ELKJOP | -1500.00 NOK | above 1000.00 NOK"
+        );
+
+        // Flags set by rules are kept
+        assert_eq!(imports[0].flag, Some(FlagColor::Red));
+        assert_eq!(imports[1].flag, Some(FlagColor::Blue));
+        assert_eq!(imports[2].flag, None);
+    }
+
+    #[test]
+    fn test_new_payee_and_deviation() {
+        let config = AlertConfig {
+            new_payee: true,
+            deviation_factor: Some(3.0),
+            ..Default::default()
+        };
+        let mut imports = vec![
+            create_import("t1", "KIWI 555 OSLO", -1300.0, 1704067200),
+            create_import("t2", "KIWI 556 OSLO", -1100.0, 1704067200),
+            create_import("t3", "NARVESEN 13", -500.0, 1704067200),
+            create_import("t4", "MENY 1", -200.0, 1704067200),
+            create_import("t5", "MENY 2", -250.0, 1704067200),
+        ];
+
+        let alerts = alerts::check_transactions(&mut imports, &grocery_history(), &config);
+        assert_eq!(alerts.len(), 2);
+        // More than three times the average of 400 NOK, Narvesen has too little history
+        assert_eq!(alerts[0].transaction_id, "t1");
+        assert_eq!(alerts[0].kind, AlertKind::Deviation { average: -400.0 });
+        assert_eq!(alerts[0].reason(), "usually -400.00 NOK");
+        // A new payee is alerted on once per sync
+        assert_eq!(alerts[1].transaction_id, "t4");
+        assert_eq!(alerts[1].kind, AlertKind::NewPayee);
+    }
+
+    #[test]
+    fn test_duplicate_charges() {
+        let config = AlertConfig {
+            duplicate_minutes: Some(10),
+            ..Default::default()
+        };
+        let mut imports = vec![
+            // Later charge listed first, as the bank lists newest first
+            create_import("t2", "CIRCLE K 12", -89.0, 1704067200 + 5 * 60),
+            create_import("t1", "CIRCLE K 12", -89.0, 1704067200),
+            create_import("t3", "CIRCLE K 12", -89.0, 1704067200 + 60 * 60),
+            create_import("t4", "CIRCLE K 12", -45.0, 1704067200 + 60),
+            create_import("t5", "REFUND", 89.0, 1704067200),
+            create_import("t6", "REFUND", 89.0, 1704067200),
+            create_import("t7", "CIRCLE K 12", -89.0, 1704067200 + 2 * 60),
+        ];
+        imports[6].transfer = Some(TransferLink {
+            account: "account2".to_string(),
+            transaction_id: "t8".to_string(),
+            side: TransferSide::Outgoing,
+        });
+
+        let alerts = alerts::check_transactions(&mut imports, &PayeeHistory::default(), &config);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].transaction_id, "t2");
+        assert_eq!(alerts[0].kind, AlertKind::DuplicateCharge { minutes: 5 });
+    }
+
+    #[test]
+    fn test_alert_json_shape() {
+        let config = AlertConfig {
+            large_amount: Some(100.0),
+            ..Default::default()
+        };
+        let mut imports = vec![create_import("t1", "ELKJOP", -1500.0, 1704067200)];
+        let alerts = alerts::check_transactions(&mut imports, &PayeeHistory::default(), &config);
+
+        let json = serde_json::to_value(&alerts[0]).unwrap();
+        assert_eq!(json["kind"], "large_amount");
+        assert_eq!(json["threshold"], 100.0);
+        assert_eq!(json["transaction_id"], "t1");
+        assert_eq!(json["account"], "account1");
+        assert_eq!(json["date"], "2024-01-01");
+    }
+}
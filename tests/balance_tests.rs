@@ -0,0 +1,183 @@
+use chrono::NaiveDate;
+use sparebank1_to_ynab::account_config::{AccountConfig, AccountTarget};
+use sparebank1_to_ynab::balance::{self, BalanceCheck};
+use sparebank1_to_ynab::sparebanken1::Account as Sparebank1Account;
+use sparebank1_to_ynab::ynab::{Account as YnabAccount, TransactionDetail};
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod balance_tests {
+    use super::*;
+
+    fn create_bank_account(key: &str, name: &str, balance: f64) -> Sparebank1Account {
+        Sparebank1Account {
+            name: name.to_string(),
+            balance,
+            key: key.to_string(),
+            account_number: "12345678901".to_string(),
+        }
+    }
+
+    fn create_ynab_account(id: &str, name: &str, cleared_balance: i64) -> YnabAccount {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": name,
+            "closed": false,
+            "balance": cleared_balance,
+            "cleared_balance": cleared_balance,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compare_balances_across_budgets() {
+        let mut account_config = AccountConfig::new();
+        account_config.insert(
+            "checking".to_string(),
+            vec![
+                AccountTarget::new("ynab-checking"),
+                AccountTarget::in_budget("other", "ynab-tracking"),
+            ],
+        );
+
+        let bank_accounts = vec![create_bank_account("checking", "Brukskonto", 12_345.67)];
+        let mut ynab_accounts = HashMap::new();
+        ynab_accounts.insert(
+            "default".to_string(),
+            vec![create_ynab_account("ynab-checking", "Checking", 12_345_670)],
+        );
+        ynab_accounts.insert(
+            "other".to_string(),
+            vec![create_ynab_account("ynab-tracking", "Joint", 12_000_000)],
+        );
+
+        let checks =
+            balance::compare_balances(&account_config, "default", &bank_accounts, &ynab_accounts);
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].bank_balance, 12_345_670);
+        assert!(checks[0].matches(0));
+        assert_eq!(checks[1].budget_id, "other");
+        assert_eq!(checks[1].difference(), -345_670);
+        assert!(!checks[1].matches(1000));
+    }
+
+    #[test]
+    fn test_missing_accounts_left_out() {
+        let mut account_config = AccountConfig::new();
+        account_config.insert(
+            "checking".to_string(),
+            vec![AccountTarget::new("ynab-missing")],
+        );
+        account_config.insert("closed".to_string(), vec![AccountTarget::new("ynab-x")]);
+
+        let bank_accounts = vec![create_bank_account("checking", "Brukskonto", 100.0)];
+        let mut ynab_accounts = HashMap::new();
+        ynab_accounts.insert(
+            "default".to_string(),
+            vec![create_ynab_account("ynab-x", "X", 0)],
+        );
+
+        let checks =
+            balance::compare_balances(&account_config, "default", &bank_accounts, &ynab_accounts);
+        assert!(checks.is_empty());
+    }
+
+    #[test]
+    fn test_balance_tolerance() {
+        let check = BalanceCheck {
+            account_key: "checking".to_string(),
+            account_name: "Brukskonto".to_string(),
+            budget_id: "default".to_string(),
+            ynab_account_id: "ynab-checking".to_string(),
+            ynab_account_name: "Checking".to_string(),
+            bank_balance: 100_000,
+            ynab_cleared_balance: 100_500,
+            balance_only: false,
+        };
+
+        assert_eq!(check.difference(), 500);
+        assert!(check.matches(500));
+        assert!(!check.matches(499));
+    }
+
+    fn create_transaction(id: &str, day: u32, cleared: &str) -> TransactionDetail {
+        TransactionDetail {
+            id: id.to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 3, day).unwrap(),
+            amount: -100_000,
+            account_id: "ynab-checking".to_string(),
+            payee_name: None,
+            memo: None,
+            cleared: cleared.to_string(),
+            approved: true,
+            flag_color: None,
+            category_id: None,
+            import_id: None,
+            deleted: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reconcilable_transactions() {
+        let mut deleted = create_transaction("deleted", 5, "cleared");
+        deleted.deleted = true;
+        let transactions = vec![
+            create_transaction("cleared", 5, "cleared"),
+            create_transaction("last-day", 10, "cleared"),
+            create_transaction("uncleared", 5, "uncleared"),
+            create_transaction("reconciled", 5, "reconciled"),
+            create_transaction("later", 11, "cleared"),
+            deleted,
+        ];
+
+        let ids: Vec<&str> =
+            balance::reconcilable(&transactions, NaiveDate::from_ymd_opt(2024, 3, 10).unwrap())
+                .into_iter()
+                .map(|t| t.id.as_str())
+                .collect();
+        assert_eq!(ids, vec!["cleared", "last-day"]);
+    }
+
+    #[test]
+    fn test_adjustments_for_balance_only_accounts() {
+        let mut account_config = AccountConfig::new();
+        let mut bsu = AccountTarget::new("ynab-bsu");
+        bsu.balance_only = true;
+        account_config.insert("bsu".to_string(), vec![bsu]);
+        let mut fund = AccountTarget::new("ynab-fund");
+        fund.balance_only = true;
+        account_config.insert("fund".to_string(), vec![fund]);
+        account_config.insert(
+            "checking".to_string(),
+            vec![AccountTarget::new("ynab-checking")],
+        );
+
+        let bank_accounts = vec![
+            create_bank_account("bsu", "BSU", 25_150.5),
+            create_bank_account("fund", "Fond", 1_000.0),
+            create_bank_account("checking", "Brukskonto", 500.0),
+        ];
+        let mut ynab_accounts = HashMap::new();
+        ynab_accounts.insert(
+            "default".to_string(),
+            vec![
+                create_ynab_account("ynab-bsu", "BSU", 25_000_000),
+                create_ynab_account("ynab-fund", "Fund", 1_000_000),
+                create_ynab_account("ynab-checking", "Checking", 400_000),
+            ],
+        );
+
+        let checks =
+            balance::compare_balances(&account_config, "default", &bank_accounts, &ynab_accounts);
+        let date = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        let adjustments = balance::adjustments(&checks, date);
+
+        // The matching fund and the regular checking account are left alone
+        assert_eq!(adjustments.len(), 1);
+        assert_eq!(adjustments[0].account_id, "ynab-bsu");
+        assert_eq!(adjustments[0].amount, 150_500);
+        assert_eq!(adjustments[0].bank_balance, 25_150_500);
+        assert_eq!(adjustments[0].date, date);
+    }
+}
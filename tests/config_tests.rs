@@ -28,7 +28,7 @@ mod config_tests {
         assert_eq!(config.account_config_path, "/tmp/accounts.json");
         assert_eq!(config.refresh_token_file_path, "refresh_token.txt");
         assert_eq!(config.initial_refresh_token, "test_refresh_token");
-        assert_eq!(config.dry_run, false);
+        assert!(!config.dry_run);
     }
 
     #[test]
@@ -0,0 +1,88 @@
+use reqwest::StatusCode;
+use sparebank1_to_ynab::account_config;
+use sparebank1_to_ynab::auth_data::AuthError;
+use sparebank1_to_ynab::config::ConfigError;
+use sparebank1_to_ynab::exit_code::ExitReason;
+use sparebank1_to_ynab::sparebanken1::{self, Sparebank1Error};
+use sparebank1_to_ynab::sync::SyncError;
+
+#[cfg(test)]
+mod exit_code_tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let reasons = [
+            ExitReason::Success,
+            ExitReason::Failure,
+            ExitReason::Config,
+            ExitReason::ReauthRequired,
+            ExitReason::Sparebank1Unavailable,
+            ExitReason::YnabUnavailable,
+            ExitReason::BalanceMismatch,
+            ExitReason::PartialSync,
+        ];
+        let codes: Vec<u8> = reasons.iter().map(|r| r.code()).collect();
+        assert_eq!(codes, vec![0, 1, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_exit_reason_from_sync_errors() {
+        let config = SyncError::from(ConfigError::ValidationError("bad".to_string()));
+        assert_eq!(ExitReason::from(&config), ExitReason::Config);
+
+        let accounts = account_config::read_accounts_json("/tmp/missing_accounts_exit.json")
+            .map_err(SyncError::from)
+            .unwrap_err();
+        assert_eq!(ExitReason::from(&accounts), ExitReason::Config);
+
+        let rejected = SyncError::from(AuthError::TokenRejected(StatusCode::BAD_REQUEST));
+        assert_eq!(ExitReason::from(&rejected), ExitReason::ReauthRequired);
+        assert!(rejected.to_string().contains("run the setup again"));
+
+        let bank = sparebanken1::parse_transactions("not json")
+            .map_err(|e| SyncError::from(Sparebank1Error::from(e)))
+            .unwrap_err();
+        assert_eq!(ExitReason::from(&bank), ExitReason::Sparebank1Unavailable);
+
+        let mismatch = SyncError::BalanceMismatch {
+            count: 2,
+            tolerance: 1.0,
+            summary: Default::default(),
+        };
+        assert_eq!(ExitReason::from(&mismatch), ExitReason::BalanceMismatch);
+        assert_eq!(
+            mismatch.to_string(),
+            "2 accounts differ from SpareBank1 by more than 1 NOK"
+        );
+    }
+
+    #[test]
+    fn test_exit_reason_for_run() {
+        assert_eq!(ExitReason::for_run(&[], 2), ExitReason::Success);
+        assert_eq!(
+            ExitReason::for_run(&[ExitReason::YnabUnavailable], 2),
+            ExitReason::PartialSync
+        );
+        // A rejected refresh token is not hidden by the profiles that synced
+        assert_eq!(
+            ExitReason::for_run(
+                &[ExitReason::YnabUnavailable, ExitReason::ReauthRequired],
+                3
+            ),
+            ExitReason::ReauthRequired
+        );
+        // When every profile failed, re-authentication is reported before outages
+        assert_eq!(
+            ExitReason::for_run(
+                &[ExitReason::YnabUnavailable, ExitReason::ReauthRequired],
+                2
+            ),
+            ExitReason::ReauthRequired
+        );
+        assert_eq!(
+            ExitReason::for_run(&[ExitReason::BalanceMismatch], 1),
+            ExitReason::BalanceMismatch
+        );
+    }
+}
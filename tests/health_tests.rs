@@ -0,0 +1,155 @@
+use chrono::{TimeDelta, Utc};
+use reqwest::StatusCode;
+use sparebank1_to_ynab::auth_data::AuthError;
+use sparebank1_to_ynab::config::Config;
+use sparebank1_to_ynab::health::Health;
+use sparebank1_to_ynab::profile::Profile;
+use sparebank1_to_ynab::server;
+use sparebank1_to_ynab::sync::{self, SyncError, SyncSummary};
+use std::fs;
+
+#[cfg(test)]
+mod health_tests {
+    use super::*;
+
+    fn create_profile(name: &str, token_path: &str, accounts_path: &str) -> Profile {
+        let config = Config::with_values(
+            "client".to_string(),
+            "secret".to_string(),
+            "fin".to_string(),
+            "token".to_string(),
+            "budget".to_string(),
+            accounts_path.to_string(),
+            Some(token_path.to_string()),
+            "refresh".to_string(),
+        )
+        .unwrap();
+        Profile {
+            name: name.to_string(),
+            config,
+        }
+    }
+
+    #[test]
+    fn test_ready_after_successful_sync() {
+        let token_path = "/tmp/test_health_ready_token.txt";
+        fs::write(token_path, "token").unwrap();
+        let health = Health::default();
+        health.register_profile(&create_profile("personal", token_path, "/tmp/a.json"));
+
+        let now = Utc::now();
+        let readiness = health.readiness(now);
+        assert!(!readiness.ready);
+        assert_eq!(readiness.profiles[0].problems, vec!["no sync finished yet"]);
+
+        health.record_sync("personal", &Ok(SyncSummary::default()), now);
+        let readiness = health.readiness(now);
+        assert!(readiness.ready);
+        assert_eq!(readiness.profiles[0].last_sync_at, Some(now));
+        assert!(readiness.profiles[0].refresh_token_expires_at.unwrap() > now);
+
+        fs::remove_file(token_path).ok();
+    }
+
+    #[test]
+    fn test_refresh_token_near_expiry() {
+        let token_path = "/tmp/test_health_expiry_token.txt";
+        fs::write(token_path, "token").unwrap();
+        let mut profile = create_profile("personal", token_path, "/tmp/a.json");
+        profile.config.refresh_token_lifetime_days = 30;
+        let health = Health::default();
+        health.register_profile(&profile);
+
+        let now = Utc::now();
+        health.record_sync("personal", &Ok(SyncSummary::default()), now);
+        assert!(health.readiness(now + TimeDelta::days(20)).ready);
+
+        let readiness = health.readiness(now + TimeDelta::days(25));
+        assert!(!readiness.ready);
+        assert!(readiness.profiles[0].problems[0].starts_with("refresh token expires at"));
+
+        fs::remove_file(token_path).ok();
+    }
+
+    #[test]
+    fn test_failed_syncs_reported() {
+        let health = Health::default();
+        health.register_profile(&create_profile(
+            "personal",
+            "/tmp/test_health_missing_token.txt",
+            "/tmp/a.json",
+        ));
+        let now = Utc::now();
+
+        let rejected = SyncError::from(AuthError::TokenRejected(StatusCode::BAD_REQUEST));
+        health.record_sync("personal", &Err(rejected), now);
+        let problems = &health.readiness(now).profiles[0].problems;
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("last sync failed"));
+        assert_eq!(problems[1], "refresh token rejected, run the setup again");
+
+        // A failure after the token refresh shows the token works again
+        let mismatch = SyncError::BalanceMismatch {
+            count: 1,
+            tolerance: 0.0,
+            summary: Default::default(),
+        };
+        health.record_sync("personal", &Err(mismatch), now);
+        let problems = &health.readiness(now).profiles[0].problems;
+        assert_eq!(
+            problems,
+            &vec!["last sync failed: 1 accounts differ from SpareBank1 by more than 0 NOK"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_account_mapping() {
+        let accounts_path = "/tmp/test_health_accounts.json";
+        let profile = create_profile("personal", "/tmp/test_health_token.txt", accounts_path);
+        let health = Health::default();
+        health.register_profile(&profile);
+        let now = Utc::now();
+
+        health.record_sync("personal", &Ok(SyncSummary::default()), now);
+        assert!(health.readiness(now).ready);
+
+        fs::write(accounts_path, "not json").unwrap();
+        health.record_config("personal", &sync::validate_profile(&profile.config));
+        let readiness = health.readiness(now);
+        assert!(!readiness.ready);
+        assert!(readiness.profiles[0].problems[0].starts_with("invalid configuration"));
+
+        fs::write(accounts_path, r#"{"checking": "ynab-checking"}"#).unwrap();
+        health.record_config("personal", &sync::validate_profile(&profile.config));
+        assert!(health.readiness(now).ready);
+
+        fs::remove_file(accounts_path).ok();
+    }
+
+    #[test]
+    fn test_liveness_requires_recent_rounds() {
+        let health = Health::default();
+        let started = Utc::now();
+        assert!(health.is_alive(started));
+
+        health.start(TimeDelta::minutes(60), started);
+        assert!(health.is_alive(started + TimeDelta::hours(2)));
+        assert!(!health.is_alive(started + TimeDelta::hours(4)));
+
+        health.record_round(started + TimeDelta::hours(3));
+        assert!(health.is_alive(started + TimeDelta::hours(4)));
+    }
+
+    #[test]
+    fn test_health_routes() {
+        let healthz = server::route("GET", "/healthz");
+        assert_eq!(healthz.status, 200);
+        assert_eq!(healthz.content_type, "application/json");
+        assert_eq!(healthz.body, r#"{"status":"ok"}"#);
+
+        // Without profiles there is nothing keeping the daemon from being ready
+        let readyz = server::route("GET", "/readyz");
+        assert_eq!(readyz.status, 200);
+        assert_eq!(readyz.body, r#"{"ready":true,"profiles":[]}"#);
+    }
+}
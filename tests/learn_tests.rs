@@ -0,0 +1,186 @@
+use chrono::{DateTime, NaiveDate};
+use sparebank1_to_ynab::learn::{self, CategoryHistory, LearnMode};
+use sparebank1_to_ynab::rules::RuleSet;
+use sparebank1_to_ynab::sparebanken1::Transaction as Sparebank1Transaction;
+use sparebank1_to_ynab::sync;
+use sparebank1_to_ynab::transfers::{TransferLink, TransferSide};
+use sparebank1_to_ynab::ynab::{ImportTransaction, TransactionDetail};
+
+#[cfg(test)]
+mod learn_tests {
+    use super::*;
+
+    fn create_ynab_transaction(
+        bank_payee: &str,
+        payee: &str,
+        category: Option<&str>,
+    ) -> TransactionDetail {
+        TransactionDetail {
+            id: "y1".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            amount: -100_000,
+            account_id: "ynab-account".to_string(),
+            payee_name: Some(payee.to_string()),
+            cleared: "cleared".to_string(),
+            approved: true,
+            category_id: category.map(|_| "category-id".to_string()),
+            category_name: Some(category.unwrap_or("Uncategorized").to_string()),
+            import_payee_name_original: Some(bank_payee.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn create_import(payee: &str) -> ImportTransaction {
+        let transaction = Sparebank1Transaction {
+            id: "txn1".to_string(),
+            payee: payee.to_string(),
+            amount: -100.0,
+            date: DateTime::from_timestamp(1704067200, 0).unwrap(),
+            account: "account1".to_string(),
+            ..Default::default()
+        };
+        ImportTransaction::new(transaction, "SB1:-100000:2024-01-01:1".to_string())
+    }
+
+    fn grocery_history() -> CategoryHistory {
+        CategoryHistory::learn(&[
+            create_ynab_transaction("KIWI 123 OSLO", "Kiwi", Some("Groceries")),
+            create_ynab_transaction("KIWI 456 OSLO", "Kiwi", Some("Groceries")),
+            create_ynab_transaction("KIWI 789 OSLO", "Kiwi", Some("Groceries")),
+            create_ynab_transaction("KIWI 789 OSLO", "Kiwi", Some("Household")),
+            create_ynab_transaction("NARVESEN 12", "Narvesen", Some("Snacks")),
+        ])
+    }
+
+    #[test]
+    fn test_payee_key_drops_numbers() {
+        assert_eq!(learn::payee_key("KIWI 123 OSLO"), "kiwi oslo");
+        assert_eq!(
+            learn::payee_key("VIPPS *NORGESGRUPPEN 4821"),
+            "vipps norgesgruppen"
+        );
+        assert_eq!(learn::payee_key("1234 5678"), "");
+    }
+
+    #[test]
+    fn test_suggest_with_confidence() {
+        let history = grocery_history();
+
+        let suggestion = history.suggest("KIWI 555 OSLO", 0.7).unwrap();
+        let category = suggestion.category.as_ref().unwrap();
+        assert_eq!(category.value, "Groceries");
+        assert_eq!((category.matches, category.total), (3, 4));
+        assert_eq!(suggestion.payee.as_ref().unwrap().value, "Kiwi");
+        assert_eq!(
+            suggestion.explain(),
+            "category Groceries (3 of 4), payee Kiwi (4 of 4) from past 'kiwi oslo' transactions"
+        );
+
+        // Three of four is below the threshold, the payee rename still holds
+        let suggestion = history.suggest("KIWI 555 OSLO", 0.8).unwrap();
+        assert_eq!(suggestion.category, None);
+        assert!(suggestion.payee.is_some());
+
+        // A single past transaction is not enough
+        assert_eq!(history.suggest("NARVESEN 99", 0.5), None);
+        assert_eq!(history.suggest("REMA 1000", 0.5), None);
+    }
+
+    #[test]
+    fn test_learn_skips_unapproved_transfers_and_splits() {
+        let mut unapproved = create_ynab_transaction("REMA 1000", "Rema", Some("Groceries"));
+        unapproved.approved = false;
+        let mut transfer = create_ynab_transaction("REMA 1000", "Rema", Some("Groceries"));
+        transfer.transfer_account_id = Some("savings".to_string());
+        let mut split = create_ynab_transaction("REMA 1000", "REMA 1000", None);
+        split.category_id = Some("split-id".to_string());
+        split.category_name = Some("Split (Multiple Categories)".to_string());
+        let uncategorised = create_ynab_transaction("REMA 1000", "REMA 1000", None);
+
+        let history = CategoryHistory::learn(&[unapproved, transfer, split, uncategorised]);
+        assert_eq!(history.suggest("REMA 1000", 0.5), None);
+    }
+
+    #[test]
+    fn test_apply_history_modes() {
+        let history = grocery_history();
+
+        let mut imports = vec![create_import("KIWI 555 OSLO")];
+        assert_eq!(
+            learn::apply_history(&mut imports, &history, LearnMode::Suggest, 0.7),
+            1
+        );
+        assert_eq!(imports[0].category, Some("Groceries".to_string()));
+        assert_eq!(imports[0].payee_name, "Kiwi");
+        assert!(imports[0].review);
+        assert!(imports[0].learned.is_some());
+
+        let mut imports = vec![create_import("KIWI 555 OSLO")];
+        learn::apply_history(&mut imports, &history, LearnMode::Apply, 0.7);
+        assert!(!imports[0].review);
+
+        let mut imports = vec![create_import("KIWI 555 OSLO")];
+        assert_eq!(
+            learn::apply_history(&mut imports, &history, LearnMode::Off, 0.7),
+            0
+        );
+        assert_eq!(imports[0].category, None);
+    }
+
+    #[test]
+    fn test_apply_history_keeps_rules_and_transfers() {
+        let history = grocery_history();
+
+        let mut categorised = create_import("KIWI 555 OSLO");
+        categorised.category = Some("Party".to_string());
+        categorised.payee_name = "Kiwi Majorstuen".to_string();
+        let mut transfer = create_import("KIWI 555 OSLO");
+        transfer.transfer = Some(TransferLink {
+            account: "account2".to_string(),
+            transaction_id: "t2".to_string(),
+            side: TransferSide::Outgoing,
+        });
+        let mut imports = vec![categorised, transfer];
+
+        assert_eq!(
+            learn::apply_history(&mut imports, &history, LearnMode::Apply, 0.7),
+            0
+        );
+        assert_eq!(imports[0].category, Some("Party".to_string()));
+        assert_eq!(imports[0].payee_name, "Kiwi Majorstuen");
+        assert_eq!(imports[1].category, None);
+    }
+
+    #[test]
+    fn test_learned_category_replaces_merchant_category() {
+        let rule_set: RuleSet = serde_json::from_str(
+            r#"{ "merchant_categories": { "groceries": "Everyday: Groceries" } }"#,
+        )
+        .unwrap();
+        let transactions = vec![
+            Sparebank1Transaction {
+                merchant_category_code: Some("5411".to_string()),
+                ..create_import("KIWI 555 OSLO").transaction
+            },
+            Sparebank1Transaction {
+                id: "txn2".to_string(),
+                merchant_category_code: Some("5411".to_string()),
+                ..create_import("MENY 12 BERGEN").transaction
+            },
+        ];
+        let mut imports = sync::prepare_transactions(transactions, &rule_set, |_| true).imports;
+        assert!(imports.iter().all(|i| i.merchant_category && i.review));
+
+        assert_eq!(
+            learn::apply_history(&mut imports, &grocery_history(), LearnMode::Apply, 0.7),
+            1
+        );
+        // History of the payee beats the broad merchant group
+        assert_eq!(imports[0].category, Some("Groceries".to_string()));
+        assert!(!imports[0].review);
+        assert!(!imports[0].merchant_category);
+        // Without history the merchant category stays, left for review
+        assert_eq!(imports[1].category, Some("Everyday: Groceries".to_string()));
+        assert!(imports[1].review);
+    }
+}
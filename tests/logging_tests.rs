@@ -0,0 +1,93 @@
+use clap::Parser;
+use sparebank1_to_ynab::logging::{self, LogFormat, LogOptions, LogRotation};
+use std::fs;
+use std::path::PathBuf;
+use tracing::{info, info_span};
+
+#[cfg(test)]
+mod logging_tests {
+    use super::*;
+
+    #[derive(Parser, Debug)]
+    struct Cli {
+        #[command(flatten)]
+        log: LogOptions,
+    }
+
+    #[test]
+    fn test_log_options() {
+        let cli = Cli::try_parse_from(["sync"]).unwrap();
+        assert_eq!(cli.log.log_format, LogFormat::Text);
+        assert_eq!(cli.log.log_file, None);
+        assert_eq!(cli.log.log_rotation, LogRotation::Daily);
+
+        let cli = Cli::try_parse_from([
+            "sync",
+            "--log-format",
+            "json",
+            "--log-file",
+            "/var/log/sync.log",
+            "--log-rotation",
+            "hourly",
+            "--log-max-files",
+            "24",
+        ])
+        .unwrap();
+        assert_eq!(cli.log.log_format, LogFormat::Json);
+        assert_eq!(cli.log.log_file, Some(PathBuf::from("/var/log/sync.log")));
+        assert_eq!(cli.log.log_rotation, LogRotation::Hourly);
+        assert_eq!(cli.log.log_max_files, Some(24));
+
+        // Rotation settings need a log file
+        assert!(Cli::try_parse_from(["sync", "--log-rotation", "never"]).is_err());
+        assert!(
+            Cli::try_parse_from(["sync", "--log-file", "a.log", "--log-max-files", "0"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_json_log_file() {
+        let directory = "/tmp/test_logging";
+        fs::remove_dir_all(directory).ok();
+        let options = LogOptions {
+            log_format: LogFormat::Json,
+            log_file: Some(PathBuf::from(format!("{}/sync.log", directory))),
+            log_rotation: LogRotation::Never,
+            ..Default::default()
+        };
+        let _guard = logging::init(&options, true).unwrap();
+
+        info_span!("profile", profile = "personal", fetched = 3usize).in_scope(|| {
+            info!(account = "acc1", imported = 2usize, "Account synced");
+        });
+
+        let content = fs::read_to_string(format!("{}/sync.log", directory)).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let event = lines
+            .iter()
+            .find(|line| line["message"] == "Account synced")
+            .unwrap();
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["account"], "acc1");
+        assert_eq!(event["imported"], 2);
+        assert_eq!(event["span"]["name"], "profile");
+        assert_eq!(event["span"]["profile"], "personal");
+        assert_eq!(event["span"]["fetched"], 3);
+
+        // Closing a span logs its fields with timings
+        let close = lines
+            .iter()
+            .find(|line| line["message"] == "close")
+            .unwrap();
+        assert_eq!(close["span"]["profile"], "personal");
+        assert!(close["time.busy"].is_string());
+
+        // Logging is set up once per process
+        assert!(logging::init(&options, true).is_err());
+
+        fs::remove_dir_all(directory).ok();
+    }
+}
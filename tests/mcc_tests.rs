@@ -0,0 +1,82 @@
+use chrono::DateTime;
+use sparebank1_to_ynab::mcc::{self, MerchantGroup};
+use sparebank1_to_ynab::rules::RuleSet;
+use sparebank1_to_ynab::sparebanken1::Transaction as Sparebank1Transaction;
+
+#[cfg(test)]
+mod mcc_tests {
+    use super::*;
+
+    fn create_card_transaction(payee: &str, mcc: &str) -> Sparebank1Transaction {
+        Sparebank1Transaction {
+            id: "txn1".to_string(),
+            payee: payee.to_string(),
+            amount: -250.0,
+            date: DateTime::from_timestamp(1704067200, 0).unwrap(),
+            account: "account1".to_string(),
+            merchant_category_code: Some(mcc.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merchant_groups() {
+        assert_eq!(mcc::merchant_group("5411"), Some(MerchantGroup::Groceries));
+        assert_eq!(mcc::merchant_group("5542"), Some(MerchantGroup::Fuel));
+        assert_eq!(
+            mcc::merchant_group("5812"),
+            Some(MerchantGroup::Restaurants)
+        );
+        assert_eq!(mcc::merchant_group("4121"), Some(MerchantGroup::Transport));
+        // Airline and hotel chains have their own codes in the 3000 range
+        assert_eq!(mcc::merchant_group("3501"), Some(MerchantGroup::Travel));
+        assert_eq!(mcc::merchant_group(" 5912 "), Some(MerchantGroup::Health));
+        assert_eq!(mcc::merchant_group("6011"), None);
+        assert_eq!(mcc::merchant_group("grocery"), None);
+    }
+
+    #[test]
+    fn test_merchant_category_fallback() {
+        let rule_set: RuleSet = serde_json::from_str(
+            r#"{
+                "rules": [
+                    { "name": "Vinmonopolet", "match": { "payee": { "contains": "vinmonopolet" } }, "category": "Fun: Wine" },
+                    { "name": "parking", "match": { "payee": { "contains": "easypark" } }, "skip": true }
+                ],
+                "merchant_categories": {
+                    "groceries": "Everyday: Groceries",
+                    "restaurants": "Fun: Eating out"
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let outcome = rule_set.evaluate(&create_card_transaction("KIWI", "5411"));
+        assert_eq!(outcome.category, Some("Everyday: Groceries".to_string()));
+        assert!(outcome.review);
+        assert!(outcome.merchant_category);
+        assert_eq!(outcome.matched_rules, vec!["merchant group groceries"]);
+
+        // An explicit rule takes precedence
+        let outcome = rule_set.evaluate(&create_card_transaction("VINMONOPOLET", "5921"));
+        assert_eq!(outcome.category, Some("Fun: Wine".to_string()));
+        let outcome = rule_set.evaluate(&create_card_transaction("VINMONOPOLET", "5411"));
+        assert_eq!(outcome.category, Some("Fun: Wine".to_string()));
+        assert!(!outcome.review);
+
+        // Skipped transactions, unmapped groups and unknown codes stay uncategorised
+        let skipped = rule_set.evaluate(&create_card_transaction("EASYPARK", "5411"));
+        assert_eq!(skipped.category, None);
+        let fuel = rule_set.evaluate(&create_card_transaction("CIRCLE K", "5542"));
+        assert_eq!(fuel.category, None);
+        let unknown = rule_set.evaluate(&create_card_transaction("ATM", "6011"));
+        assert!(unknown.matched_rules.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_merchant_group_rejected() {
+        let result: Result<RuleSet, _> =
+            serde_json::from_str(r#"{ "merchant_categories": { "groceries_and_more": "Food" } }"#);
+        assert!(result.is_err());
+    }
+}
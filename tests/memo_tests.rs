@@ -0,0 +1,115 @@
+use chrono::DateTime;
+use sparebank1_to_ynab::memo::{self, MemoTemplate, MAX_MEMO_LENGTH};
+use sparebank1_to_ynab::sparebanken1::Transaction as Sparebank1Transaction;
+
+#[cfg(test)]
+mod memo_tests {
+    use super::*;
+
+    fn create_test_transaction(description: &str) -> Sparebank1Transaction {
+        Sparebank1Transaction {
+            id: "txn1".to_string(),
+            description: description.to_string(),
+            payee: "Rema 1000".to_string(),
+            amount: -125.5,
+            date: DateTime::from_timestamp(1704067200, 0).unwrap(),
+            account: "account1".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_all_placeholders() {
+        let mut transaction = create_test_transaction("REMA 1000 TORGET");
+        transaction.account_name = Some("Brukskonto".to_string());
+        transaction.interest_date = DateTime::from_timestamp(1704153600, 0);
+        transaction.original_amount = Some(-11.2);
+        transaction.original_currency = Some("EUR".to_string());
+
+        let template: MemoTemplate = "{cleaned_description} | {description} | {account_name} | \
+            {original_amount} | {booking_date} | {interest_date} | {id}"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            template.render(&transaction),
+            "Rema 1000 | REMA 1000 TORGET | Brukskonto | -11.20 EUR | 2024-01-01 | 2024-01-02 | txn1"
+        );
+    }
+
+    #[test]
+    fn test_missing_fields_render_empty() {
+        let transaction = create_test_transaction("REMA 1000 TORGET");
+        let template: MemoTemplate = "{description}  {original_amount} {interest_date}"
+            .parse()
+            .unwrap();
+
+        // Whitespace left behind by empty fields is collapsed
+        assert_eq!(template.render(&transaction), "REMA 1000 TORGET");
+    }
+
+    #[test]
+    fn test_unknown_placeholder_rejected() {
+        assert!("{description} {amount}".parse::<MemoTemplate>().is_err());
+        assert!("{description".parse::<MemoTemplate>().is_err());
+        assert!("no placeholders".parse::<MemoTemplate>().is_ok());
+    }
+
+    #[test]
+    fn test_truncates_on_character_boundary() {
+        let transaction = create_test_transaction(&"æøå".repeat(100));
+
+        let rendered = memo::render_memo(None, &transaction);
+        assert_eq!(rendered.chars().count(), MAX_MEMO_LENGTH);
+        assert!(rendered.ends_with('…'));
+
+        let short = create_test_transaction("VIPPS");
+        assert_eq!(memo::render_memo(None, &short), "VIPPS");
+    }
+
+    #[test]
+    fn test_template_deserializes_from_string() {
+        let template: MemoTemplate = serde_json::from_str(r#""{id}: {description}""#).unwrap();
+        assert_eq!(template.to_string(), "{id}: {description}");
+
+        assert!(serde_json::from_str::<MemoTemplate>(r#""{nope}""#).is_err());
+    }
+
+    #[test]
+    fn test_render_exchange_rate() {
+        let template: MemoTemplate = "{original_currency} @ {exchange_rate}".parse().unwrap();
+
+        let mut transaction = create_test_transaction("HOTEL BERLIN");
+        transaction.amount = -1160.0;
+        transaction.original_amount = Some(-100.0);
+        transaction.original_currency = Some("EUR".to_string());
+        // Derived from the amounts when the bank reports no rate
+        assert_eq!(template.render(&transaction), "EUR @ 11.6000");
+
+        transaction.exchange_rate = Some(11.5432);
+        assert_eq!(template.render(&transaction), "EUR @ 11.5432");
+
+        // Domestic transactions have no currency or rate
+        let domestic = create_test_transaction("REMA 1000 TORGET");
+        assert_eq!(template.render(&domestic), "@");
+    }
+
+    #[test]
+    fn test_render_bank_details() {
+        let template: MemoTemplate =
+            "{type} {mcc} {reference} {counterparty_name} {counterparty_account}"
+                .parse()
+                .unwrap();
+
+        let mut transaction = create_test_transaction("GIRO");
+        transaction.type_text = Some("GIRO".to_string());
+        transaction.reference = Some("1234567890".to_string());
+        transaction.counterparty_name = Some("Strøm AS".to_string());
+        transaction.counterparty_account = Some("12345678903".to_string());
+
+        assert_eq!(
+            template.render(&transaction),
+            "GIRO 1234567890 Strøm AS 12345678903"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use sparebank1_to_ynab::metrics::{Api, Metrics, RecordedSend};
+use sparebank1_to_ynab::report::AccountReport;
+use sparebank1_to_ynab::server;
+use sparebank1_to_ynab::sync::{SyncError, SyncSummary};
+use std::fs;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing_subscriber::fmt::format::FmtSpan;
+
+#[cfg(test)]
+mod metrics_tests {
+    use super::*;
+
+    fn create_summary(account: &str, fetched: usize, imported: usize) -> SyncSummary {
+        SyncSummary {
+            accounts: vec![AccountReport {
+                account: account.to_string(),
+                fetched,
+                imported,
+                duplicates: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_account_counters_accumulate_across_syncs() {
+        let metrics = Metrics::default();
+        metrics.record_sync("personal", &Ok(create_summary("checking", 5, 3)));
+        metrics.record_sync("personal", &Ok(create_summary("checking", 2, 1)));
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("# TYPE sparebank1_ynab_transactions_fetched_total counter"));
+        assert!(rendered.contains(
+            "sparebank1_ynab_transactions_fetched_total{profile=\"personal\",account=\"checking\"} 7"
+        ));
+        assert!(rendered.contains(
+            "sparebank1_ynab_transactions_imported_total{profile=\"personal\",account=\"checking\"} 4"
+        ));
+        assert!(rendered.contains(
+            "sparebank1_ynab_transactions_duplicate_total{profile=\"personal\",account=\"checking\"} 2"
+        ));
+        assert!(
+            rendered.contains("sparebank1_ynab_syncs_total{profile=\"personal\",outcome=\"ok\"} 2")
+        );
+        assert!(rendered
+            .contains("sparebank1_ynab_last_success_timestamp_seconds{profile=\"personal\"}"));
+    }
+
+    #[test]
+    fn test_failed_sync_keeps_last_success() {
+        let metrics = Metrics::default();
+        let failure = SyncError::BalanceMismatch {
+            count: 1,
+            tolerance: 0.0,
+            summary: Default::default(),
+        };
+        metrics.record_sync("joint", &Err(failure));
+
+        let rendered = metrics.render();
+        assert!(rendered
+            .contains("sparebank1_ynab_syncs_total{profile=\"joint\",outcome=\"failed\"} 1"));
+        assert!(!rendered.contains("last_success_timestamp_seconds{profile=\"joint\"}"));
+    }
+
+    #[test]
+    fn test_api_requests_and_token_age() {
+        let token_path = "/tmp/test_metrics_refresh_token.txt";
+        fs::write(token_path, "token").unwrap();
+
+        let metrics = Metrics::default();
+        metrics.register_profile("personal", token_path);
+        metrics.register_profile("missing", "/tmp/test_metrics_missing_token.txt");
+        metrics.record_request(Api::Ynab, 0.25, false);
+        metrics.record_request(Api::Ynab, 0.5, true);
+        metrics.record_request(Api::Sparebank1, 1.0, false);
+
+        let rendered = metrics.render();
+        assert!(rendered
+            .contains("sparebank1_ynab_api_request_duration_seconds_sum{api=\"ynab\"} 0.75"));
+        assert!(
+            rendered.contains("sparebank1_ynab_api_request_duration_seconds_count{api=\"ynab\"} 2")
+        );
+        assert!(rendered.contains("sparebank1_ynab_api_errors_total{api=\"ynab\"} 1"));
+        assert!(rendered.contains("sparebank1_ynab_api_errors_total{api=\"sparebank1\"} 0"));
+        assert!(
+            rendered.contains("sparebank1_ynab_refresh_token_age_seconds{profile=\"personal\"}")
+        );
+        // Profiles without a token file have no age
+        assert!(!rendered.contains("refresh_token_age_seconds{profile=\"missing\"}"));
+
+        fs::remove_file(token_path).unwrap();
+    }
+
+    #[test]
+    fn test_server_routes() {
+        let metrics = server::route("GET", "/metrics");
+        assert_eq!(metrics.status, 200);
+        assert!(metrics
+            .content_type
+            .starts_with("text/plain; version=0.0.4"));
+        assert!(metrics
+            .body
+            .contains("# TYPE sparebank1_ynab_syncs_total counter"));
+
+        assert_eq!(server::route("GET", "/metrics?format=text").status, 200);
+        assert_eq!(server::route("GET", "/unknown").status, 404);
+        assert_eq!(server::route("POST", "/metrics").status, 405);
+    }
+
+    #[tokio::test]
+    async fn test_server_drops_oversized_requests() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        server::start(addr).await.unwrap();
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        // A request line that never ends is cut off instead of being read into memory
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /").await.unwrap();
+        stream.write_all(&[b'a'; 64 * 1024]).await.ok();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.ok();
+        assert!(response.is_empty());
+    }
+
+    /// Collects JSON log lines written by a test subscriber
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_spans() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            // A GET request ends with its headers
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let response = reqwest::Client::new()
+            .get(format!(
+                "http://{}/budgets?since=2024-01-01&token=secret",
+                address
+            ))
+            .send_recorded(Api::Ynab)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 503);
+        server.await.unwrap();
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let close: serde_json::Value = output
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .find(|line| line["fields"]["message"] == "close")
+            .unwrap();
+        let span = &close["span"];
+        assert_eq!(span["name"], "http_request");
+        assert_eq!(span["otel.name"], "GET ynab");
+        assert_eq!(span["otel.kind"], "client");
+        assert_eq!(span["api"], "ynab");
+        assert_eq!(span["http.request.method"], "GET");
+        // Query strings can carry tokens and are left out
+        assert_eq!(span["url.full"], format!("http://{}/budgets", address));
+        assert_eq!(span["server.address"], "127.0.0.1");
+        assert_eq!(span["http.response.status_code"], 503);
+        assert_eq!(span["error.type"], "503");
+        assert_eq!(span["otel.status_code"], "ERROR");
+    }
+}
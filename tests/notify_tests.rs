@@ -0,0 +1,345 @@
+use chrono::{DateTime, NaiveDate, TimeZone};
+use chrono_tz::Europe::Oslo;
+use chrono_tz::Tz;
+use reqwest::StatusCode;
+use sparebank1_to_ynab::alerts::{Alert, AlertKind};
+use sparebank1_to_ynab::auth_data::AuthError;
+use sparebank1_to_ynab::notify::{
+    self, ChannelKind, Notification, NotificationConfig, NotificationKind, Notifier,
+};
+use sparebank1_to_ynab::sync::{SyncError, SyncSummary};
+use std::fs;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+#[cfg(test)]
+mod notify_tests {
+    use super::*;
+
+    fn oslo(day: u32, hour: u32) -> DateTime<Tz> {
+        Oslo.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap()
+    }
+
+    fn mismatch() -> SyncError {
+        SyncError::BalanceMismatch {
+            count: 1,
+            tolerance: 0.0,
+            summary: Default::default(),
+        }
+    }
+
+    fn summary(fetched: usize, imported: usize) -> SyncSummary {
+        SyncSummary {
+            fetched,
+            imported,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_read_notification_channels() {
+        let temp_file = "/tmp/test_notifications.json";
+        fs::write(
+            temp_file,
+            r#"{
+                "daily_summary_hour": 8,
+                "channels": [
+                    { "type": "webhook", "url": "https://example.com/hook", "headers": { "X-Token": "abc" } },
+                    { "type": "ntfy", "url": "https://ntfy.sh/budget", "events": ["reauth_required", "daily_summary"] },
+                    { "type": "email", "host": "smtp.example.com", "from": "sync@example.com", "to": ["me@example.com"] }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let config = notify::read_notifications_json(temp_file).unwrap();
+        assert_eq!(config.daily_summary_hour, Some(8));
+        assert_eq!(config.channels.len(), 3);
+        assert!(matches!(
+            &config.channels[0].kind,
+            ChannelKind::Webhook { headers, .. } if headers["X-Token"] == "abc"
+        ));
+        assert_eq!(
+            config.channels[0].events,
+            vec![
+                NotificationKind::Failure,
+                NotificationKind::ReauthRequired,
+                NotificationKind::Alert
+            ]
+        );
+        assert_eq!(
+            config.channels[1].events,
+            vec![
+                NotificationKind::ReauthRequired,
+                NotificationKind::DailySummary
+            ]
+        );
+        assert!(matches!(
+            &config.channels[2].kind,
+            ChannelKind::Email { port: None, .. }
+        ));
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_invalid_notification_settings() {
+        let temp_file = "/tmp/test_notifications_invalid.json";
+        let invalid = [
+            r#"{ "daily_summary_hour": 24, "channels": [] }"#,
+            r#"{ "channels": [{ "type": "webhook", "url": "not a url" }] }"#,
+            r#"{ "channels": [{ "type": "email", "host": "smtp", "from": "nobody", "to": ["me@example.com"] }] }"#,
+            r#"{ "channels": [{ "type": "email", "host": "smtp", "from": "sync@example.com", "to": [] }] }"#,
+            r#"{ "channels": [{ "type": "pager", "url": "https://example.com" }] }"#,
+        ];
+        for content in invalid {
+            fs::write(temp_file, content).unwrap();
+            assert!(
+                notify::read_notifications_json(temp_file).is_err(),
+                "accepted {}",
+                content
+            );
+        }
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_repeated_failures_notified_once() {
+        let mut notifier = Notifier::new(NotificationConfig::default(), oslo(1, 9));
+
+        let notification = notifier.record_sync("personal", &Err(mismatch()));
+        assert_eq!(
+            notification,
+            Some(Notification::Failure {
+                profile: "personal".to_string(),
+                error: "1 accounts differ from SpareBank1 by more than 0 NOK".to_string(),
+                exit_code: 7,
+            })
+        );
+        assert_eq!(notifier.record_sync("personal", &Err(mismatch())), None);
+
+        // A rejected token is a different problem and is notified about
+        let rejected = SyncError::from(AuthError::TokenRejected(StatusCode::BAD_REQUEST));
+        let notification = notifier.record_sync("personal", &Err(rejected)).unwrap();
+        assert_eq!(notification.kind(), NotificationKind::ReauthRequired);
+        assert_eq!(
+            notification.title(),
+            "SpareBank1 login expired for personal"
+        );
+
+        // Failing again after a successful sync is notified about
+        assert_eq!(notifier.record_sync("personal", &Ok(summary(1, 1))), None);
+        assert!(notifier.record_sync("personal", &Err(mismatch())).is_some());
+    }
+
+    #[test]
+    fn test_config_error_notified_once() {
+        let mut notifier = Notifier::new(NotificationConfig::default(), oslo(1, 9));
+
+        let notification = notifier.record_config_error("Unknown profile: househld");
+        assert_eq!(
+            notification,
+            Some(Notification::Failure {
+                profile: notify::ALL_PROFILES.to_string(),
+                error: "Unknown profile: househld".to_string(),
+                exit_code: 3,
+            })
+        );
+        assert_eq!(
+            notifier.record_config_error("Unknown profile: househld"),
+            None
+        );
+
+        // Once the profiles load again, a new configuration error is notified about
+        notifier.record_sync("personal", &Ok(summary(1, 1)));
+        assert!(notifier
+            .record_config_error("Unknown profile: househld")
+            .is_some());
+    }
+
+    #[test]
+    fn test_daily_summary() {
+        let config = NotificationConfig {
+            daily_summary_hour: Some(8),
+            state_path: None,
+            channels: Vec::new(),
+        };
+        // Started after the hour, so the first summary is sent the next day
+        let mut notifier = Notifier::new(config, oslo(1, 9));
+        assert_eq!(notifier.daily_summary_due(oslo(1, 23)), None);
+
+        notifier.record_sync("personal", &Ok(summary(5, 3)));
+        notifier.record_sync("personal", &Ok(summary(2, 2)));
+        notifier.record_sync("personal", &Err(mismatch()));
+        assert_eq!(notifier.daily_summary_due(oslo(2, 7)), None);
+
+        let notification = notifier.daily_summary_due(oslo(2, 8)).unwrap();
+        assert_eq!(
+            notification.title(),
+            "SpareBank1 to YNAB summary for 2024-01-02"
+        );
+        assert_eq!(
+            notification.message(),
+            "personal: imported 5 of 7 fetched transactions, 1 of 3 syncs failed"
+        );
+        assert_eq!(notifier.daily_summary_due(oslo(2, 12)), None);
+
+        let json = serde_json::to_value(&notification).unwrap();
+        assert_eq!(json["event"], "daily_summary");
+        assert_eq!(json["date"], "2024-01-02");
+        assert_eq!(json["profiles"][0]["imported"], 5);
+
+        // Counts start over after a summary
+        let notification = notifier.daily_summary_due(oslo(3, 8)).unwrap();
+        assert_eq!(notification.message(), "");
+    }
+
+    #[test]
+    fn test_state_kept_between_runs() {
+        let state_path = "/tmp/test_notifier_state.json";
+        fs::remove_file(state_path).ok();
+        let config = NotificationConfig {
+            daily_summary_hour: Some(8),
+            state_path: Some(state_path.to_string()),
+            channels: Vec::new(),
+        };
+
+        // Every one-shot run starts a new notifier from the same state file
+        let mut first_run = Notifier::new(config.clone(), oslo(1, 9));
+        assert!(first_run
+            .record_sync("personal", &Err(mismatch()))
+            .is_some());
+        assert_eq!(first_run.daily_summary_due(oslo(1, 9)), None);
+
+        let mut second_run = Notifier::new(config.clone(), oslo(1, 12));
+        assert_eq!(second_run.record_sync("personal", &Err(mismatch())), None);
+        second_run.record_sync("personal", &Ok(summary(4, 2)));
+
+        let mut third_run = Notifier::new(config.clone(), oslo(2, 8));
+        let notification = third_run.daily_summary_due(oslo(2, 8)).unwrap();
+        assert_eq!(
+            notification.message(),
+            "personal: imported 2 of 4 fetched transactions, 2 of 3 syncs failed"
+        );
+
+        let mut fourth_run = Notifier::new(config, oslo(2, 9));
+        assert_eq!(fourth_run.daily_summary_due(oslo(2, 9)), None);
+        assert!(fourth_run
+            .record_sync("personal", &Err(mismatch()))
+            .is_some());
+
+        fs::remove_file(state_path).ok();
+    }
+
+    #[test]
+    fn test_alert_notification() {
+        assert_eq!(Notification::for_alerts("personal", &[]), None);
+
+        let alert = Alert {
+            account: "account1".to_string(),
+            transaction_id: "t1".to_string(),
+            date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            payee: "Elkjøp".to_string(),
+            amount: -15000.0,
+            kind: AlertKind::LargeAmount { threshold: 5000.0 },
+        };
+        let notification = Notification::for_alerts("personal", &[alert]).unwrap();
+        assert_eq!(notification.kind(), NotificationKind::Alert);
+        assert_eq!(notification.title(), "1 transactions to check for personal");
+        assert_eq!(
+            notification.message(),
+            "2024-01-02 | Elkjøp | -15000.00 NOK | above 5000.00 NOK"
+        );
+
+        let json = serde_json::to_value(&notification).unwrap();
+        assert_eq!(json["event"], "alert");
+        assert_eq!(json["alerts"][0]["kind"], "large_amount");
+        assert_eq!(json["alerts"][0]["transaction_id"], "t1");
+
+        // Alerts are counted in the daily summary
+        let config = NotificationConfig {
+            daily_summary_hour: Some(8),
+            state_path: None,
+            channels: Vec::new(),
+        };
+        let mut notifier = Notifier::new(config, oslo(1, 9));
+        let mut synced = summary(3, 3);
+        if let Notification::Alert { alerts, .. } = notification {
+            synced.alerts = alerts;
+        }
+        notifier.record_sync("personal", &Ok(synced));
+        assert_eq!(
+            notifier.daily_summary_due(oslo(2, 8)).unwrap().message(),
+            "personal: imported 3 of 3 fetched transactions, 0 of 1 syncs failed, 1 alerts"
+        );
+    }
+
+    /// Accepts a single HTTP request and returns it as text
+    async fn capture_request(listener: TcpListener) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let read = stream.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|l| {
+                        l.to_lowercase()
+                            .strip_prefix("content-length: ")?
+                            .parse()
+                            .ok()
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break;
+                }
+            }
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_send_webhook_and_ntfy() {
+        let webhook = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let ntfy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config: NotificationConfig = serde_json::from_value(serde_json::json!({
+            "channels": [
+                { "type": "webhook", "url": format!("http://{}/hook", webhook.local_addr().unwrap()) },
+                { "type": "ntfy", "url": format!("http://{}/budget", ntfy.local_addr().unwrap()), "token": "secret" },
+                { "type": "ntfy", "url": "http://127.0.0.1:9/unused", "events": ["daily_summary"] }
+            ]
+        }))
+        .unwrap();
+        let notifier = Notifier::new(config, oslo(1, 9));
+        let webhook = tokio::spawn(capture_request(webhook));
+        let ntfy = tokio::spawn(capture_request(ntfy));
+
+        let notification = Notification::Failure {
+            profile: "personal".to_string(),
+            error: "YNAB request failed".to_string(),
+            exit_code: 6,
+        };
+        notifier.send(&notification).await;
+
+        let webhook = webhook.await.unwrap();
+        assert!(webhook.starts_with("POST /hook HTTP/1.1"));
+        assert!(webhook.ends_with(
+            r#"{"event":"failure","profile":"personal","error":"YNAB request failed","exit_code":6}"#
+        ));
+
+        let ntfy = ntfy.await.unwrap().to_lowercase();
+        assert!(ntfy.starts_with("post /budget http/1.1"));
+        assert!(ntfy.contains("title: sparebank1 to ynab sync failed for personal"));
+        assert!(ntfy.contains("priority: high"));
+        assert!(ntfy.contains("authorization: bearer secret"));
+        assert!(ntfy.ends_with("ynab request failed (exit code 6)"));
+    }
+}
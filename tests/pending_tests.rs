@@ -0,0 +1,219 @@
+use chrono::{DateTime, NaiveDate};
+use sparebank1_to_ynab::pending::{self, PostedImport, VanishedPending};
+use sparebank1_to_ynab::sparebanken1::{BookingStatus, Transaction as Sparebank1Transaction};
+use sparebank1_to_ynab::ynab::{ImportTransaction, TransactionDetail, UpdateYnabTransaction};
+
+#[cfg(test)]
+mod pending_tests {
+    use super::*;
+
+    // 2024-01-02 00:00 in Oslo
+    const JAN_2: i64 = 1704150000;
+    const DAY: i64 = 86400;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn create_test_import(
+        import_id: &str,
+        amount: f64,
+        date_timestamp: i64,
+        status: BookingStatus,
+    ) -> ImportTransaction {
+        let transaction = Sparebank1Transaction {
+            id: import_id.to_string(),
+            payee: "Narvesen".to_string(),
+            amount,
+            date: DateTime::from_timestamp(date_timestamp, 0).unwrap(),
+            account: "account1".to_string(),
+            booking_status: status,
+            ..Default::default()
+        };
+        ImportTransaction::new(transaction, import_id.to_string())
+    }
+
+    fn create_ynab_transaction(
+        id: &str,
+        import_id: &str,
+        amount: i64,
+        day: u32,
+    ) -> TransactionDetail {
+        TransactionDetail {
+            id: id.to_string(),
+            date: date(day),
+            amount,
+            account_id: "ynab-account".to_string(),
+            payee_name: Some("Narvesen".to_string()),
+            memo: None,
+            cleared: "uncleared".to_string(),
+            approved: false,
+            flag_color: None,
+            category_id: None,
+            import_id: Some(import_id.to_string()),
+            deleted: false,
+            ..Default::default()
+        }
+    }
+
+    fn posted(imports: &[ImportTransaction]) -> Vec<PostedImport<'_>> {
+        imports
+            .iter()
+            .enumerate()
+            .map(|(index, import)| PostedImport {
+                index,
+                import,
+                account_id: "ynab-account",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_still_pending_left_alone() {
+        let imports = vec![create_test_import(
+            "SB1P:-50:2024-01-02:1",
+            -50.0,
+            JAN_2,
+            BookingStatus::Pending,
+        )];
+        let ynab = vec![create_ynab_transaction(
+            "y1",
+            "SB1P:-50:2024-01-02:1",
+            -50000,
+            2,
+        )];
+
+        let plan = pending::plan_pending(&ynab, &posted(&imports), date(2), VanishedPending::Flag);
+        assert_eq!(plan, Default::default());
+    }
+
+    #[test]
+    fn test_booked_settles_pending() {
+        let imports = vec![create_test_import(
+            "SB1:-52.5:2024-01-04:1",
+            -52.5,
+            JAN_2 + 2 * DAY,
+            BookingStatus::Booked,
+        )];
+        let ynab = vec![create_ynab_transaction(
+            "y1",
+            "SB1P:-50:2024-01-02:1",
+            -50000,
+            2,
+        )];
+
+        // Amount changed, matched by payee
+        let plan = pending::plan_pending(&ynab, &posted(&imports), date(2), VanishedPending::Flag);
+        assert_eq!(plan.settled, vec![(0, "ynab-account".to_string())]);
+        assert_eq!(
+            plan.updates,
+            vec![UpdateYnabTransaction {
+                id: "y1".to_string(),
+                date: Some(date(4)),
+                amount: Some(-52500),
+                cleared: Some("cleared".to_string()),
+                ..Default::default()
+            }]
+        );
+        assert_eq!(plan.cleared(), 1);
+        assert_eq!(plan.vanished, 0);
+    }
+
+    #[test]
+    fn test_already_settled_not_posted_again() {
+        let imports = vec![create_test_import(
+            "SB1:-50:2024-01-03:1",
+            -50.0,
+            JAN_2 + DAY,
+            BookingStatus::Booked,
+        )];
+        let mut settled = create_ynab_transaction("y1", "SB1P:-50:2024-01-02:1", -50000, 3);
+        settled.cleared = "cleared".to_string();
+
+        let plan = pending::plan_pending(
+            &[settled],
+            &posted(&imports),
+            date(2),
+            VanishedPending::Flag,
+        );
+        assert_eq!(plan.settled, vec![(0, "ynab-account".to_string())]);
+        assert!(plan.updates.is_empty());
+    }
+
+    #[test]
+    fn test_booked_already_imported_does_not_settle() {
+        let imports = vec![create_test_import(
+            "SB1:-50:2024-01-03:1",
+            -50.0,
+            JAN_2 + DAY,
+            BookingStatus::Booked,
+        )];
+        let ynab = vec![
+            create_ynab_transaction("y1", "SB1P:-50:2024-01-02:1", -50000, 2),
+            create_ynab_transaction("y2", "SB1:-50:2024-01-03:1", -50000, 3),
+        ];
+
+        let plan = pending::plan_pending(&ynab, &posted(&imports), date(2), VanishedPending::Flag);
+        assert!(plan.settled.is_empty());
+        assert_eq!(plan.vanished, 1);
+    }
+
+    #[test]
+    fn test_vanished_pending_flagged_once() {
+        let ynab = vec![create_ynab_transaction(
+            "y1",
+            "SB1P:-50:2024-01-02:1",
+            -50000,
+            2,
+        )];
+
+        let plan = pending::plan_pending(&ynab, &[], date(2), VanishedPending::Flag);
+        assert_eq!(plan.vanished, 1);
+        assert_eq!(
+            plan.updates[0].flag_color.as_deref(),
+            Some(pending::VANISHED_FLAG_COLOR)
+        );
+
+        let mut flagged = ynab[0].clone();
+        flagged.flag_color = Some(pending::VANISHED_FLAG_COLOR.to_string());
+        let plan = pending::plan_pending(&[flagged], &[], date(2), VanishedPending::Flag);
+        assert_eq!(plan, Default::default());
+    }
+
+    #[test]
+    fn test_vanished_pending_deleted() {
+        let ynab = vec![create_ynab_transaction(
+            "y1",
+            "SB1P:-50:2024-01-02:1",
+            -50000,
+            2,
+        )];
+
+        let plan = pending::plan_pending(&ynab, &[], date(2), VanishedPending::Delete);
+        assert_eq!(plan.deletes, vec!["y1".to_string()]);
+        assert!(plan.updates.is_empty());
+    }
+
+    #[test]
+    fn test_pending_before_window_not_vanished() {
+        let ynab = vec![create_ynab_transaction(
+            "y1",
+            "SB1P:-50:2024-01-01:1",
+            -50000,
+            1,
+        )];
+
+        let plan = pending::plan_pending(&ynab, &[], date(2), VanishedPending::Delete);
+        assert_eq!(plan, Default::default());
+    }
+
+    #[test]
+    fn test_parse_vanished_pending() {
+        assert_eq!("flag".parse::<VanishedPending>(), Ok(VanishedPending::Flag));
+        assert_eq!(
+            "DELETE".parse::<VanishedPending>(),
+            Ok(VanishedPending::Delete)
+        );
+        assert!("ignore".parse::<VanishedPending>().is_err());
+    }
+}
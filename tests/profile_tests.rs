@@ -0,0 +1,340 @@
+use sparebank1_to_ynab::learn::LearnMode;
+use sparebank1_to_ynab::profile;
+use std::env;
+use std::fs;
+use std::io::Write;
+
+#[cfg(test)]
+mod profile_tests {
+    use super::*;
+
+    fn write_profiles(path: &str, content: &str) {
+        let mut file = fs::File::create(path).unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+    }
+
+    /// Two profiles "a" and "b" with the given sync state paths
+    fn shared_state_profiles(a: &str, b: &str) -> String {
+        serde_json::json!({
+            "a": {
+                "sparebank1_client_id": "client",
+                "sparebank1_client_secret": "secret",
+                "sparebank1_fin_inst": "fin",
+                "ynab_access_token": "token",
+                "ynab_budget_id": "budget_a",
+                "account_config_path": "/tmp/a.json",
+                "initial_refresh_token": "refresh",
+                "sync_state_path": a
+            },
+            "b": {
+                "sparebank1_client_id": "client",
+                "sparebank1_client_secret": "secret",
+                "sparebank1_fin_inst": "fin",
+                "ynab_access_token": "token",
+                "ynab_budget_id": "budget_b",
+                "account_config_path": "/tmp/b.json",
+                "initial_refresh_token": "refresh",
+                "sync_state_path": b
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_read_multiple_profiles() {
+        let temp_file = "/tmp/test_profiles_valid.json";
+        write_profiles(
+            temp_file,
+            r#"{
+                "household": {
+                    "sparebank1_client_id": "client_a",
+                    "sparebank1_client_secret": "secret_a",
+                    "sparebank1_fin_inst": "fin_a",
+                    "ynab_access_token": "token_a",
+                    "ynab_budget_id": "budget_household",
+                    "account_config_path": "/tmp/household.json",
+                    "refresh_token_file_path": "/tmp/household_token.txt",
+                    "initial_refresh_token": "refresh_a"
+                },
+                "personal": {
+                    "sparebank1_client_id": "client_b",
+                    "sparebank1_client_secret": "secret_b",
+                    "sparebank1_fin_inst": "fin_b",
+                    "ynab_access_token": "token_b",
+                    "ynab_budget_id": "budget_personal",
+                    "account_config_path": "/tmp/personal.json",
+                    "initial_refresh_token": "refresh_b",
+                    "dry_run": true
+                }
+            }"#,
+        );
+
+        let profiles = profile::read_profiles_json(temp_file).unwrap();
+        assert_eq!(profiles.len(), 2);
+
+        assert_eq!(profiles[0].name, "household");
+        assert_eq!(profiles[0].config.ynab_budget_id, "budget_household");
+        assert_eq!(
+            profiles[0].config.refresh_token_file_path,
+            "/tmp/household_token.txt"
+        );
+        assert!(!profiles[0].config.dry_run);
+
+        assert_eq!(profiles[1].name, "personal");
+        assert_eq!(profiles[1].config.sparebank1_client_id, "client_b");
+        // Profiles without an explicit token store get their own file
+        assert_eq!(
+            profiles[1].config.refresh_token_file_path,
+            "refresh_token_personal.txt"
+        );
+        // Updating changed transactions stays off unless a sync state file is configured
+        assert_eq!(profiles[1].config.sync_state_path, None);
+        assert!(profiles[1].config.dry_run);
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_shared_refresh_token_file_rejected() {
+        let temp_file = "/tmp/test_profiles_shared_token.json";
+        write_profiles(
+            temp_file,
+            r#"{
+                "a": {
+                    "sparebank1_client_id": "client",
+                    "sparebank1_client_secret": "secret",
+                    "sparebank1_fin_inst": "fin",
+                    "ynab_access_token": "token",
+                    "ynab_budget_id": "budget_a",
+                    "account_config_path": "/tmp/a.json",
+                    "refresh_token_file_path": "shared.txt",
+                    "initial_refresh_token": "refresh"
+                },
+                "b": {
+                    "sparebank1_client_id": "client",
+                    "sparebank1_client_secret": "secret",
+                    "sparebank1_fin_inst": "fin",
+                    "ynab_access_token": "token",
+                    "ynab_budget_id": "budget_b",
+                    "account_config_path": "/tmp/b.json",
+                    "refresh_token_file_path": "shared.txt",
+                    "initial_refresh_token": "refresh"
+                }
+            }"#,
+        );
+
+        let result = profile::read_profiles_json(temp_file);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("refresh token"));
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_invalid_profile_reports_name() {
+        let temp_file = "/tmp/test_profiles_invalid.json";
+        write_profiles(
+            temp_file,
+            r#"{
+                "broken": {
+                    "sparebank1_client_id": "client",
+                    "sparebank1_client_secret": "secret",
+                    "sparebank1_fin_inst": "fin",
+                    "ynab_access_token": "token",
+                    "ynab_budget_id": "",
+                    "account_config_path": "/tmp/a.json",
+                    "initial_refresh_token": "refresh"
+                }
+            }"#,
+        );
+
+        let result = profile::read_profiles_json(temp_file);
+        assert!(result.is_err());
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("broken"));
+        assert!(message.contains("BUDGET_ID"));
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_empty_profiles_rejected() {
+        let temp_file = "/tmp/test_profiles_empty.json";
+        write_profiles(temp_file, "{}");
+
+        assert!(profile::read_profiles_json(temp_file).is_err());
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_missing_profiles_file() {
+        assert!(profile::read_profiles_json("/nonexistent/profiles.json").is_err());
+    }
+
+    #[test]
+    fn test_shared_sync_state_file_rejected() {
+        let temp_file = "/tmp/test_profiles_shared_state.json";
+        write_profiles(
+            temp_file,
+            r#"{
+                "a": {
+                    "sparebank1_client_id": "client",
+                    "sparebank1_client_secret": "secret",
+                    "sparebank1_fin_inst": "fin",
+                    "ynab_access_token": "token",
+                    "ynab_budget_id": "budget_a",
+                    "account_config_path": "/tmp/a.json",
+                    "initial_refresh_token": "refresh",
+                    "sync_state_path": "state.json"
+                },
+                "b": {
+                    "sparebank1_client_id": "client",
+                    "sparebank1_client_secret": "secret",
+                    "sparebank1_fin_inst": "fin",
+                    "ynab_access_token": "token",
+                    "ynab_budget_id": "budget_b",
+                    "account_config_path": "/tmp/b.json",
+                    "initial_refresh_token": "refresh",
+                    "sync_state_path": "state.json"
+                }
+            }"#,
+        );
+
+        let result = profile::read_profiles_json(temp_file);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("sync state"));
+
+        // Paths naming the same file differently are rejected too
+        let absolute = env::current_dir().unwrap().join("state.json");
+        for (a, b) in [
+            ("./state.json", "state.json"),
+            ("logs/../state.json", "state.json"),
+            ("state.json", absolute.to_str().unwrap()),
+        ] {
+            write_profiles(temp_file, &shared_state_profiles(a, b));
+            let result = profile::read_profiles_json(temp_file);
+            assert!(result.unwrap_err().to_string().contains("sync state"));
+        }
+
+        // Different files in the same directory are fine
+        write_profiles(
+            temp_file,
+            r#"{
+                "a": {
+                    "sparebank1_client_id": "client",
+                    "sparebank1_client_secret": "secret",
+                    "sparebank1_fin_inst": "fin",
+                    "ynab_access_token": "token",
+                    "ynab_budget_id": "budget_a",
+                    "account_config_path": "/tmp/a.json",
+                    "initial_refresh_token": "refresh",
+                    "sync_state_path": "./sync_state_a.json"
+                },
+                "b": {
+                    "sparebank1_client_id": "client",
+                    "sparebank1_client_secret": "secret",
+                    "sparebank1_fin_inst": "fin",
+                    "ynab_access_token": "token",
+                    "ynab_budget_id": "budget_b",
+                    "account_config_path": "/tmp/b.json",
+                    "initial_refresh_token": "refresh",
+                    "sync_state_path": "logs/../sync_state_b.json"
+                }
+            }"#,
+        );
+        assert!(profile::read_profiles_json(temp_file).is_ok());
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_negative_balance_tolerance_rejected() {
+        let temp_file = "/tmp/test_profiles_negative_tolerance.json";
+        write_profiles(
+            temp_file,
+            r#"{
+                "a": {
+                    "sparebank1_client_id": "client",
+                    "sparebank1_client_secret": "secret",
+                    "sparebank1_fin_inst": "fin",
+                    "ynab_access_token": "token",
+                    "ynab_budget_id": "budget_a",
+                    "account_config_path": "/tmp/a.json",
+                    "initial_refresh_token": "refresh",
+                    "balance_tolerance": -1.0
+                }
+            }"#,
+        );
+
+        let result = profile::read_profiles_json(temp_file);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("profile 'a'"));
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_learn_categories_settings() {
+        let temp_file = "/tmp/test_profiles_learn.json";
+        let profile_json = |confidence: &str| {
+            format!(
+                r#"{{
+                    "a": {{
+                        "sparebank1_client_id": "client",
+                        "sparebank1_client_secret": "secret",
+                        "sparebank1_fin_inst": "fin",
+                        "ynab_access_token": "token",
+                        "ynab_budget_id": "budget_a",
+                        "account_config_path": "/tmp/a.json",
+                        "initial_refresh_token": "refresh",
+                        "learn_categories": "suggest",
+                        "learn_confidence": {}
+                    }}
+                }}"#,
+                confidence
+            )
+        };
+
+        write_profiles(temp_file, &profile_json("0.9"));
+        let profiles = profile::read_profiles_json(temp_file).unwrap();
+        assert_eq!(profiles[0].config.learn_categories, LearnMode::Suggest);
+        assert_eq!(profiles[0].config.learn_confidence, 0.9);
+
+        write_profiles(temp_file, &profile_json("1.5"));
+        assert!(profile::read_profiles_json(temp_file).is_err());
+
+        fs::remove_file(temp_file).ok();
+    }
+
+    #[test]
+    fn test_refresh_token_lifetime() {
+        let temp_file = "/tmp/test_profiles_token_lifetime.json";
+        let profile_json = |lifetime: u32| {
+            format!(
+                r#"{{
+                    "a": {{
+                        "sparebank1_client_id": "client",
+                        "sparebank1_client_secret": "secret",
+                        "sparebank1_fin_inst": "fin",
+                        "ynab_access_token": "token",
+                        "ynab_budget_id": "budget_a",
+                        "account_config_path": "/tmp/a.json",
+                        "initial_refresh_token": "refresh",
+                        "refresh_token_lifetime_days": {}
+                    }}
+                }}"#,
+                lifetime
+            )
+        };
+
+        write_profiles(temp_file, &profile_json(90));
+        let profiles = profile::read_profiles_json(temp_file).unwrap();
+        assert_eq!(profiles[0].config.refresh_token_lifetime_days, 90);
+
+        write_profiles(temp_file, &profile_json(0));
+        assert!(profile::read_profiles_json(temp_file).is_err());
+
+        fs::remove_file(temp_file).ok();
+    }
+}
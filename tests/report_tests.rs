@@ -0,0 +1,185 @@
+use chrono::DateTime;
+use sparebank1_to_ynab::balance::BalanceCheck;
+use sparebank1_to_ynab::report::{AccountReports, ProfileReport, SyncReport};
+use sparebank1_to_ynab::sparebanken1::Transaction;
+use sparebank1_to_ynab::sync::{SyncError, SyncSummary};
+
+#[cfg(test)]
+mod report_tests {
+    use super::*;
+
+    fn create_transaction(account: &str, account_name: &str) -> Transaction {
+        Transaction {
+            id: format!("{}-txn", account),
+            account: account.to_string(),
+            account_name: Some(account_name.to_string()),
+            date: DateTime::from_timestamp(1704067200, 0).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn create_check(account: &str, bank_balance: i64, ynab_cleared_balance: i64) -> BalanceCheck {
+        BalanceCheck {
+            account_key: account.to_string(),
+            account_name: "Brukskonto".to_string(),
+            budget_id: "default".to_string(),
+            ynab_account_id: "ynab-checking".to_string(),
+            ynab_account_name: "Checking".to_string(),
+            bank_balance,
+            ynab_cleared_balance,
+            balance_only: false,
+        }
+    }
+
+    fn create_report(profiles: Vec<ProfileReport>) -> SyncReport {
+        SyncReport {
+            started_at: DateTime::from_timestamp(1704067200, 0).unwrap(),
+            finished_at: DateTime::from_timestamp(1704067260, 0).unwrap(),
+            profiles,
+        }
+    }
+
+    #[test]
+    fn test_account_reports_count_per_account() {
+        let keys = vec!["checking".to_string(), "savings".to_string()];
+        let mut reports = AccountReports::new(&keys);
+        reports.add_fetched(&[
+            create_transaction("checking", "Brukskonto"),
+            create_transaction("checking", "Brukskonto"),
+        ]);
+        reports.account("checking").skipped += 1;
+        reports.account("checking").imported += 1;
+        reports.add_balance(&create_check("checking", 100_000, 100_500), 1000);
+
+        let accounts = reports.finish();
+        assert_eq!(accounts.len(), 2);
+
+        let checking = &accounts[0];
+        assert_eq!(checking.account, "checking");
+        assert_eq!(checking.name.as_deref(), Some("Brukskonto"));
+        assert_eq!(checking.fetched, 2);
+        assert_eq!(checking.skipped, 1);
+        assert_eq!(checking.imported, 1);
+        assert_eq!(checking.balances[0].difference, 500);
+        assert!(checking.balances[0].matches);
+        assert!(checking.errors.is_empty());
+
+        // Mapped accounts SpareBank1 did not return are reported as errors
+        let savings = &accounts[1];
+        assert_eq!(savings.fetched, 0);
+        assert_eq!(
+            savings.errors,
+            vec!["balance not checked, account not found".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_report_json_shape() {
+        let keys = vec!["checking".to_string()];
+        let mut reports = AccountReports::new(&keys);
+        reports.add_balance(&create_check("checking", 100_000, 90_000), 0);
+        let summary = SyncSummary {
+            fetched: 3,
+            imported: 2,
+            accounts: reports.finish(),
+            ..Default::default()
+        };
+        let report = create_report(vec![
+            ProfileReport::ok("personal", false, summary),
+            ProfileReport::failed("joint", true, "token expired".to_string(), None),
+        ]);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["started_at"], "2024-01-01T00:00:00Z");
+
+        let personal = &json["profiles"][0];
+        assert_eq!(personal["status"], "ok");
+        assert_eq!(personal["fetched"], 3);
+        assert!(personal.get("error").is_none());
+        assert_eq!(personal["accounts"][0]["account"], "checking");
+        assert_eq!(
+            personal["accounts"][0]["balances"][0]["difference"],
+            -10_000
+        );
+        assert_eq!(personal["accounts"][0]["balances"][0]["matches"], false);
+
+        let joint = &json["profiles"][1];
+        assert_eq!(joint["status"], "failed");
+        assert_eq!(joint["dry_run"], true);
+        assert_eq!(joint["error"], "token expired");
+        assert!(joint.get("accounts").is_none());
+        assert_eq!(report.failed(), 1);
+    }
+
+    #[test]
+    fn test_report_table() {
+        let keys = vec!["checking".to_string()];
+        let mut reports = AccountReports::new(&keys);
+        reports.add_fetched(&[create_transaction("checking", "Brukskonto")]);
+        reports.account("checking").imported += 1;
+        reports.add_balance(&create_check("checking", 100_000, 90_000), 0);
+        let summary = SyncSummary {
+            accounts: reports.finish(),
+            ..Default::default()
+        };
+        let report = create_report(vec![
+            ProfileReport::ok("personal", false, summary),
+            ProfileReport::failed("joint", false, "token expired".to_string(), None),
+        ]);
+
+        let table = report.to_table();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            "PROFILE   ACCOUNT     FETCHED  SKIPPED  IMPORTED  DUPLICATES  UPDATED  ALERTS  BALANCE"
+        );
+        assert_eq!(
+            lines[1],
+            "personal  Brukskonto  1        0        1         0           0        0       off -10.00"
+        );
+        assert_eq!(lines[2], "joint     failed: token expired");
+    }
+
+    #[test]
+    fn test_balance_mismatch_keeps_summary() {
+        let keys = vec!["checking".to_string()];
+        let mut reports = AccountReports::new(&keys);
+        reports.add_fetched(&[create_transaction("checking", "Brukskonto")]);
+        reports.account("checking").imported += 1;
+        reports.add_balance(&create_check("checking", 100_000, 90_000), 0);
+        let error = SyncError::BalanceMismatch {
+            count: 1,
+            tolerance: 0.0,
+            summary: Box::new(SyncSummary {
+                fetched: 1,
+                imported: 1,
+                accounts: reports.finish(),
+                ..Default::default()
+            }),
+        };
+        let report = create_report(vec![ProfileReport::failed(
+            "personal",
+            false,
+            error.to_string(),
+            error.summary().cloned(),
+        )]);
+
+        let json = serde_json::to_value(&report).unwrap();
+        let personal = &json["profiles"][0];
+        assert_eq!(personal["status"], "failed");
+        assert_eq!(personal["imported"], 1);
+        assert_eq!(personal["accounts"][0]["account"], "checking");
+
+        // The imported transactions are shown above the failure
+        let table = report.to_table();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("personal  Brukskonto  1"));
+        assert_eq!(
+            lines[2],
+            "personal  failed: 1 accounts differ from SpareBank1 by more than 0 NOK"
+        );
+        assert_eq!(report.failed(), 1);
+    }
+}
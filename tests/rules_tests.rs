@@ -0,0 +1,457 @@
+use chrono::{DateTime, TimeZone, Utc};
+use sparebank1_to_ynab::rules::{self, FlagColor, RuleSet, SplitLine};
+use sparebank1_to_ynab::sparebanken1::Transaction as Sparebank1Transaction;
+use sparebank1_to_ynab::sync;
+use std::fs;
+use std::io::Write;
+
+#[cfg(test)]
+mod rules_tests {
+    use super::*;
+
+    fn create_test_transaction(
+        payee: &str,
+        description: &str,
+        amount: f64,
+        account: &str,
+    ) -> Sparebank1Transaction {
+        Sparebank1Transaction {
+            id: "txn1".to_string(),
+            description: description.to_string(),
+            payee: payee.to_string(),
+            amount,
+            date: DateTime::from_timestamp(1704067200, 0).unwrap(),
+            account: account.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn parse_rules(json: &str) -> RuleSet {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_regex_payee_rewrite() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                { "name": "Norgesgruppen", "match": { "payee": { "regex": "^VIPPS \\*NORGESGRUPPEN \\d+$" } }, "payee": "Norgesgruppen" }
+            ] }"#,
+        );
+
+        let transaction =
+            create_test_transaction("VIPPS *NORGESGRUPPEN 1234", "", -250.0, "account1");
+        let outcome = rule_set.evaluate(&transaction);
+        assert_eq!(outcome.payee, Some("Norgesgruppen".to_string()));
+        assert_eq!(outcome.matched_rules, vec!["Norgesgruppen".to_string()]);
+
+        let other = create_test_transaction("VIPPS *KIWI 1234", "", -250.0, "account1");
+        assert_eq!(rule_set.evaluate(&other).payee, None);
+    }
+
+    #[test]
+    fn test_contains_and_prefix_are_case_insensitive() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                { "match": { "description": { "contains": "netflix" } }, "payee": "Netflix" },
+                { "match": { "payee": { "prefix": "rema" } }, "payee": "REMA 1000" }
+            ] }"#,
+        );
+
+        let netflix = create_test_transaction("", "Varekjøp NETFLIX.COM", -119.0, "account1");
+        assert_eq!(
+            rule_set.evaluate(&netflix).payee,
+            Some("Netflix".to_string())
+        );
+
+        let rema = create_test_transaction("REMA 1000 GRUNERLOKKA", "", -80.0, "account1");
+        let outcome = rule_set.evaluate(&rema);
+        assert_eq!(outcome.payee, Some("REMA 1000".to_string()));
+        // Unnamed rules are reported by position
+        assert_eq!(outcome.matched_rules, vec!["rule #2".to_string()]);
+    }
+
+    #[test]
+    fn test_all_conditions_must_match() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                {
+                    "match": {
+                        "payee": { "equals": "vipps" },
+                        "account": "card_account",
+                        "amount": { "min": -500, "max": 0 }
+                    },
+                    "payee": "Small Vipps"
+                }
+            ] }"#,
+        );
+
+        let matching = create_test_transaction("VIPPS", "", -100.0, "card_account");
+        assert_eq!(
+            rule_set.evaluate(&matching).payee,
+            Some("Small Vipps".to_string())
+        );
+
+        let wrong_account = create_test_transaction("VIPPS", "", -100.0, "other_account");
+        assert_eq!(rule_set.evaluate(&wrong_account).payee, None);
+
+        let too_large = create_test_transaction("VIPPS", "", -1000.0, "card_account");
+        assert_eq!(rule_set.evaluate(&too_large).payee, None);
+
+        let income = create_test_transaction("VIPPS", "", 100.0, "card_account");
+        assert_eq!(rule_set.evaluate(&income).payee, None);
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                { "name": "specific", "match": { "payee": { "contains": "circle k" } }, "payee": "Circle K" },
+                { "name": "generic", "match": {}, "payee": "Unknown" }
+            ] }"#,
+        );
+
+        let transaction = create_test_transaction("CIRCLE K MAJORSTUEN", "", -450.0, "account1");
+        let outcome = rule_set.evaluate(&transaction);
+        assert_eq!(outcome.payee, Some("Circle K".to_string()));
+        assert_eq!(outcome.matched_rules, vec!["specific".to_string()]);
+    }
+
+    #[test]
+    fn test_prepare_transactions_rewrites_payee_name() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                { "match": { "payee": { "prefix": "VIPPS *" } }, "payee": "Vipps" }
+            ] }"#,
+        );
+
+        let transactions = vec![
+            create_test_transaction("VIPPS *OLA NORDMANN", "", -50.0, "account1"),
+            create_test_transaction("KIWI 123", "", -75.0, "account1"),
+        ];
+        let prepared = sync::prepare_transactions(transactions, &rule_set, |_| true);
+
+        assert_eq!(prepared.imports[0].payee_name, "Vipps");
+        // The bank's own values are kept for matching and memos
+        assert_eq!(prepared.imports[0].transaction.payee, "VIPPS *OLA NORDMANN");
+        assert_eq!(prepared.imports[1].payee_name, "KIWI 123");
+    }
+
+    #[test]
+    fn test_invalid_regex_rejected() {
+        let result: Result<RuleSet, _> = serde_json::from_str(
+            r#"{ "rules": [ { "match": { "payee": { "regex": "(unclosed" } }, "payee": "x" } ] }"#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_unknown_matcher_rejected() {
+        let result: Result<RuleSet, _> = serde_json::from_str(
+            r#"{ "rules": [ { "match": { "payee": { "suffix": "x" } }, "payee": "x" } ] }"#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_read_rules_json() {
+        let temp_file = "/tmp/test_rules_valid.json";
+        let mut file = fs::File::create(temp_file).unwrap();
+        let json_content = r#"{ "rules": [
+            { "match": { "payee": { "contains": "spotify" } }, "payee": "Spotify" }
+        ] }"#;
+        file.write_all(json_content.as_bytes()).unwrap();
+
+        let rule_set = rules::read_rules_json(temp_file).unwrap();
+        assert_eq!(rule_set.rules.len(), 1);
+
+        fs::remove_file(temp_file).ok();
+
+        assert!(rules::read_rules_json("/nonexistent/rules.json").is_err());
+    }
+
+    #[test]
+    fn test_category_and_review() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                { "match": { "payee": { "contains": "rema" } }, "category": "Groceries" },
+                { "match": { "payee": { "contains": "vipps" } }, "category": "Everyday: Misc", "review": true },
+                { "match": {}, "category": "Fallback", "payee": "Renamed" }
+            ] }"#,
+        );
+
+        let rema = create_test_transaction("REMA 1000", "", -80.0, "account1");
+        let outcome = rule_set.evaluate(&rema);
+        assert_eq!(outcome.category, Some("Groceries".to_string()));
+        assert!(!outcome.review);
+        // Later rules still contribute changes not set by earlier ones
+        assert_eq!(outcome.payee, Some("Renamed".to_string()));
+        assert_eq!(outcome.matched_rules.len(), 2);
+
+        let vipps = create_test_transaction("VIPPS *KARI", "", -200.0, "account1");
+        let outcome = rule_set.evaluate(&vipps);
+        assert_eq!(outcome.category, Some("Everyday: Misc".to_string()));
+        assert!(outcome.review);
+
+        let prepared = sync::prepare_transactions(vec![vipps], &rule_set, |_| true);
+        assert_eq!(
+            prepared.imports[0].category,
+            Some("Everyday: Misc".to_string())
+        );
+        assert!(prepared.imports[0].review);
+    }
+
+    #[test]
+    fn test_skip_rules() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                { "name": "round-up savings", "match": { "description": { "contains": "sparerunding" } }, "skip": true },
+                { "name": "zero amount", "match": { "amount": { "min": 0, "max": 0 } }, "skip": true },
+                { "name": "card test charges", "match": { "account": "card_account", "amount": { "min": -1, "max": 1 } }, "skip": true }
+            ] }"#,
+        );
+
+        let round_up = create_test_transaction("", "SPARERUNDING 12", -8.0, "account1");
+        assert_eq!(
+            rule_set.evaluate(&round_up).skipped_by,
+            Some("round-up savings".to_string())
+        );
+
+        let authorisation = create_test_transaction("HOTEL", "", 0.0, "account1");
+        assert_eq!(
+            rule_set.evaluate(&authorisation).skipped_by,
+            Some("zero amount".to_string())
+        );
+
+        let test_charge = create_test_transaction("SHOP", "", -1.0, "card_account");
+        assert_eq!(
+            rule_set.evaluate(&test_charge).skipped_by,
+            Some("card test charges".to_string())
+        );
+
+        let regular = create_test_transaction("SHOP", "", -1.0, "account1");
+        assert_eq!(rule_set.evaluate(&regular).skipped_by, None);
+    }
+
+    #[test]
+    fn test_date_condition_uses_oslo_date() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                { "match": { "date": { "from": "2024-01-01", "to": "2024-01-31" } }, "skip": true }
+            ] }"#,
+        );
+
+        let mut transaction = create_test_transaction("SHOP", "", -10.0, "account1");
+
+        // 2023-12-31 23:30 UTC is already 2024-01-01 in Oslo
+        transaction.date = Utc.with_ymd_and_hms(2023, 12, 31, 23, 30, 0).unwrap();
+        assert!(rule_set.evaluate(&transaction).skipped_by.is_some());
+
+        transaction.date = Utc.with_ymd_and_hms(2024, 2, 1, 12, 0, 0).unwrap();
+        assert!(rule_set.evaluate(&transaction).skipped_by.is_none());
+    }
+
+    #[test]
+    fn test_prepare_transactions_lists_skipped() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                { "name": "no zero", "match": { "amount": { "min": 0, "max": 0 } }, "skip": true }
+            ] }"#,
+        );
+
+        let transactions = vec![
+            create_test_transaction("A", "", -50.0, "account1"),
+            create_test_transaction("B", "", 0.0, "account1"),
+            create_test_transaction("C", "", -50.0, "account1"),
+        ];
+        let prepared = sync::prepare_transactions(transactions, &rule_set, |_| true);
+
+        assert_eq!(prepared.imports.len(), 2);
+        assert_eq!(prepared.skipped.len(), 1);
+        assert_eq!(prepared.skipped[0].transaction.payee, "B");
+        assert_eq!(prepared.skipped[0].rule, "no zero");
+        // Occurrence numbering is unaffected by skipped transactions
+        assert_eq!(prepared.imports[0].import_id, "SB1:-50:2024-01-01:1");
+        assert_eq!(prepared.imports[1].import_id, "SB1:-50:2024-01-01:2");
+    }
+
+    #[test]
+    fn test_balance_only_accounts_keep_import_ids() {
+        let rule_set = RuleSet::default();
+
+        // Same amount and date on both accounts, the balance-only savings account comes first
+        let transactions = vec![
+            create_test_transaction("Interest", "", -50.0, "savings"),
+            create_test_transaction("KIWI 123", "", -50.0, "checking"),
+        ];
+        let prepared =
+            sync::prepare_transactions(transactions, &rule_set, |t| t.account == "checking");
+
+        assert_eq!(prepared.not_imported, 1);
+        assert_eq!(prepared.imports.len(), 1);
+        assert_eq!(prepared.imports[0].transaction.account, "checking");
+        // The id matches the one given while savings was still imported
+        assert_eq!(prepared.imports[0].import_id, "SB1:-50:2024-01-01:2");
+    }
+
+    fn split_line(amount: Option<f64>, percent: Option<f64>) -> SplitLine {
+        SplitLine {
+            amount,
+            percent,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_split_fixed_amount_with_remainder() {
+        let lines = vec![split_line(Some(8000.0), None), split_line(None, None)];
+
+        // Fixed amounts take the sign of the transaction
+        assert_eq!(
+            rules::split_amounts(&lines, -12_500_000),
+            Some(vec![-8_000_000, -4_500_000])
+        );
+        // The remainder may not flip the sign of the transaction
+        assert_eq!(rules::split_amounts(&lines, -7_000_000), None);
+    }
+
+    #[test]
+    fn test_split_percentages_absorb_rounding() {
+        let lines = vec![
+            split_line(None, Some(33.0)),
+            split_line(None, Some(33.0)),
+            split_line(None, Some(34.0)),
+        ];
+        let amounts = rules::split_amounts(&lines, -100_010).unwrap();
+        assert_eq!(amounts.iter().sum::<i64>(), -100_010);
+        assert_eq!(amounts, vec![-33_003, -33_003, -34_004]);
+
+        // Lines that do not cover the total without a remainder line are rejected
+        let partial = vec![split_line(None, Some(50.0)), split_line(None, Some(20.0))];
+        assert_eq!(rules::split_amounts(&partial, -100_000), None);
+    }
+
+    #[test]
+    fn test_split_rule_prepares_subtransactions() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                {
+                    "name": "mortgage",
+                    "match": { "payee": { "contains": "boliglån" } },
+                    "split": [
+                        { "amount": 6000, "category": "Housing: Interest", "memo": "Interest" },
+                        { "category": "Housing: Principal", "memo": "Principal" }
+                    ],
+                    "review": true
+                },
+                { "match": {}, "category": "Everything else" }
+            ] }"#,
+        );
+
+        let mortgage = create_test_transaction("BOLIGLÅN 1234", "", -10000.0, "account1");
+        let outcome = rule_set.evaluate(&mortgage);
+        assert!(outcome.split.is_some());
+        // A split replaces the category from later rules
+        assert_eq!(outcome.category, None);
+        assert!(outcome.review);
+
+        let prepared = sync::prepare_transactions(vec![mortgage], &rule_set, |_| true);
+        let subtransactions = &prepared.imports[0].subtransactions;
+        assert_eq!(subtransactions.len(), 2);
+        assert_eq!(subtransactions[0].amount, -6_000_000);
+        assert_eq!(
+            subtransactions[0].category,
+            Some("Housing: Interest".to_string())
+        );
+        assert_eq!(subtransactions[1].amount, -4_000_000);
+        assert_eq!(subtransactions[1].memo, Some("Principal".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_split_rules_rejected() {
+        let invalid_rules = [
+            r#"{ "rules": [ { "split": [ { "category": "A" } ] } ] }"#,
+            r#"{ "rules": [ { "split": [ { "amount": 1, "percent": 50 }, { "category": "B" } ] } ] }"#,
+            r#"{ "rules": [ { "split": [ { "category": "A" }, { "category": "B" } ] } ] }"#,
+            r#"{ "rules": [ { "category": "A", "split": [ { "amount": 1 }, { "category": "B" } ] } ] }"#,
+        ];
+
+        for json in invalid_rules {
+            let rule_set: RuleSet = serde_json::from_str(json).unwrap();
+            assert!(rule_set.validate().is_err(), "expected invalid: {}", json);
+        }
+    }
+
+    #[test]
+    fn test_flag_foreign_purchases() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                { "name": "euro travel", "match": { "currency": { "equals": "eur" } }, "category": "Travel" },
+                { "name": "foreign", "match": { "foreign": true }, "flag": "purple" }
+            ] }"#,
+        );
+
+        let mut euro = create_test_transaction("HOTEL", "", -1160.0, "account1");
+        euro.original_amount = Some(-100.0);
+        euro.original_currency = Some("EUR".to_string());
+        let outcome = rule_set.evaluate(&euro);
+        assert_eq!(outcome.category, Some("Travel".to_string()));
+        assert_eq!(outcome.flag, Some(FlagColor::Purple));
+
+        let mut dollar = create_test_transaction("SHOP", "", -105.0, "account1");
+        dollar.original_currency = Some("USD".to_string());
+        let outcome = rule_set.evaluate(&dollar);
+        assert_eq!(outcome.category, None);
+        assert_eq!(outcome.matched_rules, vec!["foreign".to_string()]);
+
+        // Domestic transactions count as NOK whether or not the bank reports a currency
+        let mut domestic = create_test_transaction("SHOP", "", -105.0, "account1");
+        assert_eq!(rule_set.evaluate(&domestic).flag, None);
+        domestic.original_currency = Some("NOK".to_string());
+        assert_eq!(rule_set.evaluate(&domestic).flag, None);
+
+        let prepared = sync::prepare_transactions(vec![euro], &rule_set, |_| true);
+        assert_eq!(prepared.imports[0].flag, Some(FlagColor::Purple));
+    }
+
+    #[test]
+    fn test_unknown_flag_color_rejected() {
+        let result: Result<RuleSet, _> =
+            serde_json::from_str(r#"{ "rules": [ { "flag": "pink" } ] }"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_bank_detail_conditions() {
+        let rule_set = parse_rules(
+            r#"{ "rules": [
+                { "name": "groceries", "match": { "mcc": { "equals": "5411" } }, "category": "Groceries" },
+                { "name": "rent", "match": { "counterparty_account": { "equals": "12345678903" }, "reference": { "contains": "husleie" } }, "category": "Rent" },
+                { "name": "fees", "match": { "type_code": { "prefix": "GEB" } }, "category": "Bank fees" }
+            ] }"#,
+        );
+
+        let mut grocery = create_test_transaction("KIWI", "", -300.0, "account1");
+        grocery.merchant_category_code = Some("5411".to_string());
+        assert_eq!(
+            rule_set.evaluate(&grocery).category,
+            Some("Groceries".to_string())
+        );
+
+        let mut rent = create_test_transaction("UTLEIER", "", -12000.0, "account1");
+        rent.counterparty_account = Some("12345678903".to_string());
+        rent.reference = Some("Husleie mars".to_string());
+        assert_eq!(rule_set.evaluate(&rent).category, Some("Rent".to_string()));
+
+        // A condition on a field the bank did not send never matches
+        let mut no_reference = create_test_transaction("UTLEIER", "", -12000.0, "account1");
+        no_reference.counterparty_account = Some("12345678903".to_string());
+        assert_eq!(rule_set.evaluate(&no_reference).category, None);
+
+        let mut fee = create_test_transaction("GEBYR", "", -25.0, "account1");
+        fee.type_code = Some("GEBYR".to_string());
+        assert_eq!(
+            rule_set.evaluate(&fee).category,
+            Some("Bank fees".to_string())
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use sparebank1_to_ynab::sparebanken1::{self, BookingStatus};
+
+#[cfg(test)]
+mod sparebanken1_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_all_transaction_fields() {
+        let body = r#"{ "transactions": [ {
+            "id": "txn1",
+            "amount": -1160.0,
+            "description": "HOTEL BERLIN",
+            "cleanedDescription": "Hotel Berlin",
+            "accountKey": "account1",
+            "accountName": "Brukskonto",
+            "date": 1704067200000,
+            "interestDate": 1704153600000,
+            "originalAmount": -100.0,
+            "originalCurrency": "EUR",
+            "exchangeRate": 11.6,
+            "bookingStatus": "BOOKED",
+            "kidOrMessage": "1234567890",
+            "typeCode": 714,
+            "typeText": "VARER",
+            "merchantCategoryCode": 7011,
+            "remoteAccountNumber": "12345678903",
+            "remoteAccountName": "Hotel Berlin GmbH",
+            "source": "RECENT",
+            "isConfidential": false
+        } ] }"#;
+
+        let transactions = sparebanken1::parse_transactions(body).unwrap();
+        assert_eq!(transactions.len(), 1);
+        let transaction = &transactions[0];
+        assert_eq!(transaction.payee, "Hotel Berlin");
+        assert_eq!(transaction.date.timestamp(), 1704067200);
+        assert_eq!(transaction.interest_date.unwrap().timestamp(), 1704153600);
+        assert_eq!(transaction.exchange_rate, Some(11.6));
+        assert_eq!(transaction.booking_status, BookingStatus::Booked);
+        assert_eq!(transaction.reference.as_deref(), Some("1234567890"));
+        // Codes are read whether the API sends them as numbers or strings
+        assert_eq!(transaction.type_code.as_deref(), Some("714"));
+        assert_eq!(transaction.merchant_category_code.as_deref(), Some("7011"));
+        assert_eq!(
+            transaction.counterparty_account.as_deref(),
+            Some("12345678903")
+        );
+        assert_eq!(
+            transaction.counterparty_name.as_deref(),
+            Some("Hotel Berlin GmbH")
+        );
+        assert_eq!(transaction.source.as_deref(), Some("RECENT"));
+
+        // Unmapped fields are kept as they came
+        assert_eq!(transaction.extra.len(), 1);
+        assert_eq!(transaction.extra["isConfidential"], false);
+    }
+
+    #[test]
+    fn test_parse_minimal_transaction() {
+        let body = r#"{ "transactions": [ {
+            "id": "txn1",
+            "amount": -125.5,
+            "accountKey": "account1",
+            "date": 1704067200000,
+            "bookingStatus": "RESERVED",
+            "merchantCategoryCode": null
+        } ] }"#;
+
+        let transactions = sparebanken1::parse_transactions(body).unwrap();
+        let transaction = &transactions[0];
+        assert_eq!(transaction.description, "");
+        assert!(transaction.is_pending());
+        assert_eq!(transaction.merchant_category_code, None);
+        assert_eq!(transaction.reference, None);
+        assert!(transaction.extra.is_empty());
+    }
+
+    #[test]
+    fn test_serialize_transaction() {
+        let body = r#"{ "transactions": [ {
+            "id": "txn1",
+            "amount": -125.5,
+            "accountKey": "account1",
+            "date": 1704067200000,
+            "kidOrMessage": "Faktura 42",
+            "classificationInput": { "id": "abc" }
+        } ] }"#;
+
+        let transactions = sparebanken1::parse_transactions(body).unwrap();
+        let json = serde_json::to_value(&transactions[0]).unwrap();
+        assert_eq!(json["reference"], "Faktura 42");
+        assert_eq!(json["booking_status"], "booked");
+        assert_eq!(json["date"], "2024-01-01T00:00:00Z");
+        assert_eq!(json["extra"]["classificationInput"]["id"], "abc");
+    }
+
+    #[test]
+    fn test_invalid_code_rejected() {
+        let body = r#"{ "transactions": [ {
+            "id": "txn1",
+            "amount": -125.5,
+            "accountKey": "account1",
+            "date": 1704067200000,
+            "merchantCategoryCode": [5411]
+        } ] }"#;
+
+        assert!(sparebanken1::parse_transactions(body).is_err());
+    }
+
+    #[test]
+    fn test_parse_accounts() {
+        let body = r#"{ "accounts": [ {
+            "key": "account1",
+            "name": "Brukskonto",
+            "accountNumber": "12345678903",
+            "balance": 1234.56
+        } ] }"#;
+
+        let accounts = sparebanken1::parse_accounts(body).unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].key, "account1");
+        assert_eq!(accounts[0].account_number, "12345678903");
+        assert_eq!(accounts[0].balance, 1234.56);
+    }
+
+    #[test]
+    fn test_invalid_accounts_response_rejected() {
+        assert!(sparebanken1::parse_accounts(r#"{ "accounts": "none" }"#).is_err());
+        assert!(sparebanken1::parse_accounts("<html>maintenance</html>").is_err());
+    }
+}
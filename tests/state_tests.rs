@@ -0,0 +1,196 @@
+use chrono::NaiveDate;
+use sparebank1_to_ynab::state::{self, BankValues, PushedTransaction, SyncState};
+use sparebank1_to_ynab::ynab::{TransactionDetail, UpdateYnabTransaction};
+use std::fs;
+
+#[cfg(test)]
+mod state_tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    /// Values written for a bank transaction whose payee and description were used as they are
+    fn create_pushed(amount: i64, payee: &str, memo: &str) -> PushedTransaction {
+        PushedTransaction {
+            bank_id: "bank1".to_string(),
+            budget_id: "budget".to_string(),
+            account_id: "ynab-account".to_string(),
+            import_id: "SB1:-50:2024-01-02:1".to_string(),
+            date: date(2),
+            amount,
+            payee_name: Some(payee.to_string()),
+            memo: memo.to_string(),
+            bank: Some(BankValues {
+                date: date(2),
+                amount,
+                payee: payee.to_string(),
+                description: memo.to_string(),
+            }),
+        }
+    }
+
+    fn create_ynab_transaction(pushed: &PushedTransaction) -> TransactionDetail {
+        TransactionDetail {
+            id: "y1".to_string(),
+            date: pushed.date,
+            amount: pushed.amount,
+            account_id: pushed.account_id.clone(),
+            payee_name: pushed.payee_name.clone(),
+            memo: Some(pushed.memo.clone()),
+            cleared: "cleared".to_string(),
+            approved: true,
+            flag_color: None,
+            category_id: None,
+            import_id: Some(pushed.import_id.clone()),
+            deleted: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bank_change_updates_ynab() {
+        let recorded = create_pushed(-50000, "Rema 1000", "REMA 1000 TORGET");
+        let in_ynab = create_ynab_transaction(&recorded);
+        let mut current = create_pushed(-55000, "Rema 1000 Torget", "REMA 1000 TORGET");
+        current.date = date(3);
+        current.bank.as_mut().unwrap().date = date(3);
+
+        let plan = state::plan_update(&recorded, &current, &in_ynab);
+        assert_eq!(
+            plan.update,
+            Some(UpdateYnabTransaction {
+                id: "y1".to_string(),
+                date: Some(date(3)),
+                amount: Some(-55000),
+                payee_name: Some("Rema 1000 Torget".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(plan.recorded.amount, -55000);
+        assert_eq!(plan.recorded.import_id, recorded.import_id);
+        assert!(plan.user_edited.is_empty());
+    }
+
+    #[test]
+    fn test_user_edits_are_kept() {
+        let recorded = create_pushed(-50000, "Rema 1000", "REMA 1000 TORGET");
+        let mut in_ynab = create_ynab_transaction(&recorded);
+        in_ynab.payee_name = Some("Groceries".to_string());
+        in_ynab.memo = Some("Dinner party".to_string());
+        let current = create_pushed(-55000, "Rema", "REMA 1000 STORGATA");
+
+        let plan = state::plan_update(&recorded, &current, &in_ynab);
+        assert_eq!(
+            plan.update,
+            Some(UpdateYnabTransaction {
+                id: "y1".to_string(),
+                amount: Some(-55000),
+                ..Default::default()
+            })
+        );
+        assert_eq!(plan.user_edited, vec!["payee", "memo"]);
+        // Edited fields keep their recorded values so they stay detected as edited
+        assert_eq!(plan.recorded.payee_name.as_deref(), Some("Rema 1000"));
+    }
+
+    #[test]
+    fn test_unchanged_transaction_not_updated() {
+        let recorded = create_pushed(-50000, "Rema 1000", "REMA 1000 TORGET");
+        let mut in_ynab = create_ynab_transaction(&recorded);
+        in_ynab.memo = Some("Edited".to_string());
+
+        let plan = state::plan_update(&recorded, &recorded.clone(), &in_ynab);
+        assert_eq!(plan.update, None);
+        assert!(plan.user_edited.is_empty());
+        assert_eq!(plan.recorded, recorded);
+    }
+
+    #[test]
+    fn test_changed_rules_and_templates_not_applied() {
+        let recorded = create_pushed(-50000, "Rema 1000", "REMA 1000 TORGET");
+        let in_ynab = create_ynab_transaction(&recorded);
+        // A new rule renames the payee and a new template renders another memo
+        let mut current = recorded.clone();
+        current.payee_name = Some("Groceries".to_string());
+        current.memo = "Card purchase: REMA 1000 TORGET".to_string();
+
+        let plan = state::plan_update(&recorded, &current, &in_ynab);
+        assert_eq!(plan.update, None);
+        assert_eq!(plan.recorded, recorded);
+
+        // Once the bank changes the description, the memo is rendered again
+        current.bank.as_mut().unwrap().description = "REMA 1000 STORTORGET".to_string();
+        let plan = state::plan_update(&recorded, &current, &in_ynab);
+        assert_eq!(
+            plan.update,
+            Some(UpdateYnabTransaction {
+                id: "y1".to_string(),
+                memo: Some("Card purchase: REMA 1000 TORGET".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(plan.recorded.bank, current.bank);
+    }
+
+    #[test]
+    fn test_record_without_bank_values_taken_as_current() {
+        let mut recorded = create_pushed(-50000, "Rema 1000", "REMA 1000 TORGET");
+        recorded.bank = None;
+        let in_ynab = create_ynab_transaction(&recorded);
+        let current = create_pushed(-55000, "Rema 1000", "REMA 1000 TORGET");
+
+        let plan = state::plan_update(&recorded, &current, &in_ynab);
+        assert_eq!(plan.update, None);
+        assert_eq!(plan.recorded.amount, -50000);
+        assert_eq!(plan.recorded.bank, current.bank);
+    }
+
+    #[test]
+    fn test_transfer_payee_not_compared() {
+        let recorded = create_pushed(-50000, "Overføring", "");
+        let in_ynab = create_ynab_transaction(&recorded);
+        let mut current = recorded.clone();
+        current.payee_name = None;
+
+        let plan = state::plan_update(&recorded, &current, &in_ynab);
+        assert_eq!(plan.update, None);
+    }
+
+    #[test]
+    fn test_record_replaces_and_prunes() {
+        let mut sync_state = SyncState::default();
+        sync_state.record(create_pushed(-50000, "Rema 1000", ""));
+        sync_state.record(create_pushed(-55000, "Rema 1000", ""));
+        assert_eq!(sync_state.transactions.len(), 1);
+        assert_eq!(
+            sync_state.find("bank1", "ynab-account").unwrap().amount,
+            -55000
+        );
+        assert!(sync_state.find("bank1", "other-account").is_none());
+
+        sync_state.prune(date(3));
+        assert!(sync_state.transactions.is_empty());
+    }
+
+    #[test]
+    fn test_state_file_round_trip() {
+        let temp_file = "/tmp/test_sync_state.json";
+        fs::remove_file(temp_file).ok();
+
+        // A missing file is an empty state
+        let mut sync_state = state::read_state(temp_file).unwrap();
+        assert!(sync_state.transactions.is_empty());
+
+        sync_state.record(create_pushed(-50000, "Rema 1000", "memo"));
+        state::write_state(temp_file, &sync_state).unwrap();
+        // Written through a temporary file that is renamed into place
+        assert!(fs::metadata(format!("{}.tmp", temp_file)).is_err());
+
+        let read_back = state::read_state(temp_file).unwrap();
+        assert_eq!(read_back.transactions, sync_state.transactions);
+
+        fs::remove_file(temp_file).ok();
+    }
+}
@@ -0,0 +1,239 @@
+use sparebank1_to_ynab::config::Config;
+use sparebank1_to_ynab::logging::{self, LogFormat, LogOptions, LogRotation};
+use sparebank1_to_ynab::profile::Profile;
+use sparebank1_to_ynab::sync;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+#[cfg(test)]
+mod sync_tests {
+    use super::*;
+
+    /// Canned response for a request line of the SpareBank1 and YNAB APIs
+    ///
+    /// Budget "budget" has two regular accounts, budget "adjust" a balance-only savings account
+    /// whose adjustment YNAB already has.
+    fn respond(request_line: &str) -> serde_json::Value {
+        let mut parts = request_line.split(' ');
+        let method = parts.next().unwrap_or_default();
+        let path = parts.next().unwrap_or_default();
+        let path = path.split('?').next().unwrap_or_default();
+        match (method, path) {
+            (_, "/oauth/token") => serde_json::json!({
+                "access_token": "access",
+                "refresh_token": "rotated"
+            }),
+            (_, "/banking/transactions") => serde_json::json!({ "transactions": [
+                { "id": "t1", "amount": -100.0, "description": "KIWI", "accountKey": "checking",
+                  "date": 1704067200000u64, "bookingStatus": "BOOKED" },
+                { "id": "t2", "amount": -50.0, "description": "REMA", "accountKey": "checking",
+                  "date": 1704067200000u64, "bookingStatus": "BOOKED" },
+                { "id": "t3", "amount": 20.0, "description": "Renter", "accountKey": "savings",
+                  "date": 1704067200000u64, "bookingStatus": "BOOKED" }
+            ] }),
+            (_, "/banking/accounts") => serde_json::json!({ "accounts": [
+                { "key": "checking", "name": "Brukskonto", "accountNumber": "1", "balance": 1000.0 },
+                { "key": "savings", "name": "Sparekonto", "accountNumber": "2", "balance": 500.0 }
+            ] }),
+            (_, "/ynab/budgets/budget/accounts") => serde_json::json!({ "data": { "accounts": [
+                { "id": "ynab-checking", "name": "Checking", "cleared_balance": 1000000, "closed": false },
+                { "id": "ynab-savings", "name": "Savings", "cleared_balance": 400000, "closed": false }
+            ] } }),
+            (_, "/ynab/budgets/adjust/accounts") => serde_json::json!({ "data": { "accounts": [
+                { "id": "ynab-bsu", "name": "BSU", "cleared_balance": 400000, "closed": false }
+            ] } }),
+            ("POST", "/ynab/budgets/adjust/transactions") => serde_json::json!({ "data": {
+                "transaction_ids": [],
+                "duplicate_import_ids": ["SB1-ADJ:100000:2024-01-01:1"]
+            } }),
+            (_, "/ynab/budgets/budget/transactions" | "/ynab/budgets/adjust/transactions") => {
+                serde_json::json!({ "data": { "transactions": [] } })
+            }
+            _ => serde_json::Value::Null,
+        }
+    }
+
+    /// Answers one request per connection with the canned response for its path
+    async fn serve(mut stream: TcpStream) {
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        loop {
+            let read = stream.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|l| {
+                        l.to_lowercase()
+                            .strip_prefix("content-length: ")?
+                            .parse()
+                            .ok()
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length || read == 0 {
+                    break;
+                }
+            }
+        }
+        let text = String::from_utf8_lossy(&request);
+        let body = respond(text.lines().next().unwrap_or_default()).to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    }
+
+    /// Starts the fake API server once for all tests and points the clients at it
+    fn start_server() {
+        static SERVER: OnceLock<()> = OnceLock::new();
+        SERVER.get_or_init(|| {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            listener.set_nonblocking(true).unwrap();
+            std::thread::spawn(move || {
+                let runtime = tokio::runtime::Runtime::new().unwrap();
+                runtime.block_on(async move {
+                    let listener = TcpListener::from_std(listener).unwrap();
+                    loop {
+                        let (stream, _) = listener.accept().await.unwrap();
+                        tokio::spawn(serve(stream));
+                    }
+                });
+            });
+            env::set_var("SPAREBANK1_AUTH_URL", format!("{}/oauth/token", base));
+            env::set_var("SPAREBANK1_API_URL", format!("{}/banking", base));
+            env::set_var("YNAB_API_URL", format!("{}/ynab", base));
+        });
+    }
+
+    #[tokio::test]
+    async fn test_account_spans() {
+        let directory = "/tmp/test_sync_spans";
+        fs::remove_dir_all(directory).ok();
+        fs::create_dir_all(directory).unwrap();
+        let account_config_path = format!("{}/accounts.json", directory);
+        fs::write(
+            &account_config_path,
+            r#"{ "checking": "ynab-checking", "savings": "ynab-savings" }"#,
+        )
+        .unwrap();
+
+        start_server();
+
+        let options = LogOptions {
+            log_format: LogFormat::Json,
+            log_file: Some(PathBuf::from(format!("{}/sync.log", directory))),
+            log_rotation: LogRotation::Never,
+            ..Default::default()
+        };
+        let _guard = logging::init(&options, true).unwrap();
+
+        let config = Config::with_values(
+            "client".to_string(),
+            "secret".to_string(),
+            "fid".to_string(),
+            "ynab-token".to_string(),
+            "budget".to_string(),
+            account_config_path,
+            Some(format!("{}/refresh_token.txt", directory)),
+            "refresh".to_string(),
+        )
+        .unwrap();
+        let profile = Profile {
+            name: "personal".to_string(),
+            config,
+        };
+        let summary = sync::sync_profile(&profile, true).await.unwrap();
+        assert_eq!(summary.fetched, 3);
+        assert_eq!(summary.balance_mismatches, 1);
+
+        let content = fs::read_to_string(format!("{}/sync.log", directory)).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        // Each account's span closes with its counts and how long its balance work took
+        let close = |account: &str| {
+            lines
+                .iter()
+                .find(|line| {
+                    line["message"] == "close"
+                        && line["span"]["name"] == "account"
+                        && line["span"]["account"] == account
+                })
+                .unwrap()
+        };
+        let checking = close("checking");
+        assert_eq!(checking["span"]["account_name"], "Brukskonto");
+        assert_eq!(checking["span"]["fetched"], 2);
+        assert_eq!(checking["span"]["imported"], 0);
+        assert_eq!(checking["span"]["alerts"], 0);
+        assert!(checking["span"]["duration_ms"].is_u64());
+        assert_eq!(close("savings")["span"]["fetched"], 1);
+
+        // Balance checks are logged inside the span of their account
+        let mismatch = lines
+            .iter()
+            .find(|line| {
+                line["message"]
+                    .as_str()
+                    .is_some_and(|m| m.contains("[balance mismatch]"))
+            })
+            .unwrap();
+        assert_eq!(mismatch["span"]["name"], "account");
+        assert_eq!(mismatch["span"]["account"], "savings");
+
+        fs::remove_dir_all(directory).ok();
+    }
+
+    #[tokio::test]
+    async fn test_adjustment_already_posted_keeps_difference() {
+        start_server();
+        let directory = "/tmp/test_sync_adjust";
+        fs::remove_dir_all(directory).ok();
+        fs::create_dir_all(directory).unwrap();
+        let account_config_path = format!("{}/accounts.json", directory);
+        fs::write(
+            &account_config_path,
+            r#"{ "savings": { "account_id": "ynab-bsu", "balance_only": true } }"#,
+        )
+        .unwrap();
+
+        let config = Config::with_values(
+            "client".to_string(),
+            "secret".to_string(),
+            "fid".to_string(),
+            "ynab-token".to_string(),
+            "adjust".to_string(),
+            account_config_path,
+            Some(format!("{}/refresh_token.txt", directory)),
+            "refresh".to_string(),
+        )
+        .unwrap();
+        let profile = Profile {
+            name: "bsu".to_string(),
+            config,
+        };
+        let summary = sync::sync_profile(&profile, false).await.unwrap();
+
+        // YNAB already had today's adjustment, so the account is still reported as it is in YNAB
+        assert_eq!(summary.adjusted, 0);
+        let savings = summary
+            .accounts
+            .iter()
+            .find(|a| a.account == "savings")
+            .unwrap();
+        assert_eq!(savings.balances[0].ynab_cleared_balance, 400000);
+        assert_eq!(savings.balances[0].difference, -100000);
+
+        fs::remove_dir_all(directory).ok();
+    }
+}
@@ -0,0 +1,174 @@
+use chrono::DateTime;
+use sparebank1_to_ynab::sparebanken1::Transaction as Sparebank1Transaction;
+use sparebank1_to_ynab::transfers::{self, TransferLink, TransferSide};
+use sparebank1_to_ynab::ynab::ImportTransaction;
+
+#[cfg(test)]
+mod transfers_tests {
+    use super::*;
+
+    const DAY: i64 = 86400;
+
+    fn create_test_import(
+        id: &str,
+        description: &str,
+        amount: f64,
+        date_timestamp: i64,
+        account: &str,
+    ) -> ImportTransaction {
+        let transaction = Sparebank1Transaction {
+            id: id.to_string(),
+            description: description.to_string(),
+            payee: description.to_string(),
+            amount,
+            date: DateTime::from_timestamp(date_timestamp, 0).unwrap(),
+            account: account.to_string(),
+            ..Default::default()
+        };
+        ImportTransaction::new(transaction, format!("import-{}", id))
+    }
+
+    #[test]
+    fn test_links_matching_transfer() {
+        let mut imports = vec![
+            create_test_import(
+                "1",
+                "Overføring til sparekonto",
+                -500.0,
+                1704067200,
+                "checking",
+            ),
+            create_test_import("2", "Rema 1000", -500.0, 1704067200, "checking"),
+            create_test_import(
+                "3",
+                "Overføring fra brukskonto",
+                500.0,
+                1704067200 + DAY,
+                "savings",
+            ),
+        ];
+
+        assert_eq!(transfers::link_transfers(&mut imports), 1);
+        assert_eq!(
+            imports[0].transfer,
+            Some(TransferLink {
+                account: "savings".to_string(),
+                transaction_id: "3".to_string(),
+                side: TransferSide::Outgoing,
+            })
+        );
+        assert_eq!(imports[1].transfer, None);
+        assert_eq!(
+            imports[2].transfer,
+            Some(TransferLink {
+                account: "checking".to_string(),
+                transaction_id: "1".to_string(),
+                side: TransferSide::Incoming,
+            })
+        );
+    }
+
+    #[test]
+    fn test_requires_transfer_like_description() {
+        let mut imports = vec![
+            create_test_import("1", "Rema 1000", -500.0, 1704067200, "checking"),
+            create_test_import("2", "Lønn", 500.0, 1704067200, "savings"),
+        ];
+
+        assert_eq!(transfers::link_transfers(&mut imports), 0);
+    }
+
+    #[test]
+    fn test_counterpart_account_name_marks_transfer() {
+        let mut imports = vec![
+            create_test_import("1", "Til Buffer", -250.0, 1704067200, "checking"),
+            create_test_import("2", "Innskudd", 250.0, 1704067200, "savings"),
+        ];
+        imports[1].transaction.account_name = Some("Buffer".to_string());
+
+        assert_eq!(transfers::link_transfers(&mut imports), 1);
+    }
+
+    #[test]
+    fn test_rejects_mismatched_pairs() {
+        let mut imports = vec![
+            // Same account
+            create_test_import("1", "Overføring", -100.0, 1704067200, "checking"),
+            create_test_import("2", "Overføring", 100.0, 1704067200, "checking"),
+            // Amounts differ
+            create_test_import("3", "Overføring", -200.0, 1704067200, "checking"),
+            create_test_import("4", "Overføring", 200.5, 1704067200, "savings"),
+            // Too far apart
+            create_test_import("5", "Overføring", -300.0, 1704067200, "checking"),
+            create_test_import("6", "Overføring", 300.0, 1704067200 + 5 * DAY, "savings"),
+        ];
+
+        assert_eq!(transfers::link_transfers(&mut imports), 0);
+        assert!(imports.iter().all(|import| import.transfer.is_none()));
+    }
+
+    #[test]
+    fn test_pairs_closest_date_once() {
+        let mut imports = vec![
+            create_test_import("1", "Overføring", -100.0, 1704067200, "checking"),
+            create_test_import("2", "Overføring", 100.0, 1704067200 + 2 * DAY, "savings"),
+            create_test_import("3", "Overføring", 100.0, 1704067200, "savings"),
+            create_test_import("4", "Overføring", -100.0, 1704067200 + 2 * DAY, "checking"),
+        ];
+
+        assert_eq!(transfers::link_transfers(&mut imports), 2);
+        assert_eq!(
+            imports[2].transfer.as_ref().unwrap().side,
+            TransferSide::Incoming
+        );
+        assert_eq!(
+            imports[1].transfer.as_ref().unwrap().side,
+            TransferSide::Incoming
+        );
+        assert_eq!(
+            imports[3].transfer.as_ref().unwrap().side,
+            TransferSide::Outgoing
+        );
+    }
+
+    fn create_linked_transfers() -> Vec<ImportTransaction> {
+        let mut imports = vec![
+            create_test_import("1", "Overføring", -100.0, 1704067200, "checking"),
+            create_test_import("2", "Overføring", 100.0, 1704067200, "savings"),
+            create_test_import("3", "Overføring", -300.0, 1704067200, "checking"),
+            create_test_import("4", "Overføring", 300.0, 1704067200, "savings"),
+        ];
+        assert_eq!(transfers::link_transfers(&mut imports), 2);
+        imports
+    }
+
+    #[test]
+    fn test_unlinks_transfer_with_outgoing_side_imported() {
+        // The outgoing side was imported as a plain transaction before the incoming one showed up
+        let mut imports = create_linked_transfers();
+        let unlinked =
+            transfers::unlink_imported(&mut imports, |import| import.import_id == "import-1");
+
+        assert_eq!(unlinked, 1);
+        assert_eq!(imports[0].transfer, None);
+        assert_eq!(imports[1].transfer, None);
+        // Transfers with neither side in YNAB stay linked
+        assert!(imports[2].transfer.is_some());
+        assert!(imports[3].transfer.is_some());
+    }
+
+    #[test]
+    fn test_unlinks_transfer_with_incoming_side_imported() {
+        // The incoming side was imported first, posting the transfer would create it again
+        let mut imports = create_linked_transfers();
+        imports[3].already_imported = vec!["ynab-savings".to_string()];
+        let unlinked =
+            transfers::unlink_imported(&mut imports, |import| !import.already_imported.is_empty());
+
+        assert_eq!(unlinked, 1);
+        assert!(imports[0].transfer.is_some());
+        assert!(imports[1].transfer.is_some());
+        assert_eq!(imports[2].transfer, None);
+        assert_eq!(imports[3].transfer, None);
+    }
+}
@@ -1,6 +1,10 @@
 use chrono::{DateTime, Datelike, TimeZone, Utc};
-use sparebank1_to_ynab::sparebanken1::Transaction as Sparebank1Transaction;
-use sparebank1_to_ynab::ynab::YnabClient;
+use sparebank1_to_ynab::account_config::AccountTarget;
+use sparebank1_to_ynab::sparebanken1::{BookingStatus, Transaction as Sparebank1Transaction};
+use sparebank1_to_ynab::transfers;
+use sparebank1_to_ynab::ynab::{
+    self, CategoryGroup, ImportTransaction, RecentTransactions, TransactionDetail, YnabClient,
+};
 use std::collections::HashMap;
 
 #[cfg(test)]
@@ -9,7 +13,7 @@ mod ynab_transaction_tests {
 
     fn create_test_transaction(
         id: &str,
-        amount: f32,
+        amount: f64,
         date_timestamp: i64,
         payee: &str,
         description: &str,
@@ -22,6 +26,7 @@ mod ynab_transaction_tests {
             amount,
             date: DateTime::from_timestamp(date_timestamp, 0).unwrap(),
             account: account.to_string(),
+            ..Default::default()
         }
     }
 
@@ -29,7 +34,10 @@ mod ynab_transaction_tests {
     fn test_amount_conversion_to_milliunits() {
         // YNAB requires amounts in milliunits (amount * 1000)
         let mut account_config = HashMap::new();
-        account_config.insert("account1".to_string(), "ynab-id-1".to_string());
+        account_config.insert(
+            "account1".to_string(),
+            vec![AccountTarget::new("ynab-id-1")],
+        );
 
         let _client = YnabClient::new(
             account_config,
@@ -48,14 +56,14 @@ mod ynab_transaction_tests {
         // In a real scenario, this would be sent to YNAB which expects milliunits
 
         // Expected: 1234.56 * 1000 = 1234560 milliunits
-        assert_eq!((transaction.amount * 1000.0) as i64, 1234560);
+        assert_eq!(ynab::to_milliunits(transaction.amount), 1234560);
 
         // Test negative amount (expense)
         let transaction2 =
             create_test_transaction("txn2", -99.99, 1704067200, "Store", "Groceries", "account1");
 
         // Expected: -99.99 * 1000 = -99990 milliunits
-        assert_eq!((transaction2.amount * 1000.0) as i64, -99990);
+        assert_eq!(ynab::to_milliunits(transaction2.amount), -99990);
 
         // Test zero amount
         let transaction3 = create_test_transaction(
@@ -67,7 +75,7 @@ mod ynab_transaction_tests {
             "account1",
         );
 
-        assert_eq!((transaction3.amount * 1000.0) as i64, 0);
+        assert_eq!(ynab::to_milliunits(transaction3.amount), 0);
     }
 
     #[test]
@@ -119,7 +127,10 @@ mod ynab_transaction_tests {
         // When multiple transactions have the same amount and date,
         // the occurrence counter should increment
         let mut account_config = HashMap::new();
-        account_config.insert("account1".to_string(), "ynab-id-1".to_string());
+        account_config.insert(
+            "account1".to_string(),
+            vec![AccountTarget::new("ynab-id-1")],
+        );
 
         let _client = YnabClient::new(
             account_config,
@@ -160,7 +171,7 @@ mod ynab_transaction_tests {
         // SB1:-50.0:2024-01-01:2
         // SB1:-50.0:2024-01-01:3
 
-        let transactions = vec![txn1, txn2, txn3];
+        let transactions = [txn1, txn2, txn3];
         assert_eq!(transactions.len(), 3);
 
         // Each should be unique based on occurrence counter
@@ -170,8 +181,14 @@ mod ynab_transaction_tests {
     #[test]
     fn test_account_mapping() {
         let mut account_config = HashMap::new();
-        account_config.insert("sb1_account_1".to_string(), "ynab_account_1".to_string());
-        account_config.insert("sb1_account_2".to_string(), "ynab_account_2".to_string());
+        account_config.insert(
+            "sb1_account_1".to_string(),
+            vec![AccountTarget::new("ynab_account_1")],
+        );
+        account_config.insert(
+            "sb1_account_2".to_string(),
+            vec![AccountTarget::new("ynab_account_2")],
+        );
 
         let _client = YnabClient::new(
             account_config.clone(),
@@ -190,11 +207,11 @@ mod ynab_transaction_tests {
         // Verify account mapping exists
         assert_eq!(
             account_config.get(&transaction1.account),
-            Some(&"ynab_account_1".to_string())
+            Some(&vec![AccountTarget::new("ynab_account_1")])
         );
         assert_eq!(
             account_config.get(&transaction2.account),
-            Some(&"ynab_account_2".to_string())
+            Some(&vec![AccountTarget::new("ynab_account_2")])
         );
     }
 
@@ -233,24 +250,40 @@ mod ynab_transaction_tests {
         // Test with a very large amount (e.g., salary)
         let transaction = create_test_transaction(
             "txn1",
-            45000.50,
+            45_000.5,
             1704067200,
             "Employer",
             "Monthly salary",
             "account1",
         );
 
-        let milliunits = (transaction.amount * 1000.0) as i64;
+        let milliunits = ynab::to_milliunits(transaction.amount);
         assert_eq!(milliunits, 45000500);
     }
 
+    #[test]
+    fn test_large_amount_rounded_to_milliunits() {
+        // Neither amount is exact in binary, truncating would lose a milliunit and in f32
+        // 123456.78 would come out as 123456781
+        let transaction = create_test_transaction(
+            "txn1", 123_456.78, 1704067200, "Employer", "Bonus", "account1",
+        );
+        assert_eq!(ynab::to_milliunits(transaction.amount), 123456780);
+        assert_eq!(ynab::to_milliunits(128.04), 128040);
+        assert_eq!(ynab::to_milliunits(-128.04), -128040);
+
+        // Amounts parsed from the bank's JSON keep their øre as well
+        let amount: f64 = serde_json::from_str("1234567.89").unwrap();
+        assert_eq!(ynab::to_milliunits(amount), 1234567890);
+    }
+
     #[test]
     fn test_small_fractional_amount() {
         // Test with very small amounts (cents/øre)
         let transaction =
             create_test_transaction("txn1", 0.01, 1704067200, "Test", "One øre", "account1");
 
-        let milliunits = (transaction.amount * 1000.0) as i64;
+        let milliunits = ynab::to_milliunits(transaction.amount);
         assert_eq!(milliunits, 10);
     }
 
@@ -260,7 +293,350 @@ mod ynab_transaction_tests {
         let transaction =
             create_test_transaction("txn1", -0.0, 1704067200, "Test", "Zero amount", "account1");
 
-        let milliunits = (transaction.amount * 1000.0) as i64;
+        let milliunits = ynab::to_milliunits(transaction.amount);
         assert_eq!(milliunits, 0);
     }
+
+    fn create_test_category_groups() -> Vec<CategoryGroup> {
+        serde_json::from_str(
+            r#"[
+                {
+                    "id": "group-1",
+                    "name": "Everyday",
+                    "categories": [
+                        { "id": "cat-groceries", "name": "Groceries" },
+                        { "id": "cat-misc-everyday", "name": "Misc" },
+                        { "id": "cat-deleted", "name": "Old", "deleted": true }
+                    ]
+                },
+                {
+                    "id": "group-2",
+                    "name": "Fun",
+                    "categories": [
+                        { "id": "cat-misc-fun", "name": "Misc" },
+                        { "id": "cat-ratio", "name": "Ratio: 50/50" }
+                    ]
+                }
+            ]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resolve_category_by_name() {
+        let groups = create_test_category_groups();
+
+        let category = ynab::resolve_category(&groups, "groceries").unwrap();
+        assert_eq!(category.id, "cat-groceries");
+
+        assert!(ynab::resolve_category(&groups, "Unknown").is_none());
+        // Deleted categories are never used
+        assert!(ynab::resolve_category(&groups, "Old").is_none());
+    }
+
+    #[test]
+    fn test_resolve_category_qualified_by_group() {
+        let groups = create_test_category_groups();
+
+        assert_eq!(
+            ynab::resolve_category(&groups, "Fun: Misc").unwrap().id,
+            "cat-misc-fun"
+        );
+        assert_eq!(
+            ynab::resolve_category(&groups, "Everyday: Misc")
+                .unwrap()
+                .id,
+            "cat-misc-everyday"
+        );
+        // Names containing a colon still resolve when not meant as a group
+        assert_eq!(
+            ynab::resolve_category(&groups, "Ratio: 50/50").unwrap().id,
+            "cat-ratio"
+        );
+    }
+
+    #[test]
+    fn test_generate_import_ids_numbers_repeats() {
+        let transactions = vec![
+            create_test_transaction("txn1", -50.0, 1704067200, "A", "", "account1"),
+            create_test_transaction("txn2", -127.5, 1704067200, "B", "", "account1"),
+            create_test_transaction("txn3", -50.0, 1704067200, "C", "", "account2"),
+        ];
+
+        let import_ids = ynab::generate_import_ids(&transactions);
+        assert_eq!(
+            import_ids,
+            vec![
+                "SB1:-50:2024-01-01:1".to_string(),
+                "SB1:-127.5:2024-01-01:1".to_string(),
+                "SB1:-50:2024-01-01:2".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_generate_import_ids_pending_prefix() {
+        let mut pending = create_test_transaction("txn2", -50.0, 1704067200, "B", "", "account1");
+        pending.booking_status = BookingStatus::Pending;
+        let transactions = vec![
+            create_test_transaction("txn1", -50.0, 1704067200, "A", "", "account1"),
+            pending,
+        ];
+
+        // Pending transactions are numbered separately from booked ones
+        let import_ids = ynab::generate_import_ids(&transactions);
+        assert_eq!(
+            import_ids,
+            vec![
+                "SB1:-50:2024-01-01:1".to_string(),
+                "SB1P:-50:2024-01-01:1".to_string(),
+            ]
+        );
+    }
+
+    fn create_import(transaction: Sparebank1Transaction, category: &str) -> ImportTransaction {
+        let mut import = ImportTransaction::new(transaction, "SB1:-50:2024-01-01:1".to_string());
+        import.category = Some(category.to_string());
+        import
+    }
+
+    #[test]
+    fn test_parse_transactions_one_per_budget() {
+        let mut account_config = HashMap::new();
+        account_config.insert(
+            "account1".to_string(),
+            vec![
+                AccountTarget::new("ynab-household"),
+                AccountTarget::in_budget("budget-personal", "ynab-personal"),
+            ],
+        );
+        let client = YnabClient::new(
+            account_config,
+            "test_token".to_string(),
+            "budget-household".to_string(),
+        );
+        let personal_groups: Vec<CategoryGroup> = serde_json::from_str(
+            r#"[{ "id": "group-p", "name": "Food", "categories": [{ "id": "cat-food", "name": "Groceries" }] }]"#,
+        )
+        .unwrap();
+        let categories = HashMap::from([
+            (
+                "budget-household".to_string(),
+                create_test_category_groups(),
+            ),
+            ("budget-personal".to_string(), personal_groups),
+        ]);
+        let transaction =
+            create_test_transaction("txn1", -50.0, 1704067200, "KIWI", "", "account1");
+
+        let budgets = client.parse_transactions(
+            &[create_import(transaction, "Groceries")],
+            &categories,
+            &HashMap::new(),
+        );
+        let keys: Vec<&str> = budgets.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["budget-household", "budget-personal"]);
+
+        let household = &budgets["budget-household"];
+        assert_eq!(household.len(), 1);
+        assert_eq!(household[0].account_id, "ynab-household");
+        assert_eq!(household[0].category_id.as_deref(), Some("cat-groceries"));
+
+        // The category is resolved in each budget, the import id is shared
+        let personal = &budgets["budget-personal"];
+        assert_eq!(personal.len(), 1);
+        assert_eq!(personal[0].account_id, "ynab-personal");
+        assert_eq!(personal[0].category_id.as_deref(), Some("cat-food"));
+        assert_eq!(personal[0].import_id, household[0].import_id);
+        assert_eq!(personal[0].amount, -50000);
+    }
+
+    #[test]
+    fn test_parse_transactions_default_budget() {
+        let mut account_config = HashMap::new();
+        account_config.insert(
+            "account1".to_string(),
+            vec![AccountTarget::new("ynab-id-1")],
+        );
+        account_config.insert(
+            "account2".to_string(),
+            vec![
+                AccountTarget::new("ynab-id-2"),
+                AccountTarget::in_budget("test_budget", "ynab-id-3"),
+            ],
+        );
+        let client = YnabClient::new(
+            account_config,
+            "test_token".to_string(),
+            "test_budget".to_string(),
+        );
+        let categories =
+            HashMap::from([("test_budget".to_string(), create_test_category_groups())]);
+        let mut already_imported = create_import(
+            create_test_transaction("txn2", -20.0, 1704067200, "B", "", "account2"),
+            "Unknown",
+        );
+        already_imported.already_imported = vec!["ynab-id-2".to_string()];
+        let imports = vec![
+            create_import(
+                create_test_transaction("txn1", -50.0, 1704067200, "A", "", "account1"),
+                "Groceries",
+            ),
+            already_imported,
+        ];
+
+        let budgets = client.parse_transactions(&imports, &categories, &HashMap::new());
+        // Targets without a budget fall back to the profile's budget
+        assert_eq!(budgets.len(), 1);
+        let transactions = &budgets["test_budget"];
+        let accounts: Vec<&str> = transactions.iter().map(|t| t.account_id.as_str()).collect();
+        assert_eq!(accounts, vec!["ynab-id-1", "ynab-id-3"]);
+        assert_eq!(transactions[0].approved, Some(true));
+        // Unknown categories are left out and the transaction is not approved
+        assert_eq!(transactions[1].category_id, None);
+        assert_eq!(transactions[1].approved, None);
+    }
+
+    fn create_transfer_client() -> YnabClient {
+        let mut account_config = HashMap::new();
+        account_config.insert(
+            "checking".to_string(),
+            vec![AccountTarget::new("ynab-checking")],
+        );
+        account_config.insert(
+            "savings".to_string(),
+            vec![AccountTarget::new("ynab-savings")],
+        );
+        YnabClient::new(
+            account_config,
+            "test_token".to_string(),
+            "test_budget".to_string(),
+        )
+    }
+
+    fn create_transfer_payees() -> HashMap<String, HashMap<String, String>> {
+        HashMap::from([(
+            "test_budget".to_string(),
+            HashMap::from([
+                ("ynab-checking".to_string(), "payee-checking".to_string()),
+                ("ynab-savings".to_string(), "payee-savings".to_string()),
+            ]),
+        )])
+    }
+
+    fn create_transfer() -> Vec<ImportTransaction> {
+        let outgoing =
+            create_test_transaction("out", -100.0, 1704067200, "", "Overføring", "checking");
+        let incoming =
+            create_test_transaction("in", 100.0, 1704067200, "", "Overføring", "savings");
+        let mut imports = vec![
+            ImportTransaction::new(outgoing, "SB1:-100:2024-01-01:1".to_string()),
+            ImportTransaction::new(incoming, "SB1:100:2024-01-01:1".to_string()),
+        ];
+        assert_eq!(transfers::link_transfers(&mut imports), 1);
+        imports
+    }
+
+    #[test]
+    fn test_transfer_with_one_side_imported_as_plain_posts_both_sides() {
+        let client = create_transfer_client();
+        let transfer_payees = create_transfer_payees();
+        let recent_with = |account_id: &str, import_id: &str| RecentTransactions {
+            budgets: [(
+                "test_budget".to_string(),
+                vec![TransactionDetail {
+                    account_id: account_id.to_string(),
+                    import_id: Some(import_id.to_string()),
+                    ..Default::default()
+                }],
+            )]
+            .into(),
+            ..Default::default()
+        };
+
+        // Neither side in YNAB: posted once, as a transfer from checking
+        let imports = create_transfer();
+        let recent = RecentTransactions::default();
+        assert!(!imports.iter().any(|i| client.imported_as_plain(i, &recent)));
+        let budgets = client.parse_transactions(&imports, &HashMap::new(), &transfer_payees);
+        assert_eq!(budgets["test_budget"].len(), 1);
+        assert_eq!(
+            budgets["test_budget"][0].payee_id.as_deref(),
+            Some("payee-savings")
+        );
+
+        for recent in [
+            recent_with("ynab-checking", "SB1:-100:2024-01-01:1"),
+            recent_with("ynab-savings", "SB1:100:2024-01-01:1"),
+        ] {
+            let mut imports = create_transfer();
+            let unlinked =
+                transfers::unlink_imported(&mut imports, |i| client.imported_as_plain(i, &recent));
+            assert_eq!(unlinked, 1);
+
+            // Both sides are posted as plain transactions, YNAB skips the one it has
+            let budgets = client.parse_transactions(&imports, &HashMap::new(), &transfer_payees);
+            let posted = &budgets["test_budget"];
+            assert_eq!(posted.len(), 2);
+            assert!(posted.iter().all(|t| t.payee_id.is_none()));
+        }
+    }
+
+    #[test]
+    fn test_transfer_posted_once_over_two_runs() {
+        let client = create_transfer_client();
+        let transfer_payees = create_transfer_payees();
+
+        // First run: the outgoing side is posted as a transfer
+        let mut imports = create_transfer();
+        let recent = RecentTransactions::default();
+        let unlinked =
+            transfers::unlink_imported(&mut imports, |i| client.imported_as_plain(i, &recent));
+        assert_eq!(unlinked, 0);
+        let budgets = client.parse_transactions(&imports, &HashMap::new(), &transfer_payees);
+        let first_run = &budgets["test_budget"];
+        assert_eq!(first_run.len(), 1);
+
+        // YNAB now has the posted side and the side it created, without an import id
+        let recent = RecentTransactions {
+            budgets: [(
+                "test_budget".to_string(),
+                vec![
+                    TransactionDetail {
+                        account_id: "ynab-checking".to_string(),
+                        amount: -100_000,
+                        import_id: Some(first_run[0].import_id.clone()),
+                        transfer_account_id: Some("ynab-savings".to_string()),
+                        ..Default::default()
+                    },
+                    TransactionDetail {
+                        account_id: "ynab-savings".to_string(),
+                        amount: 100_000,
+                        transfer_account_id: Some("ynab-checking".to_string()),
+                        ..Default::default()
+                    },
+                ],
+            )]
+            .into(),
+            ..Default::default()
+        };
+
+        // Second run: the transfer stays linked, so only the import id YNAB has is sent again
+        let mut imports = create_transfer();
+        let unlinked =
+            transfers::unlink_imported(&mut imports, |i| client.imported_as_plain(i, &recent));
+        assert_eq!(unlinked, 0);
+        assert!(imports.iter().all(|i| i.transfer.is_some()));
+        let budgets = client.parse_transactions(&imports, &HashMap::new(), &transfer_payees);
+        let second_run = &budgets["test_budget"];
+        assert_eq!(second_run.len(), 1);
+        let known: Vec<Option<&str>> = recent.budgets["test_budget"]
+            .iter()
+            .map(|t| t.import_id.as_deref())
+            .collect();
+        // Nothing new is posted, YNAB skips the known import id as a duplicate
+        assert!(second_run
+            .iter()
+            .all(|t| known.contains(&Some(t.import_id.as_str()))));
+    }
 }